@@ -1,3 +1,6 @@
 fn main() {
-    tauri_build::build()
+    // 只有图形界面需要 Tauri 构建步骤，无头反代服务跳过
+    if std::env::var_os("CARGO_FEATURE_GUI").is_some() {
+        tauri_build::build()
+    }
 }
@@ -1,4 +1,6 @@
 // 账号验证模块
+use crate::header_profile::{HeaderProfile, API_RUNTIME};
+use crate::http_client::QUERY_TIMEOUT;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 
@@ -34,10 +36,10 @@ pub struct UsageData {
 // AWS OIDC Token 响应
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
-struct OidcTokenResponse {
-    access_token: String,
-    refresh_token: Option<String>,
-    expires_in: u64,
+pub struct OidcTokenResponse {
+    pub access_token: String,
+    pub refresh_token: Option<String>,
+    pub expires_in: u64,
 }
 
 // Kiro GetUserInfo 响应
@@ -127,25 +129,16 @@ struct UserInfo {
     user_id: Option<String>,
 }
 
-// 核心验证函数
-#[tauri::command]
-pub async fn verify_account_credentials(
-    refresh_token: String,
-    client_id: String,
-    client_secret: String,
-    region: Option<String>,
-) -> Result<VerifyCredentialsResponse, String> {
-    let region = region.unwrap_or_else(|| "us-east-1".to_string());
-    
-    println!("[验证] 开始验证账号凭证");
-    println!("[验证] Region: {}", region);
-    println!("[验证] Client ID: {}...", &client_id[..client_id.len().min(20)]);
-    
-    // 步骤 1: 使用 refresh_token 获取 access_token
+// 使用 refresh_token 向 AWS OIDC 换取新的 access_token
+pub async fn refresh_access_token(
+    client: &reqwest::Client,
+    refresh_token: &str,
+    client_id: &str,
+    client_secret: &str,
+    region: &str,
+) -> Result<OidcTokenResponse, String> {
     let oidc_url = format!("https://oidc.{}.amazonaws.com/token", region);
-    println!("[验证] OIDC URL: {}", oidc_url);
-    
-    let client = reqwest::Client::new();
+    println!("[OIDC] OIDC URL: {}", oidc_url);
     
     let oidc_payload = json!({
         "clientId": client_id,
@@ -154,31 +147,73 @@ pub async fn verify_account_credentials(
         "grantType": "refresh_token"
     });
     
-    println!("[验证] 发送 OIDC 请求...");
     let oidc_response = client
         .post(&oidc_url)
+        .timeout(QUERY_TIMEOUT)
         .header("Content-Type", "application/json")
         .json(&oidc_payload)
         .send()
         .await
         .map_err(|e| format!("OIDC 请求失败: {}", e))?;
     
-    println!("[验证] OIDC 响应状态: {}", oidc_response.status());
+    println!("[OIDC] OIDC 响应状态: {}", oidc_response.status());
     
     if !oidc_response.status().is_success() {
         let status = oidc_response.status();
         let error_text = oidc_response.text().await.unwrap_or_default();
-        return Ok(VerifyCredentialsResponse {
-            success: false,
-            data: None,
-            error: Some(format!("OIDC 认证失败 ({}): {}", status, error_text)),
-        });
+        return Err(format!("OIDC 认证失败 ({}): {}", status, error_text));
     }
     
-    let oidc_data: OidcTokenResponse = oidc_response
+    oidc_response
         .json()
         .await
-        .map_err(|e| format!("解析 OIDC 响应失败: {}", e))?;
+        .map_err(|e| format!("解析 OIDC 响应失败: {}", e))
+}
+
+// 核心验证函数
+#[cfg(feature = "gui")]
+#[tauri::command]
+pub async fn verify_account_credentials(
+    http_clients: tauri::State<'_, crate::http_client::HttpClients>,
+    refresh_token: String,
+    client_id: String,
+    client_secret: String,
+    region: Option<String>,
+    outbound_proxy: Option<crate::http_client::OutboundProxy>,
+    header_profile: Option<crate::header_profile::HeaderOverrides>,
+) -> Result<VerifyCredentialsResponse, String> {
+    let client = http_clients.for_proxy(outbound_proxy.as_ref())?;
+    let profile = http_clients.header_profile(header_profile.as_ref());
+    verify_credentials(&client, &profile, refresh_token, client_id, client_secret, region).await
+}
+
+/// 使用 refresh_token 验证凭证并获取账号信息
+pub async fn verify_credentials(
+    client: &reqwest::Client,
+    profile: &HeaderProfile,
+    refresh_token: String,
+    client_id: String,
+    client_secret: String,
+    region: Option<String>,
+) -> Result<VerifyCredentialsResponse, String> {
+    let region = region.unwrap_or_else(|| "us-east-1".to_string());
+    
+    println!("[验证] 开始验证账号凭证");
+    println!("[验证] Region: {}", region);
+    println!("[验证] Client ID: {}...", &client_id[..client_id.len().min(20)]);
+    
+    // 步骤 1: 使用 refresh_token 获取 access_token
+    println!("[验证] 发送 OIDC 请求...");
+    let oidc_data = match refresh_access_token(client, &refresh_token, &client_id, &client_secret, &region).await {
+        Ok(data) => data,
+        Err(e) => {
+            return Ok(VerifyCredentialsResponse {
+                success: false,
+                data: None,
+                error: Some(e),
+            });
+        }
+    };
     
     println!("[OIDC] Token 刷新成功");
     println!("[OIDC] Access Token 长度: {}", oidc_data.access_token.len());
@@ -203,13 +238,16 @@ pub async fn verify_account_credentials(
         api_base
     );
     
+    let mut client_headers = reqwest::header::HeaderMap::new();
+    profile.apply_user_agent(&mut client_headers, API_RUNTIME)?;
+    
     println!("[验证] 发送 GetUsageLimits 请求...");
     let usage_response = client
         .get(&usage_url)
+        .timeout(QUERY_TIMEOUT)
+        .headers(client_headers)
         .header("Accept", "application/json")
         .header("Authorization", format!("Bearer {}", access_token))
-        .header("User-Agent", "aws-sdk-rust/1.3.9 os/windows lang/rust/1.87.0")
-        .header("x-amz-user-agent", "aws-sdk-rust/1.3.9 ua/2.1 api/ssooidc/1.88.0 os/windows lang/rust/1.87.0 m/E app/KiroManager")
         .send()
         .await
         .map_err(|e| format!("获取使用量失败: {}", e))?;
@@ -0,0 +1,646 @@
+// 账号管理命令行工具 - 与图形界面共用 accounts.json，可通过 SSH 脚本化轮换账号
+use kiro_manager_lib::auth::{refresh_access_token, verify_credentials, AccountData};
+use kiro_manager_lib::header_profile::{HeaderOverrides, HeaderProfile};
+use kiro_manager_lib::http_client::{HttpClients, NetworkSettings, OutboundProxy};
+use kiro_manager_lib::{storage, switch};
+use serde_json::{json, Value};
+use std::path::PathBuf;
+
+const USAGE: &str = "用法: kiro-accounts [--file <路径>] <命令> [参数]
+
+命令:
+  list                          列出所有账号（* 表示本地当前登录账号）
+  import <文件>                 导入账号（导出文件、账号数组或 refreshToken 凭证数组）
+  import --local                导入 Kiro IDE 当前登录的账号
+  verify <账号>... | --all      验证凭证并更新订阅与用量
+  refresh <账号>... | --all     仅刷新 access token
+  switch <账号>                 切换 Kiro IDE 登录到指定账号
+  export [-o <文件>] [--no-credentials] [<账号>...]
+                                导出账号（默认全部，输出到标准输出）
+  remove <账号>...              删除账号
+  logout                        清除 Kiro IDE 本地登录
+  help                          显示帮助
+
+<账号> 可以是 list 中的序号、账号 ID（或前缀）、邮箱。
+账号文件默认为应用数据目录中的 accounts.json，可用 --file 或环境变量 KIRO_ACCOUNTS_FILE 覆盖。";
+
+/// 距离过期不足该时间（毫秒）时，切换前先刷新 Token
+const REFRESH_AHEAD_MS: i64 = 5 * 60 * 1000;
+
+fn now_ms() -> i64 {
+    chrono::Utc::now().timestamp_millis()
+}
+
+fn str_field<'a>(value: &'a Value, key: &str) -> &'a str {
+    value.get(key).and_then(|v| v.as_str()).unwrap_or("")
+}
+
+fn credential<'a>(account: &'a Value, key: &str) -> &'a str {
+    account.get("credentials").map(|c| str_field(c, key)).unwrap_or("")
+}
+
+/// 账号显示名称
+fn label(account: &Value) -> String {
+    let email = str_field(account, "email");
+    if email.is_empty() {
+        str_field(account, "id").to_string()
+    } else {
+        email.to_string()
+    }
+}
+
+/// 浅合并 JSON 对象，`credentials` 字段单独合并以保留其他凭证信息
+fn merge(target: &mut Value, patch: Value) {
+    let (Some(target), Value::Object(patch)) = (target.as_object_mut(), patch) else {
+        return;
+    };
+    for (key, value) in patch {
+        match (key.as_str(), target.get_mut(&key), value) {
+            ("credentials", Some(Value::Object(existing)), Value::Object(updates)) => {
+                existing.extend(updates);
+            }
+            (_, _, Value::Null) => {
+                target.remove(&key);
+            }
+            (_, _, value) => {
+                target.insert(key, value);
+            }
+        }
+    }
+}
+
+/// 验证结果转换为账号字段更新
+fn account_patch(data: &AccountData) -> Value {
+    let now = now_ms();
+    json!({
+        "email": data.email,
+        "userId": data.user_id,
+        "credentials": {
+            "accessToken": data.access_token,
+            "refreshToken": data.refresh_token,
+            "expiresAt": now + data.expires_in.unwrap_or(3600) as i64 * 1000
+        },
+        "subscription": {
+            "type": data.subscription_type,
+            "title": data.subscription_title,
+            "daysRemaining": data.days_remaining
+        },
+        "usage": {
+            "current": data.usage.current,
+            "limit": data.usage.limit,
+            "percentUsed": if data.usage.limit > 0.0 { data.usage.current / data.usage.limit } else { 0.0 },
+            "lastUpdated": now,
+            "nextResetDate": data.usage.next_reset_date
+        },
+        "status": "active",
+        "lastError": null,
+        "lastUsedAt": now
+    })
+}
+
+/// 验证失败时的状态更新
+fn error_patch(error: &str) -> Value {
+    let is_suspended = error.contains("封禁") || error.contains("suspended");
+    json!({
+        "status": if is_suspended { "suspended" } else { "error" },
+        "lastError": error
+    })
+}
+
+/// 账号文件
+struct AccountsFile {
+    path: PathBuf,
+    accounts: Vec<Value>,
+}
+
+impl AccountsFile {
+    fn load(path: PathBuf) -> Result<Self, String> {
+        let accounts = if path.exists() {
+            let data = std::fs::read_to_string(&path)
+                .map_err(|e| format!("读取账号数据失败 {:?}: {}", path, e))?;
+            serde_json::from_str(&data).map_err(|e| format!("解析账号数据失败 {:?}: {}", path, e))?
+        } else {
+            vec![]
+        };
+        Ok(Self { path, accounts })
+    }
+
+    fn save(&self) -> Result<(), String> {
+        let data = serde_json::to_string(&self.accounts)
+            .map_err(|e| format!("序列化账号数据失败: {}", e))?;
+        // 先写临时文件再替换，避免写入中断时损坏账号数据
+        let file_name = self.path.file_name().ok_or_else(|| format!("无效的账号文件路径 {:?}", self.path))?;
+        let temp_path = self.path.with_file_name(format!("{}.tmp", file_name.to_string_lossy()));
+        std::fs::write(&temp_path, data).map_err(|e| format!("写入临时文件失败 {:?}: {}", temp_path, e))?;
+        std::fs::rename(&temp_path, &self.path).map_err(|e| {
+            let _ = std::fs::remove_file(&temp_path);
+            format!("保存账号数据失败 {:?}: {}", self.path, e)
+        })
+    }
+
+    /// 按序号、ID、ID 前缀或邮箱查找账号
+    ///
+    /// 完整 ID 或邮箱优先；序号和 ID 前缀指向不同账号时报错，需改用完整 ID
+    fn find(&self, selector: &str) -> Result<usize, String> {
+        let exact = self.accounts.iter().position(|acc| {
+            str_field(acc, "id") == selector || str_field(acc, "email").eq_ignore_ascii_case(selector)
+        });
+        if let Some(index) = exact {
+            return Ok(index);
+        }
+
+        let mut matches: Vec<usize> = self
+            .accounts
+            .iter()
+            .enumerate()
+            .filter(|(_, acc)| str_field(acc, "id").starts_with(selector))
+            .map(|(i, _)| i)
+            .collect();
+        if let Ok(index) = selector.parse::<usize>() {
+            if index >= 1 && index <= self.accounts.len() && !matches.contains(&(index - 1)) {
+                matches.insert(0, index - 1);
+            }
+        }
+
+        match matches.as_slice() {
+            [index] => Ok(*index),
+            [] => Err(format!("找不到账号: {}", selector)),
+            _ => {
+                let candidates: Vec<String> = matches
+                    .iter()
+                    .map(|&i| format!("#{} {}", i + 1, str_field(&self.accounts[i], "id")))
+                    .collect();
+                Err(format!("账号选择不唯一: {} 可能是 {}，请使用完整 ID", selector, candidates.join("、")))
+            }
+        }
+    }
+
+    /// 解析命令参数中的账号列表，`--all` 表示全部
+    fn select(&self, args: &[String]) -> Result<Vec<usize>, String> {
+        if args.iter().any(|a| a == "--all") {
+            return Ok((0..self.accounts.len()).collect());
+        }
+        if args.is_empty() {
+            return Err("请指定账号或使用 --all".to_string());
+        }
+        let mut indexes = Vec::new();
+        for arg in args {
+            let index = self.find(arg)?;
+            if !indexes.contains(&index) {
+                indexes.push(index);
+            }
+        }
+        Ok(indexes)
+    }
+
+    /// 是否已存在相同凭证的账号
+    fn contains_credentials(&self, refresh_token: &str, email: &str) -> bool {
+        self.accounts.iter().any(|acc| {
+            (!refresh_token.is_empty() && credential(acc, "refreshToken") == refresh_token)
+                || (!email.is_empty() && str_field(acc, "email") == email)
+        })
+    }
+}
+
+/// 账号单独设置了出站代理时使用该代理，否则使用全局代理
+fn client_for(clients: &HttpClients, account: &Value) -> Result<reqwest::Client, String> {
+    let proxy = account
+        .get("outboundProxy")
+        .and_then(|p| serde_json::from_value::<OutboundProxy>(p.clone()).ok());
+    clients.for_proxy(proxy.as_ref())
+}
+
+/// 账号单独设置了请求头配置时使用该配置，否则使用全局配置
+fn profile_for(clients: &HttpClients, account: &Value) -> HeaderProfile {
+    let profile = account
+        .get("headerProfile")
+        .and_then(|p| serde_json::from_value::<HeaderOverrides>(p.clone()).ok());
+    clients.header_profile(profile.as_ref())
+}
+
+/// 使用 refreshToken 等凭证验证并创建新账号
+async fn account_from_credentials(clients: &HttpClients, cred: &Value) -> Result<Value, String> {
+    let refresh_token = str_field(cred, "refreshToken");
+    if refresh_token.is_empty() {
+        return Err("缺少 refreshToken".to_string());
+    }
+
+    let provider = Some(str_field(cred, "provider")).filter(|p| !p.is_empty()).unwrap_or("BuilderId");
+    let is_social = provider == "Google" || provider == "Github";
+    let client_id = str_field(cred, "clientId");
+    let client_secret = str_field(cred, "clientSecret");
+    if !is_social && (client_id.is_empty() || client_secret.is_empty()) {
+        return Err(format!("{} 登录需要 clientId 和 clientSecret", provider));
+    }
+    let region = Some(str_field(cred, "region")).filter(|r| !r.is_empty()).unwrap_or("us-east-1");
+
+    let client = client_for(clients, cred)?;
+    let result = verify_credentials(
+        &client,
+        &profile_for(clients, cred),
+        refresh_token.to_string(),
+        client_id.to_string(),
+        client_secret.to_string(),
+        Some(region.to_string()),
+    )
+    .await?;
+    let data = result.data.ok_or_else(|| result.error.unwrap_or_else(|| "验证失败".to_string()))?;
+
+    let now = now_ms();
+    let mut account = json!({
+        "id": uuid::Uuid::new_v4().to_string(),
+        "nickname": data.email.split('@').next().unwrap_or_default(),
+        "idp": provider,
+        "credentials": {
+            "csrfToken": "",
+            "clientId": client_id,
+            "clientSecret": client_secret,
+            "region": region,
+            "authMethod": if is_social { "social" } else { "IdC" },
+            "provider": provider
+        },
+        "tags": [],
+        "isActive": false,
+        "createdAt": now
+    });
+    for key in ["outboundProxy", "headerProfile"] {
+        if let Some(value) = cred.get(key) {
+            merge(&mut account, json!({ key: value }));
+        }
+    }
+    merge(&mut account, account_patch(&data));
+    Ok(account)
+}
+
+async fn cmd_list(file: &AccountsFile) -> Result<(), String> {
+    if file.accounts.is_empty() {
+        println!("没有账号");
+        return Ok(());
+    }
+
+    let active_token = switch::get_active_account().await.ok().flatten().unwrap_or_default();
+    let now = now_ms();
+
+    println!("{:<4} {:<36} {:<12} {:<14} {:<10} Token", "#", "邮箱", "订阅", "用量", "状态");
+    for (i, acc) in file.accounts.iter().enumerate() {
+        let marker = if !active_token.is_empty() && credential(acc, "accessToken") == active_token { "*" } else { " " };
+        let subscription = acc.get("subscription").map(|s| str_field(s, "type")).unwrap_or("");
+        let usage = acc
+            .get("usage")
+            .map(|u| {
+                format!(
+                    "{:.1}/{:.0}",
+                    u.get("current").and_then(|v| v.as_f64()).unwrap_or(0.0),
+                    u.get("limit").and_then(|v| v.as_f64()).unwrap_or(0.0)
+                )
+            })
+            .unwrap_or_default();
+        let expires_at = acc
+            .get("credentials")
+            .and_then(|c| c.get("expiresAt"))
+            .and_then(|e| e.as_i64())
+            .unwrap_or(0);
+        let token = if expires_at > now {
+            format!("{} 分钟后过期", (expires_at - now) / 60_000)
+        } else {
+            "已过期".to_string()
+        };
+
+        println!(
+            "{}{:<3} {:<36} {:<12} {:<14} {:<10} {}",
+            marker,
+            i + 1,
+            label(acc),
+            subscription,
+            usage,
+            str_field(acc, "status"),
+            token
+        );
+    }
+    Ok(())
+}
+
+async fn cmd_import(clients: &HttpClients, file: &mut AccountsFile, args: &[String]) -> Result<(), String> {
+    let items: Vec<Value> = match args.first().map(|s| s.as_str()) {
+        Some("--local") => {
+            let local = storage::get_local_active_account().await?;
+            let data = local.data.ok_or_else(|| local.error.unwrap_or_else(|| "读取本地账号失败".to_string()))?;
+            vec![json!({
+                "refreshToken": data.refresh_token,
+                "clientId": data.client_id,
+                "clientSecret": data.client_secret,
+                "region": data.region
+            })]
+        }
+        Some(path) => {
+            let data = std::fs::read_to_string(path).map_err(|e| format!("读取导入文件失败: {}", e))?;
+            let parsed: Value = serde_json::from_str(&data).map_err(|e| format!("JSON 格式错误: {}", e))?;
+            match parsed {
+                // 图形界面导出的 { version, accounts } 格式
+                Value::Object(ref obj) if obj.get("accounts").is_some_and(|a| a.is_array()) => {
+                    obj["accounts"].as_array().cloned().unwrap_or_default()
+                }
+                Value::Array(items) => items,
+                other => vec![other],
+            }
+        }
+        None => return Err("请指定导入文件或 --local".to_string()),
+    };
+
+    let mut success = 0;
+    let mut failed = 0;
+    for (i, item) in items.iter().enumerate() {
+        // 完整账号数据直接导入，凭证数据需要先验证
+        let result = if item.get("credentials").is_some() {
+            let mut account = item.clone();
+            merge(&mut account, json!({ "isActive": false }));
+            if str_field(&account, "id").is_empty() {
+                merge(&mut account, json!({ "id": uuid::Uuid::new_v4().to_string() }));
+            }
+            Ok(account)
+        } else {
+            account_from_credentials(clients, item).await
+        };
+
+        match result {
+            Ok(account) if file.contains_credentials(credential(&account, "refreshToken"), str_field(&account, "email")) => {
+                println!("#{}: 账号已存在，跳过 {}", i + 1, label(&account));
+            }
+            Ok(account) => {
+                println!("#{}: 已导入 {}", i + 1, label(&account));
+                file.accounts.push(account);
+                success += 1;
+            }
+            Err(e) => {
+                println!("#{}: 导入失败: {}", i + 1, e);
+                failed += 1;
+            }
+        }
+    }
+
+    file.save()?;
+    println!("成功: {} 个，失败: {} 个", success, failed);
+    Ok(())
+}
+
+async fn cmd_verify(clients: &HttpClients, file: &mut AccountsFile, args: &[String]) -> Result<(), String> {
+    let mut failed = 0;
+    for index in file.select(args)? {
+        let account = &file.accounts[index];
+        let name = label(account);
+        let client = client_for(clients, account)?;
+        let result = verify_credentials(
+            &client,
+            &profile_for(clients, account),
+            credential(account, "refreshToken").to_string(),
+            credential(account, "clientId").to_string(),
+            credential(account, "clientSecret").to_string(),
+            Some(credential(account, "region").to_string()).filter(|r| !r.is_empty()),
+        )
+        .await;
+
+        let patch = match result {
+            Ok(response) => match response.data {
+                Some(data) => {
+                    println!("{}: {} 用量 {:.1}/{:.0}", name, data.subscription_title, data.usage.current, data.usage.limit);
+                    account_patch(&data)
+                }
+                None => {
+                    let error = response.error.unwrap_or_else(|| "验证失败".to_string());
+                    println!("{}: {}", name, error);
+                    failed += 1;
+                    error_patch(&error)
+                }
+            },
+            Err(e) => {
+                println!("{}: {}", name, e);
+                failed += 1;
+                error_patch(&e)
+            }
+        };
+        merge(&mut file.accounts[index], patch);
+    }
+
+    file.save()?;
+    if failed > 0 {
+        return Err(format!("{} 个账号验证失败", failed));
+    }
+    Ok(())
+}
+
+/// 刷新单个账号的 Token
+/// 刷新账号 Token，社交登录账号不支持刷新时返回 false
+async fn refresh_account(clients: &HttpClients, account: &mut Value) -> Result<bool, String> {
+    if credential(account, "authMethod") == "social" {
+        return Ok(false);
+    }
+    let refresh_token = credential(account, "refreshToken");
+    let client_id = credential(account, "clientId");
+    let client_secret = credential(account, "clientSecret");
+    if refresh_token.is_empty() || client_id.is_empty() || client_secret.is_empty() {
+        return Err("账号缺少刷新凭证".to_string());
+    }
+    let region = Some(credential(account, "region")).filter(|r| !r.is_empty()).unwrap_or("us-east-1");
+    let client = client_for(clients, account)?;
+
+    match refresh_access_token(&client, refresh_token, client_id, client_secret, region).await {
+        Ok(token) => {
+            let refresh_token = token.refresh_token.unwrap_or_else(|| refresh_token.to_string());
+            merge(
+                account,
+                json!({
+                    "credentials": {
+                        "accessToken": token.access_token,
+                        "refreshToken": refresh_token,
+                        "expiresAt": now_ms() + token.expires_in as i64 * 1000
+                    },
+                    "status": "active",
+                    "lastError": null
+                }),
+            );
+            Ok(true)
+        }
+        Err(e) => {
+            merge(account, error_patch(&e));
+            Err(e)
+        }
+    }
+}
+
+async fn cmd_refresh(clients: &HttpClients, file: &mut AccountsFile, args: &[String]) -> Result<(), String> {
+    let mut failed = 0;
+    for index in file.select(args)? {
+        let name = label(&file.accounts[index]);
+        match refresh_account(clients, &mut file.accounts[index]).await {
+            Ok(true) => println!("{}: Token 已刷新", name),
+            Ok(false) => println!("{}: 社交登录账号不支持刷新，已跳过", name),
+            Err(e) => {
+                println!("{}: 刷新失败: {}", name, e);
+                failed += 1;
+            }
+        }
+    }
+
+    file.save()?;
+    if failed > 0 {
+        return Err(format!("{} 个账号刷新失败", failed));
+    }
+    Ok(())
+}
+
+async fn cmd_switch(clients: &HttpClients, file: &mut AccountsFile, args: &[String]) -> Result<(), String> {
+    let selector = args.first().ok_or("请指定要切换的账号")?;
+    let index = file.find(selector)?;
+
+    let expires_at = file.accounts[index]
+        .get("credentials")
+        .and_then(|c| c.get("expiresAt"))
+        .and_then(|e| e.as_i64())
+        .unwrap_or(0);
+    if expires_at - now_ms() < REFRESH_AHEAD_MS {
+        println!("Token 即将过期，正在刷新");
+        let result = refresh_account(clients, &mut file.accounts[index]).await;
+        file.save()?;
+        if !result? {
+            println!("社交登录账号不支持刷新，使用现有 Token");
+        }
+    }
+
+    let account = &file.accounts[index];
+    let optional = |key: &str| Some(credential(account, key).to_string()).filter(|v| !v.is_empty());
+    let result = switch::switch_account(
+        credential(account, "accessToken").to_string(),
+        credential(account, "refreshToken").to_string(),
+        credential(account, "clientId").to_string(),
+        credential(account, "clientSecret").to_string(),
+        optional("region"),
+        optional("startUrl"),
+        optional("authMethod"),
+        optional("provider").or_else(|| Some(str_field(account, "idp").to_string()).filter(|v| !v.is_empty())),
+    )
+    .await?;
+    if !result.success {
+        return Err(result.error.unwrap_or_else(|| "切换失败".to_string()));
+    }
+
+    let now = now_ms();
+    for (i, acc) in file.accounts.iter_mut().enumerate() {
+        merge(acc, json!({ "isActive": i == index }));
+    }
+    merge(&mut file.accounts[index], json!({ "lastUsedAt": now }));
+    file.save()?;
+
+    println!("已切换到 {}", label(&file.accounts[index]));
+    Ok(())
+}
+
+fn cmd_export(file: &AccountsFile, args: &[String]) -> Result<(), String> {
+    let mut output: Option<String> = None;
+    let mut include_credentials = true;
+    let mut selectors = Vec::new();
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "-o" | "--output" => output = Some(iter.next().ok_or("--output 缺少参数值")?.clone()),
+            "--no-credentials" => include_credentials = false,
+            _ => selectors.push(arg.clone()),
+        }
+    }
+
+    let indexes = if selectors.is_empty() {
+        (0..file.accounts.len()).collect()
+    } else {
+        file.select(&selectors)?
+    };
+
+    let accounts: Vec<Value> = indexes
+        .into_iter()
+        .map(|i| {
+            let mut account = file.accounts[i].clone();
+            if !include_credentials {
+                merge(&mut account, json!({ "credentials": { "accessToken": "", "refreshToken": "", "csrfToken": "" } }));
+            }
+            account
+        })
+        .collect();
+    let count = accounts.len();
+
+    // 与图形界面导出的 JSON 格式一致
+    let content = serde_json::to_string_pretty(&json!({
+        "version": "1.0",
+        "exportedAt": chrono::Utc::now().to_rfc3339(),
+        "accounts": accounts
+    }))
+    .map_err(|e| format!("序列化导出数据失败: {}", e))?;
+
+    match output {
+        Some(path) => {
+            std::fs::write(&path, content).map_err(|e| format!("写入导出文件失败: {}", e))?;
+            eprintln!("已导出 {} 个账号到: {}", count, path);
+        }
+        None => println!("{}", content),
+    }
+    Ok(())
+}
+
+fn cmd_remove(file: &mut AccountsFile, args: &[String]) -> Result<(), String> {
+    if args.iter().any(|a| a == "--all") {
+        return Err("remove 不支持 --all，请逐个指定账号".to_string());
+    }
+    let mut indexes = file.select(args)?;
+    indexes.sort_unstable_by(|a, b| b.cmp(a));
+    for index in indexes {
+        let account = file.accounts.remove(index);
+        println!("已删除 {}", label(&account));
+    }
+    file.save()
+}
+
+async fn run(args: Vec<String>) -> Result<(), String> {
+    let mut args = args.into_iter().peekable();
+    let mut file_path: Option<PathBuf> = std::env::var("KIRO_ACCOUNTS_FILE").ok().map(PathBuf::from);
+    if args.peek().map(|a| a == "--file" || a == "-f").unwrap_or(false) {
+        args.next();
+        file_path = Some(args.next().ok_or("--file 缺少参数值")?.into());
+    }
+
+    let command = args.next().unwrap_or_else(|| "help".to_string());
+    let rest: Vec<String> = args.collect();
+
+    if command == "help" || command == "-h" || command == "--help" {
+        println!("{}", USAGE);
+        return Ok(());
+    }
+    if command == "logout" {
+        switch::logout_account().await?;
+        println!("已清除本地登录");
+        return Ok(());
+    }
+
+    let path = match file_path {
+        Some(path) => path,
+        None => storage::accounts_file_path()?,
+    };
+    let mut file = AccountsFile::load(path)?;
+    let clients = HttpClients::new(NetworkSettings::load())?;
+
+    match command.as_str() {
+        "list" | "ls" => cmd_list(&file).await,
+        "import" => cmd_import(&clients, &mut file, &rest).await,
+        "verify" => cmd_verify(&clients, &mut file, &rest).await,
+        "refresh" => cmd_refresh(&clients, &mut file, &rest).await,
+        "switch" => cmd_switch(&clients, &mut file, &rest).await,
+        "export" => cmd_export(&file, &rest),
+        "remove" | "rm" => cmd_remove(&mut file, &rest),
+        _ => Err(format!("未知命令: {}\n\n{}", command, USAGE)),
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    if let Err(e) = run(std::env::args().skip(1).collect()).await {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
@@ -1,8 +1,9 @@
 // 对话功能模块
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use crate::header_profile::{HeaderOverrides, HeaderProfile};
+use crate::http_client::{HttpClients, OutboundProxy, API_TIMEOUT};
 use reqwest::Client;
-use std::time::Duration;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ChatMessage {
@@ -25,6 +26,7 @@ pub struct ChatResponse {
 /// 发送对话消息
 #[tauri::command]
 pub async fn send_chat_message(
+    http_clients: tauri::State<'_, HttpClients>,
     model: String,
     messages: Vec<ChatMessage>,
     access_token: String,
@@ -33,6 +35,8 @@ pub async fn send_chat_message(
     max_tokens: Option<u32>,
     use_custom_api: Option<bool>,
     custom_base_url: Option<String>,
+    outbound_proxy: Option<OutboundProxy>,
+    header_profile: Option<HeaderOverrides>,
 ) -> Result<ChatResponse, String> {
     println!("[Chat] 发送对话消息");
     println!("[Chat] 模型: {}", model);
@@ -40,10 +44,12 @@ pub async fn send_chat_message(
     println!("[Chat] 使用自定义 API: {:?}", use_custom_api);
     
     let use_custom = use_custom_api.unwrap_or(false);
+    let http_client = http_clients.for_proxy(outbound_proxy.as_ref())?;
     
     if use_custom && custom_base_url.is_some() {
         // 使用自定义 OpenAI 格式 API
         send_openai_format_message(
+            &http_client,
             model,
             messages,
             access_token,
@@ -54,6 +60,8 @@ pub async fn send_chat_message(
     } else {
         // 使用 AWS Kiro API
         send_aws_kiro_message(
+            &http_client,
+            &http_clients.header_profile(header_profile.as_ref()),
             model,
             messages,
             access_token,
@@ -64,6 +72,7 @@ pub async fn send_chat_message(
 
 /// 发送 OpenAI 格式的消息
 async fn send_openai_format_message(
+    client: &Client,
     model: String,
     messages: Vec<ChatMessage>,
     api_key: String,
@@ -74,11 +83,6 @@ async fn send_openai_format_message(
     println!("[Chat] 使用 OpenAI 格式 API");
     println!("[Chat] Base URL: {}", base_url);
     
-    let client = Client::builder()
-        .timeout(Duration::from_secs(120))
-        .build()
-        .map_err(|e| format!("创建 HTTP 客户端失败: {}", e))?;
-    
     // 构建 OpenAI 格式的请求
     let mut request_body = serde_json::json!({
         "model": model,
@@ -120,6 +124,7 @@ async fn send_openai_format_message(
     
     let response = client
         .post(&url)
+        .timeout(API_TIMEOUT)
         .headers(headers)
         .json(&request_body)
         .send()
@@ -197,6 +202,8 @@ async fn send_openai_format_message(
 
 /// 发送 AWS Kiro 格式的消息
 async fn send_aws_kiro_message(
+    client: &Client,
+    profile: &HeaderProfile,
     model: String,
     messages: Vec<ChatMessage>,
     access_token: String,
@@ -254,11 +261,6 @@ async fn send_aws_kiro_message(
     });
     
     // 调用 Kiro API
-    let client = Client::builder()
-        .timeout(Duration::from_secs(120))
-        .build()
-        .map_err(|e| format!("创建 HTTP 客户端失败: {}", e))?;
-    
     let url = "https://codewhisperer.us-east-1.amazonaws.com/generateAssistantResponse";
     
     let mut headers = reqwest::header::HeaderMap::new();
@@ -286,48 +288,14 @@ async fn send_aws_kiro_message(
             .parse()
             .map_err(|e| format!("无效的 X-Amz-Target 头: {}", e))?,
     );
-    headers.insert(
-        "User-Agent",
-        "aws-sdk-js/1.0.18 ua/2.1 os/windows lang/js md/nodejs#20.16.0 api/codewhispererstreaming#1.0.18 m/E KiroIDE-0.6.18"
-            .parse()
-            .map_err(|e| format!("无效的 User-Agent 头: {}", e))?,
-    );
-    headers.insert(
-        "X-Amz-User-Agent",
-        "aws-sdk-js/1.0.18 KiroIDE-0.6.18"
-            .parse()
-            .map_err(|e| format!("无效的 X-Amz-User-Agent 头: {}", e))?,
-    );
-    headers.insert(
-        "x-amzn-kiro-agent-mode",
-        "spec"
-            .parse()
-            .map_err(|e| format!("无效的 agent-mode 头: {}", e))?,
-    );
-    headers.insert(
-        "x-amzn-codewhisperer-optout",
-        "true"
-            .parse()
-            .map_err(|e| format!("无效的 optout 头: {}", e))?,
-    );
-    headers.insert(
-        "Amz-Sdk-Request",
-        "attempt=1; max=3"
-            .parse()
-            .map_err(|e| format!("无效的 Sdk-Request 头: {}", e))?,
-    );
-    headers.insert(
-        "Amz-Sdk-Invocation-Id",
-        uuid::Uuid::new_v4().to_string()
-            .parse()
-            .map_err(|e| format!("无效的 Invocation-Id 头: {}", e))?,
-    );
+    profile.apply(&mut headers)?;
     
     println!("[Chat] 请求 URL: {}", url);
     println!("[Chat] 请求体: {}", serde_json::to_string_pretty(&kiro_request).unwrap_or_default());
     
     let response = client
         .post(url)
+        .timeout(API_TIMEOUT)
         .headers(headers)
         .json(&kiro_request)
         .send()
@@ -0,0 +1,145 @@
+// 请求头配置 - 调用 Kiro 接口时模拟的客户端信息，Kiro 更新后可直接修改配置，无需重新编译
+use reqwest::header::{HeaderMap, HeaderValue};
+use serde::{Deserialize, Serialize};
+
+/// 对话接口（generateAssistantResponse）
+pub const API_STREAMING: &str = "codewhispererstreaming";
+
+/// 模型列表、用量等查询接口
+pub const API_RUNTIME: &str = "codewhispererruntime";
+
+/// 模拟的 Kiro IDE 客户端信息
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HeaderProfile {
+    /// Kiro IDE 版本
+    #[serde(rename = "ideVersion")]
+    pub ide_version: String,
+    /// 操作系统：windows、macos 或 linux
+    pub os: String,
+    /// Agent 模式：spec 或 vibe
+    #[serde(rename = "agentMode")]
+    pub agent_mode: String,
+    /// aws-sdk-js 版本
+    #[serde(rename = "sdkVersion")]
+    pub sdk_version: String,
+    /// Node.js 版本
+    #[serde(rename = "nodeVersion")]
+    pub node_version: String,
+    /// 是否拒绝数据用于服务改进
+    pub optout: bool,
+}
+
+impl Default for HeaderProfile {
+    fn default() -> Self {
+        Self {
+            ide_version: "0.6.18".to_string(),
+            os: "windows".to_string(),
+            agent_mode: "spec".to_string(),
+            sdk_version: "1.0.18".to_string(),
+            node_version: "20.16.0".to_string(),
+            optout: true,
+        }
+    }
+}
+
+/// 账号单独的请求头配置，未设置的字段沿用全局配置
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HeaderOverrides {
+    #[serde(default)]
+    #[serde(rename = "ideVersion")]
+    pub ide_version: Option<String>,
+    #[serde(default)]
+    pub os: Option<String>,
+    #[serde(default)]
+    #[serde(rename = "agentMode")]
+    pub agent_mode: Option<String>,
+    #[serde(default)]
+    #[serde(rename = "sdkVersion")]
+    pub sdk_version: Option<String>,
+    #[serde(default)]
+    #[serde(rename = "nodeVersion")]
+    pub node_version: Option<String>,
+    #[serde(default)]
+    pub optout: Option<bool>,
+}
+
+impl HeaderProfile {
+    /// 用账号配置覆盖对应字段，空字符串视为未设置
+    pub fn with_overrides(&self, overrides: &HeaderOverrides) -> Self {
+        let pick = |value: &Option<String>, fallback: &String| {
+            value
+                .as_deref()
+                .map(str::trim)
+                .filter(|v| !v.is_empty())
+                .map(str::to_string)
+                .unwrap_or_else(|| fallback.clone())
+        };
+        Self {
+            ide_version: pick(&overrides.ide_version, &self.ide_version),
+            os: pick(&overrides.os, &self.os),
+            agent_mode: pick(&overrides.agent_mode, &self.agent_mode),
+            sdk_version: pick(&overrides.sdk_version, &self.sdk_version),
+            node_version: pick(&overrides.node_version, &self.node_version),
+            optout: overrides.optout.unwrap_or(self.optout),
+        }
+    }
+
+    /// 校验配置，各字段都会写入请求头
+    pub fn validate(&self) -> Result<(), String> {
+        let fields = [
+            ("IDE 版本", &self.ide_version),
+            ("操作系统", &self.os),
+            ("Agent 模式", &self.agent_mode),
+            ("SDK 版本", &self.sdk_version),
+            ("Node.js 版本", &self.node_version),
+        ];
+        for (name, value) in fields {
+            if value.trim().is_empty() {
+                return Err(format!("请求头配置的{}不能为空", name));
+            }
+            if value.chars().any(|c| c.is_whitespace() || c.is_control()) {
+                return Err(format!("请求头配置的{}不能包含空白字符: {:?}", name, value));
+            }
+        }
+        Ok(())
+    }
+
+    /// User-Agent，`api` 为 `API_STREAMING` 或 `API_RUNTIME`
+    pub fn user_agent(&self, api: &str) -> String {
+        format!(
+            "aws-sdk-js/{sdk} ua/2.1 os/{os} lang/js md/nodejs#{node} api/{api}#{sdk} m/E KiroIDE-{ide}",
+            sdk = self.sdk_version,
+            os = self.os,
+            node = self.node_version,
+            api = api,
+            ide = self.ide_version,
+        )
+    }
+
+    /// X-Amz-User-Agent
+    pub fn amz_user_agent(&self) -> String {
+        format!("aws-sdk-js/{} KiroIDE-{}", self.sdk_version, self.ide_version)
+    }
+
+    /// 写入客户端标识头，用于查询接口
+    pub fn apply_user_agent(&self, headers: &mut HeaderMap, api: &str) -> Result<(), String> {
+        insert(headers, "User-Agent", &self.user_agent(api))?;
+        insert(headers, "X-Amz-User-Agent", &self.amz_user_agent())?;
+        insert(headers, "Amz-Sdk-Invocation-Id", &uuid::Uuid::new_v4().to_string())
+    }
+
+    /// 写入对话接口需要的全部客户端头
+    pub fn apply(&self, headers: &mut HeaderMap) -> Result<(), String> {
+        self.apply_user_agent(headers, API_STREAMING)?;
+        insert(headers, "x-amzn-kiro-agent-mode", &self.agent_mode)?;
+        insert(headers, "x-amzn-codewhisperer-optout", if self.optout { "true" } else { "false" })?;
+        insert(headers, "Amz-Sdk-Request", "attempt=1; max=3")
+    }
+}
+
+fn insert(headers: &mut HeaderMap, name: &'static str, value: &str) -> Result<(), String> {
+    let value = HeaderValue::from_str(value).map_err(|e| format!("无效的 {} 头: {}", name, e))?;
+    headers.insert(name, value);
+    Ok(())
+}
@@ -0,0 +1,311 @@
+// 共享 HTTP 客户端 - 所有上游请求复用连接池，避免每次请求重新进行 TLS 握手
+use crate::header_profile::{HeaderOverrides, HeaderProfile};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
+/// 建立连接超时
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// 空闲连接保留时间
+const POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(90);
+
+/// 每个主机保留的最大空闲连接数
+const POOL_MAX_IDLE_PER_HOST: usize = 32;
+
+/// TCP 和 HTTP/2 keep-alive 间隔
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// 默认 User-Agent，调用 Kiro 接口时会被请求头覆盖
+const USER_AGENT: &str = concat!("KiroManager/", env!("CARGO_PKG_VERSION"));
+
+/// 普通 API 请求超时
+pub const API_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// 模型列表等轻量请求超时
+pub const QUERY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// 出站代理，支持 http、https、socks5 和 socks5h（由代理解析域名）
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct OutboundProxy {
+    pub url: String,
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+}
+
+impl OutboundProxy {
+    /// 校验代理地址
+    pub fn validate(&self) -> Result<(), String> {
+        self.to_url().map(|_| ())
+    }
+
+    /// 不含认证信息的代理地址，用于日志
+    pub fn display(&self) -> String {
+        match reqwest::Url::parse(self.url.trim()) {
+            Ok(url) => format!(
+                "{}://{}:{}",
+                url.scheme(),
+                url.host_str().unwrap_or_default(),
+                url.port_or_known_default().unwrap_or_default()
+            ),
+            Err(_) => self.url.clone(),
+        }
+    }
+
+    /// 合并认证信息后的代理 URL
+    fn to_url(&self) -> Result<reqwest::Url, String> {
+        let mut url = reqwest::Url::parse(self.url.trim())
+            .map_err(|_| format!("无效的代理地址: {}", self.url))?;
+
+        if !matches!(url.scheme(), "http" | "https" | "socks5" | "socks5h") {
+            return Err(format!("不支持的代理协议: {}，请使用 http、https 或 socks5", url.scheme()));
+        }
+        if url.host_str().is_none() {
+            return Err(format!("代理地址缺少主机: {}", self.url));
+        }
+
+        if let Some(username) = self.username.as_deref().filter(|u| !u.is_empty()) {
+            url.set_username(username)
+                .map_err(|_| "代理地址无法设置用户名".to_string())?;
+            url.set_password(self.password.as_deref())
+                .map_err(|_| "代理地址无法设置密码".to_string())?;
+        }
+
+        Ok(url)
+    }
+}
+
+/// 网络设置（保存在数据目录的 network.json，无头服务可通过参数指定）
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct NetworkSettings {
+    /// 全局出站代理，账号单独设置的代理优先
+    #[serde(default)]
+    #[serde(rename = "outboundProxy")]
+    pub outbound_proxy: Option<OutboundProxy>,
+    /// 全局请求头配置，账号单独设置的配置优先
+    #[serde(default)]
+    #[serde(rename = "headerProfile")]
+    pub header_profile: HeaderProfile,
+}
+
+impl NetworkSettings {
+    /// 读取网络设置，文件不存在或格式错误时使用默认设置
+    pub fn load() -> Self {
+        match crate::storage::network_settings_path() {
+            Ok(path) => Self::load_from(&path),
+            Err(_) => Self::default(),
+        }
+    }
+
+    /// 从指定文件读取网络设置，文件不存在或格式错误时使用默认设置
+    pub fn load_from(path: &std::path::Path) -> Self {
+        let Ok(content) = std::fs::read_to_string(path) else {
+            return Self::default();
+        };
+        serde_json::from_str(&content).unwrap_or_else(|e| {
+            println!("[网络] 解析网络设置失败: {}", e);
+            Self::default()
+        })
+    }
+
+    /// 保存网络设置
+    pub fn save(&self) -> Result<(), String> {
+        let path = crate::storage::network_settings_path()?;
+        let content = serde_json::to_string_pretty(self)
+            .map_err(|e| format!("序列化网络设置失败: {}", e))?;
+        std::fs::write(&path, content).map_err(|e| format!("保存网络设置失败: {}", e))
+    }
+}
+
+/// 创建 HTTP 客户端
+///
+/// 整体超时按请求设置（见 `API_TIMEOUT` 等），客户端只限制建立连接的时间。
+/// 未指定代理时沿用系统的 HTTP_PROXY / HTTPS_PROXY 环境变量。
+pub fn build_client(proxy: Option<&OutboundProxy>) -> Result<reqwest::Client, String> {
+    let mut builder = reqwest::Client::builder()
+        .user_agent(USER_AGENT)
+        .connect_timeout(CONNECT_TIMEOUT)
+        .pool_idle_timeout(POOL_IDLE_TIMEOUT)
+        .pool_max_idle_per_host(POOL_MAX_IDLE_PER_HOST)
+        .tcp_keepalive(KEEP_ALIVE_INTERVAL)
+        .http2_keep_alive_interval(KEEP_ALIVE_INTERVAL)
+        .http2_keep_alive_while_idle(true)
+        .http2_adaptive_window(true);
+
+    if let Some(proxy) = proxy {
+        let proxy = reqwest::Proxy::all(proxy.to_url()?)
+            .map_err(|e| format!("无效的代理地址: {}", e))?;
+        builder = builder.proxy(proxy);
+    }
+
+    builder
+        .build()
+        .map_err(|e| format!("创建 HTTP 客户端失败: {}", e))
+}
+
+/// 按出站代理区分的 HTTP 客户端集合
+///
+/// 没有单独设置代理的账号使用全局客户端；设置了代理的账号按代理地址共用客户端，
+/// 同一代理下的请求复用连接池。同时保存全局请求头配置。克隆后共享同一组客户端。
+#[derive(Clone)]
+pub struct HttpClients {
+    header_profile: Arc<RwLock<HeaderProfile>>,
+    global_proxy: Arc<RwLock<Option<OutboundProxy>>>,
+    default_client: Arc<RwLock<reqwest::Client>>,
+    proxy_clients: Arc<Mutex<HashMap<OutboundProxy, reqwest::Client>>>,
+}
+
+impl HttpClients {
+    /// 使用网络设置中的全局出站代理和请求头配置创建客户端集合
+    pub fn new(settings: NetworkSettings) -> Result<Self, String> {
+        let NetworkSettings { outbound_proxy: global_proxy, header_profile } = settings;
+        header_profile.validate()?;
+        let default_client = build_client(global_proxy.as_ref())?;
+        if let Some(proxy) = &global_proxy {
+            println!("[网络] 全局出站代理: {}", proxy.display());
+        }
+
+        Ok(Self {
+            header_profile: Arc::new(RwLock::new(header_profile)),
+            global_proxy: Arc::new(RwLock::new(global_proxy)),
+            default_client: Arc::new(RwLock::new(default_client)),
+            proxy_clients: Arc::new(Mutex::new(HashMap::new())),
+        })
+    }
+
+    /// 全局客户端
+    pub fn client(&self) -> reqwest::Client {
+        self.default_client.read().unwrap().clone()
+    }
+
+    /// 获取指定出站代理的客户端，未指定时使用全局客户端
+    pub fn for_proxy(&self, proxy: Option<&OutboundProxy>) -> Result<reqwest::Client, String> {
+        let Some(proxy) = proxy.filter(|p| !p.url.trim().is_empty()) else {
+            return Ok(self.client());
+        };
+        if self.global_proxy.read().unwrap().as_ref() == Some(proxy) {
+            return Ok(self.client());
+        }
+
+        let mut clients = self.proxy_clients.lock().unwrap();
+        if let Some(client) = clients.get(proxy) {
+            return Ok(client.clone());
+        }
+
+        let client = build_client(Some(proxy))?;
+        println!("[网络] 创建出站代理客户端: {}", proxy.display());
+        clients.insert(proxy.clone(), client.clone());
+        Ok(client)
+    }
+
+    /// 当前全局出站代理
+    pub fn global_proxy(&self) -> Option<OutboundProxy> {
+        self.global_proxy.read().unwrap().clone()
+    }
+
+    /// 获取请求头配置，账号单独设置的字段覆盖全局配置
+    pub fn header_profile(&self, overrides: Option<&HeaderOverrides>) -> HeaderProfile {
+        let global = self.header_profile.read().unwrap();
+        match overrides {
+            Some(overrides) => global.with_overrides(overrides),
+            None => global.clone(),
+        }
+    }
+
+    /// 修改全局请求头配置
+    pub fn set_header_profile(&self, profile: HeaderProfile) -> Result<(), String> {
+        profile.validate()?;
+        println!("[网络] 请求头配置: KiroIDE-{} os/{} mode/{}", profile.ide_version, profile.os, profile.agent_mode);
+        *self.header_profile.write().unwrap() = profile;
+        Ok(())
+    }
+
+    /// 修改全局出站代理，之后的请求使用新客户端
+    pub fn set_global_proxy(&self, proxy: Option<OutboundProxy>) -> Result<(), String> {
+        let client = build_client(proxy.as_ref())?;
+        match &proxy {
+            Some(proxy) => println!("[网络] 全局出站代理: {}", proxy.display()),
+            None => println!("[网络] 已关闭全局出站代理"),
+        }
+        *self.default_client.write().unwrap() = client;
+        *self.global_proxy.write().unwrap() = proxy;
+        // 网络设置变化后重新创建账号代理客户端
+        self.proxy_clients.lock().unwrap().clear();
+        Ok(())
+    }
+
+    /// 只保留仍在使用的出站代理客户端，账号代理变化后释放不再使用的连接池
+    pub fn retain_proxies<'a>(&self, in_use: impl IntoIterator<Item = &'a OutboundProxy>) {
+        let in_use: HashSet<&OutboundProxy> = in_use.into_iter().collect();
+        let mut clients = self.proxy_clients.lock().unwrap();
+        let before = clients.len();
+        clients.retain(|proxy, _| in_use.contains(proxy));
+        if clients.len() < before {
+            println!("[网络] 释放 {} 个不再使用的出站代理客户端", before - clients.len());
+        }
+    }
+}
+
+/// 获取网络设置
+#[cfg(feature = "gui")]
+#[tauri::command]
+pub async fn get_network_settings(
+    http_clients: tauri::State<'_, HttpClients>,
+) -> Result<NetworkSettings, String> {
+    Ok(NetworkSettings {
+        outbound_proxy: http_clients.global_proxy(),
+        header_profile: http_clients.header_profile(None),
+    })
+}
+
+/// 保存网络设置并立即生效
+#[cfg(feature = "gui")]
+#[tauri::command]
+pub async fn save_network_settings(
+    settings: NetworkSettings,
+    http_clients: tauri::State<'_, HttpClients>,
+) -> Result<(), String> {
+    let outbound_proxy = settings.outbound_proxy.filter(|p| !p.url.trim().is_empty());
+    settings.header_profile.validate()?;
+    http_clients.set_global_proxy(outbound_proxy.clone())?;
+    http_clients.set_header_profile(settings.header_profile.clone())?;
+    NetworkSettings {
+        outbound_proxy,
+        header_profile: settings.header_profile,
+    }
+    .save()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn proxy(url: &str) -> OutboundProxy {
+        serde_json::from_value(serde_json::json!({ "url": url })).unwrap()
+    }
+
+    #[test]
+    fn evicts_proxy_clients_that_are_no_longer_used() {
+        let clients = HttpClients::new(NetworkSettings::default()).unwrap();
+        let kept = proxy("http://127.0.0.1:8001");
+        let dropped = proxy("http://127.0.0.1:8002");
+        clients.for_proxy(Some(&kept)).unwrap();
+        clients.for_proxy(Some(&dropped)).unwrap();
+
+        clients.retain_proxies([&kept]);
+        let cached: Vec<OutboundProxy> = clients.proxy_clients.lock().unwrap().keys().cloned().collect();
+        assert_eq!(cached, vec![kept]);
+    }
+
+    #[test]
+    fn changing_the_global_proxy_rebuilds_proxy_clients() {
+        let clients = HttpClients::new(NetworkSettings::default()).unwrap();
+        clients.for_proxy(Some(&proxy("http://127.0.0.1:8001"))).unwrap();
+
+        clients.set_global_proxy(Some(proxy("http://127.0.0.1:8003"))).unwrap();
+        assert!(clients.proxy_clients.lock().unwrap().is_empty());
+    }
+}
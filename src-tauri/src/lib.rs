@@ -1,16 +1,26 @@
 // 模块声明
+#[cfg(feature = "gui")]
 mod window;
-mod auth;
-mod storage;
+pub mod auth;
+pub mod header_profile;
+pub mod http_client;
+pub mod model_list;
+pub mod storage;
+#[cfg(feature = "gui")]
 mod models;
-mod switch;
+pub mod switch;
+#[cfg(feature = "gui")]
 mod machine_id;
+#[cfg(feature = "gui")]
 mod kiro_settings;
-mod proxy;
+pub mod proxy;
+#[cfg(feature = "gui")]
 mod chat;
 
+#[cfg(feature = "gui")]
 use tauri::{Manager, PhysicalPosition};
 
+#[cfg(feature = "gui")]
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     tauri::Builder::default()
@@ -18,10 +28,22 @@ pub fn run() {
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_fs::init())
         .setup(|app| {
+            // 共享 HTTP 客户端，所有命令和反代服务复用连接池；全局代理无效时退回直连
+            let http_clients = http_client::HttpClients::new(http_client::NetworkSettings::load())
+                .or_else(|e| {
+                    println!("[网络] 网络设置无效，使用默认设置: {}", e);
+                    http_client::HttpClients::new(http_client::NetworkSettings::default())
+                })?;
+            app.manage(http_clients.clone());
+            
             // 初始化 ProxyState
-            let proxy_state = proxy::ProxyState::new(app.handle());
+            let proxy_state = proxy::ProxyState::new(app.handle(), http_clients);
+            proxy_state.watch_config();
             app.manage(proxy_state);
             
+            // 按配置自动启动反代服务
+            tauri::async_runtime::spawn(proxy::commands::auto_start_proxy(app.handle().clone()));
+            
             let window = app.get_webview_window("main").unwrap();
             
             // 恢复窗口位置
@@ -53,6 +75,8 @@ pub fn run() {
         })
         .invoke_handler(tauri::generate_handler![
             auth::verify_account_credentials,
+            http_client::get_network_settings,
+            http_client::save_network_settings,
             storage::get_local_active_account,
             models::get_account_models,
             storage::load_accounts,
@@ -87,7 +111,9 @@ pub fn run() {
             proxy::commands::start_proxy_server,
             proxy::commands::stop_proxy_server,
             proxy::commands::get_proxy_status,
+            proxy::commands::proxy_frontend_ready,
             proxy::commands::update_proxy_config,
+            proxy::commands::generate_proxy_api_key,
             proxy::commands::sync_proxy_accounts,
             proxy::commands::get_proxy_accounts,
             proxy::commands::get_proxy_models,
@@ -0,0 +1,332 @@
+// 模型列表客户端 - 账号界面和反代服务共用，分页获取 ListAvailableModels 并去重
+use crate::header_profile::{HeaderProfile, API_RUNTIME};
+use crate::http_client::QUERY_TIMEOUT;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::time::Duration;
+
+/// 每页模型数量
+const PAGE_SIZE: &str = "50";
+
+/// 最多翻页次数，超过时视为上游异常，返回错误而不是不完整的列表
+const MAX_PAGES: usize = 20;
+
+/// 单页请求最多尝试次数
+const MAX_ATTEMPTS: u32 = 3;
+
+/// 重试间隔，按尝试次数递增
+const RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// 模型信息
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelInfo {
+    pub id: String,
+    pub name: String,
+    pub description: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub input_types: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_input_tokens: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_output_tokens: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rate_multiplier: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rate_unit: Option<String>,
+}
+
+impl ModelInfo {
+    /// 是否支持图片输入
+    pub fn supports_vision(&self) -> bool {
+        self.input_types
+            .iter()
+            .flatten()
+            .any(|t| t.eq_ignore_ascii_case("image"))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ListModelsResponse {
+    #[serde(default)]
+    models: Vec<KiroModel>,
+    #[serde(default)]
+    next_token: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct KiroModel {
+    model_id: String,
+    #[serde(default)]
+    model_name: Option<String>,
+    #[serde(default)]
+    description: String,
+    #[serde(default)]
+    supported_input_types: Option<Vec<String>>,
+    #[serde(default)]
+    token_limits: Option<TokenLimits>,
+    #[serde(default)]
+    rate_multiplier: Option<f64>,
+    #[serde(default)]
+    rate_unit: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TokenLimits {
+    #[serde(default)]
+    max_input_tokens: Option<i64>,
+    #[serde(default)]
+    max_output_tokens: Option<i64>,
+}
+
+impl From<KiroModel> for ModelInfo {
+    fn from(model: KiroModel) -> Self {
+        Self {
+            name: model.model_name.unwrap_or_else(|| model.model_id.clone()),
+            id: model.model_id,
+            description: model.description,
+            input_types: model.supported_input_types,
+            max_input_tokens: model.token_limits.as_ref().and_then(|t| t.max_input_tokens),
+            max_output_tokens: model.token_limits.as_ref().and_then(|t| t.max_output_tokens),
+            rate_multiplier: model.rate_multiplier,
+            rate_unit: model.rate_unit,
+        }
+    }
+}
+
+/// 单页请求失败的原因
+enum PageError {
+    /// 网络错误、429 或 5xx，可以重试
+    Transient(String),
+    /// 认证失败、参数错误等，重试无意义
+    Fatal(String),
+}
+
+/// 获取账号可用的全部模型，跟随 nextToken 翻页并按模型 ID 去重
+pub async fn list_available_models(
+    client: &Client,
+    profile: &HeaderProfile,
+    access_token: &str,
+    region: &str,
+) -> Result<Vec<ModelInfo>, String> {
+    // 根据区域确定正确的端点
+    let base_url = if region.starts_with("eu-") {
+        "https://q.eu-central-1.amazonaws.com"
+    } else {
+        "https://q.us-east-1.amazonaws.com"
+    };
+    list_models_from(client, profile, &format!("{}/ListAvailableModels", base_url), access_token).await
+}
+
+/// 从指定端点分页获取模型列表
+async fn list_models_from(
+    client: &Client,
+    profile: &HeaderProfile,
+    url: &str,
+    access_token: &str,
+) -> Result<Vec<ModelInfo>, String> {
+    let mut models = Vec::new();
+    let mut seen_ids = HashSet::new();
+    let mut seen_tokens = HashSet::new();
+    let mut next_token: Option<String> = None;
+    let mut complete = false;
+
+    for _ in 0..MAX_PAGES {
+        let page = fetch_page_with_retry(client, profile, url, access_token, next_token.as_deref()).await?;
+        println!("[模型列表] 获取到 {} 个模型", page.models.len());
+
+        for model in page.models {
+            if seen_ids.insert(model.model_id.clone()) {
+                models.push(ModelInfo::from(model));
+            }
+        }
+
+        match page.next_token.filter(|token| !token.is_empty()) {
+            None => {
+                complete = true;
+                break;
+            }
+            // 上游返回已请求过的 nextToken 时后面只会重复已有的页，视为结束
+            Some(token) if !seen_tokens.insert(token.clone()) => {
+                println!("[模型列表] nextToken 重复，停止翻页");
+                complete = true;
+                break;
+            }
+            Some(token) => next_token = Some(token),
+        }
+    }
+
+    if !complete {
+        println!("[模型列表] 翻页 {} 次后仍有 nextToken，放弃本次获取", MAX_PAGES);
+        return Err(format!("模型列表超过 {} 页仍未结束", MAX_PAGES));
+    }
+
+    println!("[模型列表] 总共获取到 {} 个模型（去重后）", models.len());
+    Ok(models)
+}
+
+/// 请求一页模型列表，临时错误按间隔重试
+async fn fetch_page_with_retry(
+    client: &Client,
+    profile: &HeaderProfile,
+    url: &str,
+    access_token: &str,
+    next_token: Option<&str>,
+) -> Result<ListModelsResponse, String> {
+    let mut attempt = 1;
+    loop {
+        match fetch_page(client, profile, url, access_token, next_token).await {
+            Ok(page) => return Ok(page),
+            Err(PageError::Fatal(e)) => return Err(e),
+            Err(PageError::Transient(e)) if attempt >= MAX_ATTEMPTS => return Err(e),
+            Err(PageError::Transient(e)) => {
+                println!("[模型列表] 第 {} 次请求失败，稍后重试: {}", attempt, e);
+                tokio::time::sleep(RETRY_DELAY * attempt).await;
+                attempt += 1;
+            }
+        }
+    }
+}
+
+/// 请求一页模型列表，nextToken 作为查询参数编码
+async fn fetch_page(
+    client: &Client,
+    profile: &HeaderProfile,
+    url: &str,
+    access_token: &str,
+    next_token: Option<&str>,
+) -> Result<ListModelsResponse, PageError> {
+    let mut headers = reqwest::header::HeaderMap::new();
+    profile
+        .apply_user_agent(&mut headers, API_RUNTIME)
+        .map_err(PageError::Fatal)?;
+
+    let mut query = vec![("origin", "AI_EDITOR"), ("maxResults", PAGE_SIZE)];
+    if let Some(token) = next_token {
+        query.push(("nextToken", token));
+    }
+
+    let response = client
+        .get(url)
+        .query(&query)
+        .timeout(QUERY_TIMEOUT)
+        .headers(headers)
+        .bearer_auth(access_token)
+        .header("Content-Type", "application/json")
+        .header("Accept", "application/json")
+        .send()
+        .await
+        .map_err(|e| PageError::Transient(format!("请求失败: {}", e)))?;
+
+    let status = response.status();
+    if !status.is_success() {
+        let error = format!("API 返回错误: {}", status.as_u16());
+        return Err(if status.as_u16() == 429 || status.is_server_error() {
+            PageError::Transient(error)
+        } else {
+            PageError::Fatal(error)
+        });
+    }
+
+    response
+        .json()
+        .await
+        .map_err(|e| PageError::Fatal(format!("解析响应失败: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use warp::http::StatusCode;
+    use warp::Filter;
+
+    /// 启动本地的 ListAvailableModels 端点，respond 收到第几次请求（从 0 开始）和 nextToken，返回状态码和响应体
+    async fn serve(
+        respond: impl Fn(usize, Option<&str>) -> (StatusCode, Value) + Clone + Send + Sync + 'static,
+    ) -> (String, Arc<AtomicUsize>) {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let route = warp::path("ListAvailableModels")
+            .and(warp::query::<HashMap<String, String>>())
+            .map(move |query: HashMap<String, String>| {
+                let call = counter.fetch_add(1, Ordering::SeqCst);
+                let (status, body) = respond(call, query.get("nextToken").map(String::as_str));
+                warp::reply::with_status(warp::reply::json(&body), status)
+            });
+        let (addr, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        (format!("http://{}/ListAvailableModels", addr), calls)
+    }
+
+    async fn list(url: &str) -> Result<Vec<ModelInfo>, String> {
+        let client = Client::builder().no_proxy().build().unwrap();
+        list_models_from(&client, &HeaderProfile::default(), url, "token").await
+    }
+
+    fn page(ids: &[&str], next_token: Option<&str>) -> (StatusCode, Value) {
+        let models: Vec<Value> = ids.iter().map(|id| json!({ "modelId": id, "rateMultiplier": 1.0 })).collect();
+        (StatusCode::OK, json!({ "models": models, "nextToken": next_token }))
+    }
+
+    fn ids(models: &[ModelInfo]) -> Vec<&str> {
+        models.iter().map(|m| m.id.as_str()).collect()
+    }
+
+    #[tokio::test]
+    async fn follows_next_token_and_dedupes_models() {
+        let (url, calls) = serve(|_, token| match token {
+            None => page(&["a", "b"], Some("page 2/+")),
+            Some("page 2/+") => page(&["b", "c"], None),
+            Some(other) => panic!("unexpected token {}", other),
+        })
+        .await;
+
+        let models = list(&url).await.unwrap();
+        assert_eq!(ids(&models), ["a", "b", "c"]);
+        assert_eq!(models[0].name, "a");
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn stops_when_the_next_token_repeats() {
+        let (url, calls) = serve(|call, _| page(&[if call == 0 { "a" } else { "b" }], Some("same"))).await;
+        let models = list(&url).await.unwrap();
+        assert_eq!(ids(&models), ["a", "b"]);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn errors_when_pages_do_not_end_within_the_limit() {
+        let (url, calls) = serve(|call, _| page(&["a"], Some(&format!("token-{}", call)))).await;
+        assert!(list(&url).await.unwrap_err().contains("未结束"));
+        assert_eq!(calls.load(Ordering::SeqCst), MAX_PAGES);
+    }
+
+    #[tokio::test]
+    async fn retries_rate_limits_and_server_errors_but_not_auth_failures() {
+        let (url, calls) = serve(|call, _| match call {
+            0 => (StatusCode::TOO_MANY_REQUESTS, json!({})),
+            1 => (StatusCode::SERVICE_UNAVAILABLE, json!({})),
+            _ => page(&["a"], None),
+        })
+        .await;
+        assert_eq!(ids(&list(&url).await.unwrap()), ["a"]);
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+        let (url, calls) = serve(|_, _| (StatusCode::UNAUTHORIZED, json!({}))).await;
+        assert_eq!(list(&url).await.unwrap_err(), "API 返回错误: 401");
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        let (url, calls) = serve(|_, _| (StatusCode::BAD_GATEWAY, json!({}))).await;
+        assert_eq!(list(&url).await.unwrap_err(), "API 返回错误: 502");
+        assert_eq!(calls.load(Ordering::SeqCst), MAX_ATTEMPTS as usize);
+    }
+}
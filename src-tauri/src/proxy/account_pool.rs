@@ -1,4 +1,5 @@
 // 账号池管理
+use super::model_catalog::ModelCatalog;
 use super::types::ProxyAccount;
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
@@ -7,6 +8,7 @@ use std::sync::{Arc, Mutex};
 pub struct AccountPool {
     accounts: Arc<Mutex<HashMap<String, ProxyAccount>>>,
     current_index: Arc<Mutex<usize>>,
+    model_catalog: Arc<ModelCatalog>,
 }
 
 impl AccountPool {
@@ -15,6 +17,7 @@ impl AccountPool {
         Self {
             accounts: Arc::new(Mutex::new(HashMap::new())),
             current_index: Arc::new(Mutex::new(0)),
+            model_catalog: Arc::new(ModelCatalog::default()),
         }
     }
 
@@ -36,6 +39,7 @@ impl AccountPool {
     pub fn remove_account(&self, account_id: &str) {
         let mut accounts = self.accounts.lock().unwrap();
         accounts.remove(account_id);
+        self.model_catalog.remove(account_id);
     }
 
     /// 清空所有账号
@@ -44,6 +48,8 @@ impl AccountPool {
         accounts.clear();
         let mut index = self.current_index.lock().unwrap();
         *index = 0;
+        // 重新同步时账号可能被移除或更换了凭证，模型列表需要重新获取
+        self.model_catalog.clear();
     }
 
     /// 获取指定账号
@@ -58,6 +64,17 @@ impl AccountPool {
         accounts.values().cloned().collect()
     }
 
+    /// 获取所有可用账号
+    pub fn get_available_accounts(&self) -> Vec<ProxyAccount> {
+        let accounts = self.accounts.lock().unwrap();
+        accounts.values().filter(|acc| acc.is_available).cloned().collect()
+    }
+
+    /// 账号的模型列表缓存
+    pub fn model_catalog(&self) -> &Arc<ModelCatalog> {
+        &self.model_catalog
+    }
+
     /// 获取可用账号数量
     pub fn get_available_count(&self) -> usize {
         let accounts = self.accounts.lock().unwrap();
@@ -66,10 +83,15 @@ impl AccountPool {
 
     /// 获取下一个可用账号（轮询）
     pub fn get_next_account(&self) -> Option<ProxyAccount> {
+        self.get_next_account_where(|_| true)
+    }
+
+    /// 获取下一个满足条件的可用账号（轮询）
+    pub fn get_next_account_where(&self, filter: impl Fn(&ProxyAccount) -> bool) -> Option<ProxyAccount> {
         let accounts = self.accounts.lock().unwrap();
         let available: Vec<_> = accounts
             .values()
-            .filter(|acc| acc.is_available)
+            .filter(|acc| acc.is_available && filter(acc))
             .collect();
 
         if available.is_empty() {
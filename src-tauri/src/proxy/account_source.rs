@@ -0,0 +1,169 @@
+// 从账号数据文件构建代理账号
+use super::types::ProxyAccount;
+use serde_json::{json, Value};
+use std::path::Path;
+
+/// 刷新后需要写回账号文件的令牌
+#[derive(Debug, Clone)]
+pub struct RefreshedToken {
+    pub account_id: String,
+    pub access_token: String,
+    /// 上游没有轮换时为 None，保留文件中原有的值
+    pub refresh_token: Option<String>,
+    pub expires_at: i64,
+}
+
+/// 读取账号文件并转换为代理账号
+pub fn load_accounts_file(path: &Path, selected_ids: &[String]) -> Result<Vec<ProxyAccount>, String> {
+    let data = std::fs::read_to_string(path)
+        .map_err(|e| format!("读取账号文件失败 {:?}: {}", path, e))?;
+    parse_accounts(&data, selected_ids)
+}
+
+/// 把刷新后的令牌写回账号文件，其余字段原样保留
+///
+/// 先写入同目录的临时文件再重命名，进程中途退出也不会留下不完整的账号文件
+pub fn save_refreshed_tokens(path: &Path, tokens: &[RefreshedToken]) -> Result<(), String> {
+    let data = std::fs::read_to_string(path)
+        .map_err(|e| format!("读取账号文件失败 {:?}: {}", path, e))?;
+    let mut items: Vec<Value> = serde_json::from_str(&data)
+        .map_err(|e| format!("解析账号文件失败: {}", e))?;
+
+    for item in items.iter_mut() {
+        let Some(id) = item.get("id").and_then(|id| id.as_str()).map(|id| id.to_string()) else {
+            continue;
+        };
+        let Some(token) = tokens.iter().find(|token| token.account_id == id) else {
+            continue;
+        };
+        // 管理器格式的令牌在 credentials 中，ProxyAccount 格式在顶层
+        let target = if item.get("credentials").is_some() {
+            item.get_mut("credentials")
+        } else {
+            Some(item)
+        };
+        let Some(fields) = target.and_then(|t| t.as_object_mut()) else {
+            continue;
+        };
+        fields.insert("accessToken".to_string(), json!(token.access_token));
+        if let Some(refresh_token) = &token.refresh_token {
+            fields.insert("refreshToken".to_string(), json!(refresh_token));
+        }
+        fields.insert("expiresAt".to_string(), json!(token.expires_at));
+    }
+
+    let content = serde_json::to_string_pretty(&items)
+        .map_err(|e| format!("序列化账号数据失败: {}", e))?;
+    let file_name = path.file_name().ok_or_else(|| format!("无效的账号文件路径 {:?}", path))?;
+    let temp_path = path.with_file_name(format!("{}.tmp", file_name.to_string_lossy()));
+    std::fs::write(&temp_path, content)
+        .map_err(|e| format!("写入临时文件失败 {:?}: {}", temp_path, e))?;
+    std::fs::rename(&temp_path, path).map_err(|e| {
+        let _ = std::fs::remove_file(&temp_path);
+        format!("保存账号文件失败 {:?}: {}", path, e)
+    })
+}
+
+/// 解析账号数据
+///
+/// 同时支持管理器保存的 accounts.json（`Account[]`）和直接导出的 `ProxyAccount[]`。
+/// `selected_ids` 为空时使用全部账号，与前端同步逻辑一致。
+pub fn parse_accounts(data: &str, selected_ids: &[String]) -> Result<Vec<ProxyAccount>, String> {
+    let items: Vec<Value> = serde_json::from_str(data)
+        .map_err(|e| format!("解析账号文件失败: {}", e))?;
+
+    let accounts = items
+        .iter()
+        .filter_map(|item| {
+            if item.get("credentials").is_some() {
+                from_stored_account(item)
+            } else {
+                serde_json::from_value::<ProxyAccount>(item.clone()).ok()
+            }
+        })
+        .filter(|acc| !acc.access_token.is_empty())
+        .filter(|acc| selected_ids.is_empty() || selected_ids.contains(&acc.id))
+        .collect();
+
+    Ok(accounts)
+}
+
+/// 管理器账号格式转换为代理账号
+fn from_stored_account(item: &Value) -> Option<ProxyAccount> {
+    let credentials = item.get("credentials")?;
+    let str_field = |v: &Value, key: &str| v.get(key).and_then(|s| s.as_str()).map(|s| s.to_string());
+
+    // 封禁和错误状态的账号保留在列表中，但标记为不可用
+    let status = item.get("status").and_then(|s| s.as_str()).unwrap_or("unknown");
+
+    Some(ProxyAccount {
+        id: str_field(item, "id")?,
+        email: str_field(item, "email"),
+        access_token: str_field(credentials, "accessToken").unwrap_or_default(),
+        refresh_token: str_field(credentials, "refreshToken"),
+        profile_arn: None,
+        expires_at: credentials.get("expiresAt").and_then(|e| e.as_i64()),
+        client_id: str_field(credentials, "clientId"),
+        client_secret: str_field(credentials, "clientSecret"),
+        region: str_field(credentials, "region"),
+        auth_method: str_field(credentials, "authMethod"),
+        is_available: status != "suspended" && status != "error",
+        last_used: Some(0),
+        request_count: 0,
+        error_count: 0,
+        outbound_proxy: item
+            .get("outboundProxy")
+            .and_then(|p| serde_json::from_value(p.clone()).ok()),
+        header_profile: item
+            .get("headerProfile")
+            .and_then(|p| serde_json::from_value(p.clone()).ok()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn save_refreshed_tokens_updates_both_formats_in_place() {
+        let path = std::env::temp_dir().join(format!("kiro-accounts-{}.json", uuid::Uuid::new_v4().simple()));
+        let original = json!([
+            {"id": "a", "email": "a@example.com", "status": "active", "credentials": {"accessToken": "old-a", "refreshToken": "rt-a", "expiresAt": 1, "clientId": "cid"}},
+            {"id": "b", "accessToken": "old-b", "refreshToken": "rt-b", "expiresAt": 1},
+            {"id": "c", "accessToken": "old-c", "refreshToken": "rt-c", "expiresAt": 1}
+        ]);
+        std::fs::write(&path, original.to_string()).unwrap();
+
+        let tokens = [
+            RefreshedToken {
+                account_id: "a".to_string(),
+                access_token: "new-a".to_string(),
+                refresh_token: Some("rt-a2".to_string()),
+                expires_at: 100,
+            },
+            RefreshedToken {
+                account_id: "b".to_string(),
+                access_token: "new-b".to_string(),
+                refresh_token: None,
+                expires_at: 200,
+            },
+        ];
+        save_refreshed_tokens(&path, &tokens).unwrap();
+
+        let saved: Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(saved[0]["credentials"]["accessToken"], "new-a");
+        assert_eq!(saved[0]["credentials"]["refreshToken"], "rt-a2");
+        assert_eq!(saved[0]["credentials"]["expiresAt"], 100);
+        assert_eq!(saved[0]["credentials"]["clientId"], "cid");
+        assert_eq!(saved[0]["email"], "a@example.com");
+        assert!(saved[0].get("accessToken").is_none());
+        assert_eq!(saved[1]["accessToken"], "new-b");
+        assert_eq!(saved[1]["refreshToken"], "rt-b");
+        assert_eq!(saved[1]["expiresAt"], 200);
+        assert_eq!(saved[2], original[2]);
+
+        let accounts = parse_accounts(&saved.to_string(), &[]).unwrap();
+        assert_eq!(accounts.len(), 3);
+    }
+}
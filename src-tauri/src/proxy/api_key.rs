@@ -0,0 +1,177 @@
+// API Key 生成与校验 - 配置中只保存可见前缀和加盐哈希，明文仅在生成时返回一次
+use super::types::{ApiKey, ApiKeyUsage, ProxyConfig};
+use sha2::{Digest, Sha256};
+
+/// 生成的 Key 的固定开头
+const KEY_PREFIX: &str = "sk-kiro-";
+
+/// 列表中显示的前缀长度（字符数）
+const VISIBLE_PREFIX_LEN: usize = 12;
+
+/// 生成新的 API Key，返回保存到配置中的记录和只展示一次的明文
+pub fn generate(name: &str) -> (ApiKey, String) {
+    let key = format!(
+        "{}{}{}",
+        KEY_PREFIX,
+        uuid::Uuid::new_v4().simple(),
+        uuid::Uuid::new_v4().simple()
+    );
+    let mut api_key = ApiKey {
+        id: uuid::Uuid::new_v4().to_string(),
+        name: name.to_string(),
+        prefix: String::new(),
+        key_hash: String::new(),
+        salt: String::new(),
+        legacy_key: None,
+        enabled: true,
+        credits_limit: None,
+        usage: ApiKeyUsage::default(),
+        created_at: chrono::Utc::now().timestamp_millis(),
+        last_used_at: None,
+    };
+    set_secret(&mut api_key, &key);
+    (api_key, key)
+}
+
+/// 校验客户端提供的 Key 是否与记录匹配
+///
+/// 先按公开的前缀筛选，再以恒定时间比较哈希
+pub fn verify(api_key: &ApiKey, provided_key: &str) -> bool {
+    if api_key.key_hash.is_empty() || !provided_key.starts_with(&api_key.prefix) {
+        return false;
+    }
+    constant_time_eq(
+        hash_key(&api_key.salt, provided_key).as_bytes(),
+        api_key.key_hash.as_bytes(),
+    )
+}
+
+/// 把旧版配置中的明文 Key 转换为前缀和哈希，返回是否有 Key 被转换
+pub fn hash_plaintext_keys(config: &mut ProxyConfig) -> bool {
+    let Some(api_keys) = config.api_keys.as_mut() else {
+        return false;
+    };
+    let mut migrated = 0;
+    for api_key in api_keys.iter_mut() {
+        let Some(key) = api_key.legacy_key.take() else {
+            continue;
+        };
+        let key = key.trim().to_string();
+        if key.is_empty() {
+            continue;
+        }
+        set_secret(api_key, &key);
+        migrated += 1;
+    }
+    if migrated > 0 {
+        println!("[ApiKey] 已将 {} 个明文 API Key 转换为哈希存储", migrated);
+    }
+    migrated > 0
+}
+
+/// 生成新的盐并写入前缀和哈希
+fn set_secret(api_key: &mut ApiKey, key: &str) {
+    // 短 Key 最多显示一半，避免前缀泄露整个 Key
+    let visible = VISIBLE_PREFIX_LEN.min(key.chars().count() / 2);
+    api_key.prefix = key.chars().take(visible).collect();
+    api_key.salt = uuid::Uuid::new_v4().simple().to_string();
+    api_key.key_hash = hash_key(&api_key.salt, key);
+}
+
+/// SHA-256(salt || key) 的十六进制表示
+fn hash_key(salt: &str, key: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(salt.as_bytes());
+    hasher.update(key.as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+/// 恒定时间比较，耗时只与长度有关，不随第一个不同字节的位置变化
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proxy::pipeline::check_api_key;
+
+    fn config_with(api_keys: Vec<ApiKey>) -> ProxyConfig {
+        ProxyConfig {
+            api_keys: Some(api_keys),
+            ..ProxyConfig::default()
+        }
+    }
+
+    #[test]
+    fn generated_key_round_trips() {
+        let (api_key, key) = generate("test");
+        assert!(key.starts_with(KEY_PREFIX));
+        assert_eq!(api_key.prefix, key[..VISIBLE_PREFIX_LEN]);
+        assert!(!api_key.key_hash.contains(&key));
+        assert!(verify(&api_key, &key));
+
+        // 同名生成的 Key 使用不同的盐
+        let (other, other_key) = generate("test");
+        assert_ne!(api_key.salt, other.salt);
+        assert!(!verify(&api_key, &other_key));
+    }
+
+    #[test]
+    fn rejects_wrong_key_with_the_same_prefix() {
+        let (api_key, key) = generate("test");
+        let mut wrong = key.clone();
+        let last = if wrong.ends_with('0') { "1" } else { "0" };
+        wrong.replace_range(wrong.len() - 1.., last);
+        assert!(wrong.starts_with(&api_key.prefix));
+        assert!(!verify(&api_key, &wrong));
+        assert!(!verify(&api_key, &api_key.prefix));
+        assert!(!verify(&api_key, ""));
+    }
+
+    #[test]
+    fn rejects_disabled_key() {
+        let (mut api_key, key) = generate("test");
+        let id = api_key.id.clone();
+        assert_eq!(check_api_key(&config_with(vec![api_key.clone()]), Some(&key)), Ok(Some(id)));
+
+        api_key.enabled = false;
+        let config = config_with(vec![api_key]);
+        assert_eq!(check_api_key(&config, Some(&key)).unwrap_err().1, "invalid_api_key");
+        assert_eq!(check_api_key(&config, None).unwrap_err().1, "missing_authorization");
+    }
+
+    #[test]
+    fn migrates_plaintext_keys_without_writing_them_back() {
+        let saved = r#"{"id":"1","name":"old","key":" sk-legacy-secret ","enabled":true}"#;
+        let api_key: ApiKey = serde_json::from_str(saved).unwrap();
+        assert!(api_key.key_hash.is_empty());
+        let mut config = config_with(vec![api_key]);
+
+        assert!(hash_plaintext_keys(&mut config));
+        let api_key = &config.api_keys.as_ref().unwrap()[0];
+        assert!(api_key.legacy_key.is_none());
+        // 短 Key 只显示一半
+        assert_eq!(api_key.prefix, "sk-legac");
+        assert!(verify(api_key, "sk-legacy-secret"));
+
+        let saved = serde_json::to_string(api_key).unwrap();
+        assert!(!saved.contains("sk-legacy-secret"));
+        assert!(!saved.contains("\"key\""));
+
+        // 再次加载时没有需要转换的 Key
+        assert!(!hash_plaintext_keys(&mut config));
+    }
+
+    #[test]
+    fn constant_time_eq_compares_length_and_content() {
+        assert!(constant_time_eq(b"abc", b"abc"));
+        assert!(!constant_time_eq(b"abc", b"abd"));
+        assert!(!constant_time_eq(b"abc", b"abcd"));
+        assert!(!constant_time_eq(b"", b"a"));
+        assert!(constant_time_eq(b"", b""));
+    }
+}
@@ -1,19 +1,37 @@
 // 反代服务 Tauri 命令
+use super::account_source::load_accounts_file;
+use super::api_key::{generate, hash_plaintext_keys};
+use super::config_watcher::watch_config_file;
 use super::types::*;
 use super::ProxyServer;
+use crate::http_client::HttpClients;
 use std::sync::Arc;
 use tauri::State;
 use tokio::sync::RwLock;
 use std::path::PathBuf;
+use std::time::Duration;
+
+/// 配置文件检查间隔
+const CONFIG_WATCH_INTERVAL: Duration = Duration::from_secs(2);
 
 /// 全局代理服务器状态
 pub struct ProxyState {
     pub server: Arc<RwLock<Option<ProxyServer>>>,
     pub config_path: PathBuf,
+    pub http_clients: HttpClients,
+    /// 自动启动结果的通知状态
+    auto_start: std::sync::Mutex<AutoStartNotice>,
+}
+
+/// 前端就绪前产生的自动启动结果先暂存，就绪后再发送，避免事件在监听注册前丢失
+#[derive(Default)]
+struct AutoStartNotice {
+    frontend_ready: bool,
+    pending: Option<serde_json::Value>,
 }
 
 impl ProxyState {
-    pub fn new(app_handle: &tauri::AppHandle) -> Self {
+    pub fn new(app_handle: &tauri::AppHandle, http_clients: HttpClients) -> Self {
         use tauri::Manager;
         
         let config_dir = app_handle
@@ -28,14 +46,22 @@ impl ProxyState {
         Self {
             server: Arc::new(RwLock::new(None)),
             config_path,
+            http_clients,
+            auto_start: std::sync::Mutex::new(AutoStartNotice::default()),
         }
     }
     
-    /// 加载配置
+    /// 加载配置，旧版明文 API Key 转换为哈希后写回
     pub fn load_config(&self) -> Option<ProxyConfig> {
         if self.config_path.exists() {
             let content = std::fs::read_to_string(&self.config_path).ok()?;
-            serde_json::from_str(&content).ok()
+            let mut config: ProxyConfig = serde_json::from_str(&content).ok()?;
+            if hash_plaintext_keys(&mut config) {
+                if let Err(e) = self.save_config(&config) {
+                    println!("[Proxy] {}", e);
+                }
+            }
+            Some(config)
         } else {
             None
         }
@@ -51,6 +77,89 @@ impl ProxyState {
         
         Ok(())
     }
+    
+    /// 监听配置文件，外部修改后自动应用到代理服务器
+    pub fn watch_config(&self) {
+        let server = self.server.clone();
+        let path = self.config_path.clone();
+        tauri::async_runtime::spawn(watch_config_file(path, CONFIG_WATCH_INTERVAL, move |config| {
+            let server = server.clone();
+            async move {
+                match server.read().await.as_ref() {
+                    Some(server) => server.update_config(config).await,
+                    // 服务器尚未初始化，首次使用时会直接读取配置文件
+                    None => Ok(()),
+                }
+            }
+        }));
+    }
+}
+
+/// 应用启动时加载保存的配置和账号，开启自动启动时启动代理服务器并通知前端
+pub async fn auto_start_proxy(app_handle: tauri::AppHandle) {
+    use tauri::{Emitter, Manager};
+    
+    let state = app_handle.state::<ProxyState>();
+    let config = state.load_config().unwrap_or_default();
+    if !config.auto_start.unwrap_or(false) {
+        return;
+    }
+    
+    println!("[Proxy] 自动启动代理服务器");
+    let address = format!("http://{}:{}", config.host, config.port);
+    
+    let payload = match start_with_saved_accounts(&state, config).await {
+        Ok(count) => {
+            println!("[Proxy] 自动启动成功: {}，账号 {} 个", address, count);
+            serde_json::json!({
+                "success": true,
+                "address": address,
+                "accountCount": count
+            })
+        }
+        Err(e) => {
+            println!("[Proxy] 自动启动失败: {}", e);
+            serde_json::json!({
+                "success": false,
+                "error": e
+            })
+        }
+    };
+    
+    let mut notice = state.auto_start.lock().unwrap();
+    if notice.frontend_ready {
+        let _ = app_handle.emit("proxy-auto-start", payload);
+    } else {
+        notice.pending = Some(payload);
+    }
+}
+
+/// 前端已注册自动启动事件的监听，发送暂存的自动启动结果
+#[tauri::command]
+pub fn proxy_frontend_ready(app_handle: tauri::AppHandle, state: State<'_, ProxyState>) {
+    use tauri::Emitter;
+    
+    let mut notice = state.auto_start.lock().unwrap();
+    notice.frontend_ready = true;
+    if let Some(payload) = notice.pending.take() {
+        let _ = app_handle.emit("proxy-auto-start", payload);
+    }
+}
+
+/// 从 accounts.json 同步账号并启动服务器，返回账号数量
+async fn start_with_saved_accounts(state: &ProxyState, config: ProxyConfig) -> Result<usize, String> {
+    let accounts_path = crate::storage::accounts_file_path()?;
+    let accounts = load_accounts_file(&accounts_path, &config.selected_account_ids)?;
+    if accounts.is_empty() {
+        return Err("没有可用的反代账号".to_string());
+    }
+    
+    let mut server_lock = state.server.write().await;
+    let server = server_lock.get_or_insert_with(|| ProxyServer::new(config, state.http_clients.clone()));
+    let count = server.sync_accounts(accounts);
+    server.start().await?;
+    
+    Ok(count)
 }
 
 /// 启动代理服务器
@@ -94,28 +203,8 @@ pub async fn get_proxy_status(
     
     // 如果服务器未初始化，尝试加载配置
     if server_lock.is_none() {
-        let config = state.load_config().unwrap_or_else(|| {
-            ProxyConfig {
-                enabled: false,
-                port: 5580,
-                host: "127.0.0.1".to_string(),
-                api_key: None,
-                api_keys: None,
-                enable_multi_account: true,
-                selected_account_ids: vec![],
-                log_requests: true,
-                max_retries: Some(3),
-                preferred_endpoint: None,
-                auto_start: None,
-                auto_continue_rounds: None,
-                disable_tools: None,
-                auto_switch_on_quota_exhausted: None,
-                model_mappings: None,
-                enable_openai: true,
-                enable_claude: true,
-            }
-        });
-        *server_lock = Some(ProxyServer::new(config));
+        let config = state.load_config().unwrap_or_default();
+        *server_lock = Some(ProxyServer::new(config, state.http_clients.clone()));
     }
     
     if let Some(server) = server_lock.as_ref() {
@@ -143,25 +232,48 @@ pub async fn get_proxy_status(
 /// 更新代理配置
 #[tauri::command]
 pub async fn update_proxy_config(
-    config: ProxyConfig,
+    mut config: ProxyConfig,
     state: State<'_, ProxyState>,
 ) -> Result<serde_json::Value, String> {
     println!("[Proxy] 更新配置");
     
-    // 保存配置到文件
-    state.save_config(&config)?;
+    hash_plaintext_keys(&mut config);
+    config.validate()?;
     
     let mut server_lock = state.server.write().await;
     if let Some(server) = server_lock.as_mut() {
-        server.update_config(config.clone()).await;
+        // 运行中修改地址会先绑定新地址，失败时不保存
+        server.update_config(config.clone()).await?;
     } else {
         // 首次初始化
-        *server_lock = Some(ProxyServer::new(config));
+        *server_lock = Some(ProxyServer::new(config.clone(), state.http_clients.clone()));
     }
     
+    // 保存配置到文件
+    state.save_config(&config)?;
+    
     Ok(serde_json::json!({ "success": true }))
 }
 
+/// 生成新的 API Key
+///
+/// 明文只出现在这次返回值中，由前端展示一次；加入配置的记录只包含前缀和哈希
+#[tauri::command]
+pub async fn generate_proxy_api_key(name: String) -> Result<serde_json::Value, String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("请输入 Key 名称".to_string());
+    }
+    
+    let (api_key, key) = generate(name);
+    println!("[Proxy] 生成 API Key: {} ({}...)", api_key.name, api_key.prefix);
+    
+    Ok(serde_json::json!({
+        "apiKey": api_key,
+        "key": key
+    }))
+}
+
 /// 同步账号到代理池
 #[tauri::command]
 pub async fn sync_proxy_accounts(
@@ -175,26 +287,7 @@ pub async fn sync_proxy_accounts(
     // 如果服务器未初始化，先创建默认配置
     if server_lock.is_none() {
         println!("[Proxy] 服务器未初始化，创建默认配置");
-        let default_config = ProxyConfig {
-            enabled: false,
-            port: 5580,
-            host: "127.0.0.1".to_string(),
-            api_key: None,
-            api_keys: None,
-            enable_multi_account: true,
-            selected_account_ids: vec![],
-            log_requests: true,
-            max_retries: Some(3),
-            preferred_endpoint: None,
-            auto_start: None,
-            auto_continue_rounds: None,
-            disable_tools: None,
-            auto_switch_on_quota_exhausted: None,
-            model_mappings: None,
-            enable_openai: true,
-            enable_claude: true,
-        };
-        *server_lock = Some(ProxyServer::new(default_config));
+        *server_lock = Some(ProxyServer::new(ProxyConfig::default(), state.http_clients.clone()));
     }
     
     if let Some(server) = server_lock.as_ref() {
@@ -235,10 +328,10 @@ pub async fn get_proxy_models(
 ) -> Result<serde_json::Value, String> {
     let server_lock = state.server.read().await;
     if let Some(server) = server_lock.as_ref() {
-        let models = server.get_available_models().await?;
+        let (models, from_cache) = server.get_available_models().await?;
         Ok(serde_json::json!({
             "models": models,
-            "fromCache": false
+            "fromCache": from_cache
         }))
     } else {
         Err("代理服务器未初始化".to_string())
@@ -0,0 +1,97 @@
+// 反代配置文件监听 - 修改 proxy_config.json 后无需重启即可生效
+use super::api_key::hash_plaintext_keys;
+use super::types::ProxyConfig;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// 读取并校验配置文件
+///
+/// 文件中有旧版明文 API Key 时转换为哈希并写回，写回失败不影响加载
+pub fn load_config_file(path: &Path) -> Result<ProxyConfig, String> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| format!("读取配置文件失败 {:?}: {}", path, e))?;
+    let mut config: ProxyConfig = serde_json::from_str(&content)
+        .map_err(|e| format!("解析配置文件失败 {:?}: {}", path, e))?;
+    if hash_plaintext_keys(&mut config) {
+        if let Err(e) = save_config_file(path, &config) {
+            println!("[ConfigWatcher] {}", e);
+        }
+    }
+    config.validate()?;
+    Ok(config)
+}
+
+/// 写入配置文件，先写临时文件再替换，监听方不会读到写了一半的文件
+pub fn save_config_file(path: &Path, config: &ProxyConfig) -> Result<(), String> {
+    let content = serde_json::to_string_pretty(config)
+        .map_err(|e| format!("序列化配置失败: {}", e))?;
+    let file_name = path.file_name().ok_or_else(|| format!("无效的配置文件路径 {:?}", path))?;
+    let temp_path = path.with_file_name(format!("{}.tmp", file_name.to_string_lossy()));
+    std::fs::write(&temp_path, content)
+        .map_err(|e| format!("写入临时文件失败 {:?}: {}", temp_path, e))?;
+    std::fs::rename(&temp_path, path).map_err(|e| {
+        let _ = std::fs::remove_file(&temp_path);
+        format!("保存配置文件失败 {:?}: {}", path, e)
+    })
+}
+
+/// 文件修改时间和大小，任一变化即视为文件已修改
+fn file_stamp(path: &Path) -> Option<(SystemTime, u64)> {
+    let metadata = std::fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+/// 轮询配置文件，文件变化且校验通过后调用 `on_change`
+///
+/// 校验失败时保留当前配置，直到文件再次被修改
+pub async fn watch_config_file<F, Fut>(path: PathBuf, interval: Duration, on_change: F)
+where
+    F: Fn(ProxyConfig) -> Fut,
+    Fut: Future<Output = Result<(), String>>,
+{
+    let mut last_stamp = file_stamp(&path);
+    let mut ticker = tokio::time::interval(interval);
+    ticker.tick().await;
+
+    loop {
+        ticker.tick().await;
+
+        let stamp = file_stamp(&path);
+        if stamp.is_none() || stamp == last_stamp {
+            continue;
+        }
+        last_stamp = stamp;
+
+        println!("[ConfigWatcher] 检测到配置文件变化: {:?}", path);
+        let result = match load_config_file(&path) {
+            Ok(config) => on_change(config).await,
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(()) => println!("[ConfigWatcher] 配置已重新加载"),
+            Err(e) => println!("[ConfigWatcher] 配置未应用: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn saves_through_a_temp_file_and_reloads() {
+        let dir = std::env::temp_dir().join(format!("kiro-config-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("proxy_config.json");
+        let config = ProxyConfig {
+            port: 18080,
+            ..ProxyConfig::default()
+        };
+
+        save_config_file(&path, &config).unwrap();
+        assert!(!dir.join("proxy_config.json.tmp").exists());
+        assert_eq!(load_config_file(&path).unwrap().port, 18080);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
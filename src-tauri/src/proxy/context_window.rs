@@ -0,0 +1,216 @@
+// 上下文窗口 - 估算请求的输入 token，超出模型上限时按配置裁剪对话或拒绝
+use super::token_estimator::{estimate_text_tokens, truncate_to_token_limit};
+
+/// 本地估算有误差，按上限的这个百分比留出余量
+const BUDGET_PERCENT: u64 = 90;
+
+/// 截断工具结果时每条保留的 token 数
+const TOOL_RESULT_KEEP_TOKENS: u64 = 200;
+
+/// 超出上下文窗口时的处理方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// 从最早的轮次开始丢弃
+    Truncate,
+    /// 从最早的工具结果开始截断，截断处注明省略了多少内容；配置值沿用 summarize
+    Summarize,
+    /// 直接拒绝
+    Reject,
+}
+
+impl OverflowPolicy {
+    /// 解析配置值，未知值按 truncate 处理
+    pub fn from_config(value: &str) -> Self {
+        match value {
+            "summarize" => Self::Summarize,
+            "reject" => Self::Reject,
+            _ => Self::Truncate,
+        }
+    }
+}
+
+/// 对话中的一轮消息
+#[derive(Debug, Clone)]
+pub struct Turn {
+    pub text: String,
+    /// 是否为工具调用结果，summarize 策略只截断这类消息
+    pub tool_result: bool,
+}
+
+impl Turn {
+    /// 普通消息
+    pub fn message(text: String) -> Self {
+        Self { text, tool_result: false }
+    }
+
+    /// 工具调用结果
+    pub fn tool_result(text: String) -> Self {
+        Self { text, tool_result: true }
+    }
+}
+
+/// 模型的上下文窗口和超出时的处理方式
+#[derive(Debug, Clone, Copy)]
+pub struct ContextWindow {
+    /// 模型的输入 token 上限，模型目录中没有时不做限制
+    pub max_input_tokens: Option<u64>,
+    pub policy: OverflowPolicy,
+}
+
+impl ContextWindow {
+    /// 按上下文窗口裁剪对话，fixed_tokens 是系统提示词、工具定义等不参与裁剪的部分
+    ///
+    /// 最后一轮消息始终保留原样；裁剪后仍放不下或策略为 reject 时返回错误信息
+    pub fn fit(&self, mut turns: Vec<Turn>, fixed_tokens: u64) -> Result<Vec<Turn>, String> {
+        let Some(limit) = self.max_input_tokens else {
+            return Ok(turns);
+        };
+        let budget = limit * BUDGET_PERCENT / 100;
+        let mut turn_tokens: Vec<u64> = turns.iter().map(|turn| estimate_text_tokens(&turn.text)).collect();
+        let mut total = fixed_tokens + turn_tokens.iter().sum::<u64>();
+        if total <= budget {
+            return Ok(turns);
+        }
+
+        let original = total;
+        let exceeded = |total: u64| format!("输入约 {} tokens，超出模型上下文窗口 {} tokens，请缩短对话后重试", total, limit);
+        let keep = turns.len().saturating_sub(1);
+
+        match self.policy {
+            OverflowPolicy::Reject => Err(exceeded(total)),
+            OverflowPolicy::Truncate => {
+                let mut dropped = 0;
+                while total > budget && dropped < keep {
+                    total -= turn_tokens[dropped];
+                    dropped += 1;
+                }
+                if total > budget {
+                    return Err(exceeded(original));
+                }
+                println!(
+                    "[Context] 输入约 {} tokens 超出上限 {}，丢弃最早的 {} 轮对话",
+                    original, limit, dropped
+                );
+                Ok(turns.split_off(dropped))
+            }
+            OverflowPolicy::Summarize => {
+                let mut summarized = 0;
+                for (turn, tokens) in turns.iter_mut().zip(turn_tokens.iter_mut()).take(keep) {
+                    if total <= budget {
+                        break;
+                    }
+                    if !turn.tool_result {
+                        continue;
+                    }
+                    let Some(kept) = truncate_to_token_limit(&turn.text, TOOL_RESULT_KEEP_TOKENS) else {
+                        continue;
+                    };
+                    let kept_chars = kept.chars().count();
+                    let total_chars = turn.text.chars().count();
+                    let text = format!(
+                        "{}\n[tool output truncated: showing the first {} of {} characters, {} characters omitted]",
+                        kept,
+                        kept_chars,
+                        total_chars,
+                        total_chars - kept_chars
+                    );
+                    let new_tokens = estimate_text_tokens(&text);
+                    total = total.saturating_sub(*tokens) + new_tokens;
+                    *tokens = new_tokens;
+                    turn.text = text;
+                    summarized += 1;
+                }
+                if total > budget {
+                    return Err(exceeded(original));
+                }
+                println!(
+                    "[Context] 输入约 {} tokens 超出上限 {}，截断了 {} 条工具结果",
+                    original, limit, summarized
+                );
+                Ok(turns)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 每个四字母单词估算为 1 个 token
+    fn words(count: usize) -> String {
+        "abcd ".repeat(count)
+    }
+
+    fn window(limit: u64, policy: OverflowPolicy) -> ContextWindow {
+        ContextWindow {
+            max_input_tokens: Some(limit),
+            policy,
+        }
+    }
+
+    #[test]
+    fn keeps_turns_within_budget_or_without_limit() {
+        let turns = vec![Turn::message(words(40)), Turn::message(words(40))];
+        let unlimited = ContextWindow {
+            max_input_tokens: None,
+            policy: OverflowPolicy::Reject,
+        };
+        assert_eq!(unlimited.fit(turns.clone(), 1_000_000).unwrap().len(), 2);
+        // 预算为上限的 90%
+        assert_eq!(window(100, OverflowPolicy::Reject).fit(turns.clone(), 10).unwrap().len(), 2);
+        assert!(window(100, OverflowPolicy::Reject).fit(turns, 11).is_err());
+    }
+
+    #[test]
+    fn truncate_drops_oldest_turns_and_keeps_the_last() {
+        let turns: Vec<Turn> = (0..5).map(|i| Turn::message(format!("{}{}", i, words(29)))).collect();
+        let fitted = window(100, OverflowPolicy::Truncate).fit(turns, 0).unwrap();
+        assert_eq!(fitted.len(), 3);
+        assert!(fitted[0].text.starts_with('2'));
+        assert!(fitted[2].text.starts_with('4'));
+
+        let too_long = vec![Turn::message(words(10)), Turn::message(words(100))];
+        let err = window(100, OverflowPolicy::Truncate).fit(too_long, 0).unwrap_err();
+        assert!(err.contains("超出模型上下文窗口 100 tokens"));
+    }
+
+    #[test]
+    fn summarize_truncates_only_earlier_tool_results_with_a_marker() {
+        let turns = vec![
+            Turn::tool_result(words(500)),
+            Turn::message(words(30)),
+            Turn::message(words(10)),
+        ];
+        let fitted = window(300, OverflowPolicy::Summarize).fit(turns, 0).unwrap();
+        assert_eq!(fitted.len(), 3);
+        assert!(fitted[0].text.contains("[tool output truncated: showing the first "));
+        assert!(fitted[0].text.ends_with(" of 2500 characters, 1500 characters omitted]"));
+        assert!(estimate_text_tokens(&fitted[0].text) < 250);
+        assert_eq!(fitted[1].text, words(30));
+        assert_eq!(fitted[2].text, words(10));
+
+        // 没有可截断的工具结果时无法放下
+        let messages = vec![Turn::message(words(500)), Turn::message(words(10))];
+        assert!(window(300, OverflowPolicy::Summarize).fit(messages, 0).is_err());
+
+        // 最后一轮即使是工具结果也保持原样
+        let last_tool = vec![Turn::message(words(10)), Turn::tool_result(words(500))];
+        assert!(window(300, OverflowPolicy::Summarize).fit(last_tool, 0).is_err());
+    }
+
+    #[test]
+    fn reject_returns_an_error_with_the_estimate() {
+        let turns = vec![Turn::message(words(50)), Turn::message(words(50))];
+        let err = window(100, OverflowPolicy::Reject).fit(turns, 0).unwrap_err();
+        assert!(err.starts_with("输入约 100 tokens"));
+    }
+
+    #[test]
+    fn policy_parses_config_values() {
+        assert_eq!(OverflowPolicy::from_config("summarize"), OverflowPolicy::Summarize);
+        assert_eq!(OverflowPolicy::from_config("reject"), OverflowPolicy::Reject);
+        assert_eq!(OverflowPolicy::from_config("truncate"), OverflowPolicy::Truncate);
+        assert_eq!(OverflowPolicy::from_config("unknown"), OverflowPolicy::Truncate);
+    }
+}
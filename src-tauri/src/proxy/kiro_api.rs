@@ -1,8 +1,11 @@
 // Kiro API 调用
 use super::types::{KiroRequest, ProxyAccount};
+use super::upstream_error::UpstreamError;
+use crate::header_profile::HeaderProfile;
+use crate::http_client::API_TIMEOUT;
+use futures::StreamExt;
 use reqwest::Client;
 use serde_json::Value;
-use std::time::Duration;
 
 /// Kiro API 端点配置
 const KIRO_ENDPOINTS: &[(&str, &str, &str)] = &[
@@ -18,18 +21,28 @@ const KIRO_ENDPOINTS: &[(&str, &str, &str)] = &[
     ),
 ];
 
-/// 调用 Kiro API（非流式）
+/// 调用 Kiro API，等待完整响应
 pub async fn call_kiro_api(
+    client: &Client,
+    profile: &HeaderProfile,
     account: &ProxyAccount,
     request: &KiroRequest,
     model: &str,
     endpoint_index: usize,
-) -> Result<Value, String> {
-    let client = Client::builder()
-        .timeout(Duration::from_secs(120))
-        .build()
-        .map_err(|e| format!("创建 HTTP 客户端失败: {}", e))?;
+) -> Result<Value, UpstreamError> {
+    call_kiro_api_streaming(client, profile, account, request, model, endpoint_index, &mut |_| {}).await
+}
 
+/// 调用 Kiro API，事件流边到达边解析，正文和思考过程的增量交给 on_delta，最后返回完整响应
+pub async fn call_kiro_api_streaming(
+    client: &Client,
+    profile: &HeaderProfile,
+    account: &ProxyAccount,
+    request: &KiroRequest,
+    model: &str,
+    endpoint_index: usize,
+    on_delta: &mut impl FnMut(KiroDelta),
+) -> Result<Value, UpstreamError> {
     let (url, origin, amz_target) = KIRO_ENDPOINTS
         .get(endpoint_index)
         .ok_or("无效的端点索引")?;
@@ -59,42 +72,7 @@ pub async fn call_kiro_api(
             .parse()
             .map_err(|e| format!("无效的 X-Amz-Target 头: {}", e))?,
     );
-    headers.insert(
-        "User-Agent",
-        "aws-sdk-js/1.0.18 ua/2.1 os/windows lang/js md/nodejs#20.16.0 api/codewhispererstreaming#1.0.18 m/E KiroIDE-0.6.18"
-            .parse()
-            .map_err(|e| format!("无效的 User-Agent 头: {}", e))?,
-    );
-    headers.insert(
-        "X-Amz-User-Agent",
-        "aws-sdk-js/1.0.18 KiroIDE-0.6.18"
-            .parse()
-            .map_err(|e| format!("无效的 X-Amz-User-Agent 头: {}", e))?,
-    );
-    headers.insert(
-        "x-amzn-kiro-agent-mode",
-        "spec"
-            .parse()
-            .map_err(|e| format!("无效的 agent-mode 头: {}", e))?,
-    );
-    headers.insert(
-        "x-amzn-codewhisperer-optout",
-        "true"
-            .parse()
-            .map_err(|e| format!("无效的 optout 头: {}", e))?,
-    );
-    headers.insert(
-        "Amz-Sdk-Request",
-        "attempt=1; max=3"
-            .parse()
-            .map_err(|e| format!("无效的 Sdk-Request 头: {}", e))?,
-    );
-    headers.insert(
-        "Amz-Sdk-Invocation-Id",
-        uuid::Uuid::new_v4().to_string()
-            .parse()
-            .map_err(|e| format!("无效的 Invocation-Id 头: {}", e))?,
-    );
+    profile.apply(&mut headers)?;
 
     // 构建请求体 - 使用 Kiro 原生格式
     let mut body = serde_json::to_value(request)
@@ -115,188 +93,650 @@ pub async fn call_kiro_api(
     }
 
     println!("[KiroAPI] 请求 URL: {}", url);
-    println!("[KiroAPI] 请求体: {}", serde_json::to_string_pretty(&body).unwrap_or_default());
+    println!("[KiroAPI] 请求体字节数: {}", body.to_string().len());
 
     let response = client
         .post(*url)
+        .timeout(API_TIMEOUT)
         .headers(headers)
         .json(&body)
         .send()
         .await
-        .map_err(|e| format!("请求失败: {}", e))?;
+        .map_err(|e| UpstreamError::network(&e))?;
 
     let status = response.status();
     if !status.is_success() {
+        let header = |name: &str| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(|v| v.trim().to_string())
+        };
+        let retry_after = header("retry-after").and_then(|v| v.parse().ok());
+        let error_type = header("x-amzn-errortype");
         let error_text = response
             .text()
             .await
             .unwrap_or_else(|_| "无法读取错误响应".to_string());
-        return Err(format!("API 返回错误 {}: {}", status.as_u16(), error_text));
+        let error = UpstreamError::from_response(status.as_u16(), error_type.as_deref(), &error_text, retry_after);
+        println!("[KiroAPI] {}", error);
+        return Err(error);
     }
 
-    // 获取原始字节，解析 AWS Event Stream 格式
-    let response_bytes = response
-        .bytes()
-        .await
-        .map_err(|e| format!("读取响应失败: {}", e))?;
+    // 边接收边解析 AWS Event Stream 格式
+    let mut reader = EventStreamReader::default();
+    let mut received = 0;
+    let mut body = response.bytes_stream();
+    while let Some(chunk) = body.next().await {
+        let chunk = chunk.map_err(|e| UpstreamError::network(&e))?;
+        received += chunk.len();
+        reader.feed(&chunk, on_delta);
+    }
 
-    println!("[KiroAPI] 响应字节数: {}", response_bytes.len());
+    println!("[KiroAPI] 响应字节数: {}", received);
+    Ok(reader.finish(on_delta))
+}
 
-    // 解析 Event Stream，提取所有 content
-    let mut full_content = String::new();
-    let mut input_tokens = 0u64;
-    let mut output_tokens = 0u64;
-    let mut credits = 0.0f64;
+/// 上游的增量输出
+#[derive(Debug, Clone, PartialEq)]
+pub enum KiroDelta {
+    /// 正文
+    Text(String),
+    /// 思考过程
+    Reasoning(String),
+    /// 思考过程的签名
+    ReasoningSignature(String),
+}
+
+/// AWS Event Stream 的增量解析：按到达的字节拆出完整的消息，逐个事件累积为统一的响应格式
+#[derive(Default)]
+struct EventStreamReader {
+    /// 尚未凑成完整消息的字节
+    buffer: Vec<u8>,
+    full_content: String,
+    input_tokens: u64,
+    output_tokens: u64,
+    credits: f64,
+    /// 是否收到上游计量信息，未收到时由调用方按模型倍率估算
+    metered: bool,
+    stop_reason: Option<String>,
+    /// 思考过程，来自 reasoningContentEvent 或正文开头的 <thinking> 标签
+    reasoning: String,
+    reasoning_signature: Option<String>,
+    /// 工具调用按 toolUseId 合并，input 分多个事件返回
+    tool_uses: Vec<(String, String, String)>,
+    inline_thinking: InlineThinkingSplitter,
+}
 
-    let mut offset = 0;
-    while offset < response_bytes.len() {
-        if offset + 16 > response_bytes.len() {
-            break;
+impl EventStreamReader {
+    /// 追加收到的字节，解析其中的完整消息
+    fn feed(&mut self, bytes: &[u8], on_delta: &mut impl FnMut(KiroDelta)) {
+        self.buffer.extend_from_slice(bytes);
+
+        let mut offset = 0;
+        while offset + 16 <= self.buffer.len() {
+            // 读取消息头
+            let prelude = &self.buffer[offset..];
+            let total_length = u32::from_be_bytes([prelude[0], prelude[1], prelude[2], prelude[3]]) as usize;
+            let headers_length = u32::from_be_bytes([prelude[4], prelude[5], prelude[6], prelude[7]]) as usize;
+
+            if total_length < 16 {
+                // 长度无效，丢弃剩余数据
+                println!("[KiroAPI] 无效的消息长度: {}", total_length);
+                offset = self.buffer.len();
+                break;
+            }
+            if offset + total_length > self.buffer.len() {
+                break;
+            }
+
+            let message = &self.buffer[offset..offset + total_length];
+            let event_type = message.get(12..12 + headers_length).and_then(event_type).map(str::to_string);
+
+            // 提取 payload，去掉末尾的 message CRC
+            let payload = message.get(12 + headers_length..total_length - 4).unwrap_or_default();
+            if let Ok(event) = serde_json::from_slice::<Value>(payload) {
+                self.handle_event(event_type.as_deref(), &event, on_delta);
+            }
+            offset += total_length;
+        }
+        self.buffer.drain(..offset);
+    }
+
+    fn handle_event(&mut self, event_type: Option<&str>, event: &Value, on_delta: &mut impl FnMut(KiroDelta)) {
+        // 提取 content，思考事件单独收集
+        if event_type == Some("reasoningContentEvent") {
+            let reasoning_event = event.get("reasoningText").unwrap_or(event);
+            if let Some(text) = reasoning_event.get("text").and_then(|t| t.as_str()) {
+                self.reasoning.push_str(text);
+                on_delta(KiroDelta::Reasoning(text.to_string()));
+            }
+            if let Some(signature) = reasoning_event.get("signature").and_then(|s| s.as_str()) {
+                self.reasoning_signature = Some(signature.to_string());
+                on_delta(KiroDelta::ReasoningSignature(signature.to_string()));
+            }
+        } else if let Some(content) = event.get("content").and_then(|c| c.as_str()) {
+            self.full_content.push_str(content);
+            if self.reasoning.is_empty() {
+                self.inline_thinking.push(content, on_delta);
+            } else {
+                on_delta(KiroDelta::Text(content.to_string()));
+            }
         }
 
-        // 读取消息头
-        let total_length = u32::from_be_bytes([
-            response_bytes[offset],
-            response_bytes[offset + 1],
-            response_bytes[offset + 2],
-            response_bytes[offset + 3],
-        ]) as usize;
+        // 提取 token 信息
+        if let Some(tokens) = event.get("inputTokens").and_then(|t| t.as_u64()) {
+            self.input_tokens = tokens;
+        }
+        if let Some(tokens) = event.get("outputTokens").and_then(|t| t.as_u64()) {
+            self.output_tokens = tokens;
+        }
+        if let Some(c) = event.get("credits").and_then(|c| c.as_f64()) {
+            self.credits = c;
+            self.metered = true;
+        } else if let (Some(usage), Some(_)) = (
+            event.get("usage").and_then(|u| u.as_f64()),
+            event.get("unit").and_then(|u| u.as_str()),
+        ) {
+            // 计量事件：{ "unit": "credit", "usage": 0.12 }
+            self.credits += usage;
+            self.metered = true;
+        }
 
-        if offset + total_length > response_bytes.len() {
-            break;
+        if let Some(reason) = event
+            .get("stopReason")
+            .or_else(|| event.get("stop_reason"))
+            .and_then(|r| r.as_str())
+        {
+            self.stop_reason = Some(reason.to_string());
         }
 
-        let headers_length = u32::from_be_bytes([
-            response_bytes[offset + 4],
-            response_bytes[offset + 5],
-            response_bytes[offset + 6],
-            response_bytes[offset + 7],
-        ]) as usize;
-
-        // 提取 payload
-        let payload_start = offset + 12 + headers_length;
-        let payload_end = offset + total_length - 4; // 减去 message CRC
-
-        if payload_start < payload_end && payload_end <= response_bytes.len() {
-            let payload_bytes = &response_bytes[payload_start..payload_end];
-            
-            // 尝试解析 payload 为 JSON
-            if let Ok(payload_text) = String::from_utf8(payload_bytes.to_vec()) {
-                if let Ok(event) = serde_json::from_str::<Value>(&payload_text) {
-                    // 提取 content
-                    if let Some(content) = event.get("content").and_then(|c| c.as_str()) {
-                        full_content.push_str(content);
-                    }
-                    
-                    // 提取 token 信息
-                    if let Some(tokens) = event.get("inputTokens").and_then(|t| t.as_u64()) {
-                        input_tokens = tokens;
-                    }
-                    if let Some(tokens) = event.get("outputTokens").and_then(|t| t.as_u64()) {
-                        output_tokens = tokens;
-                    }
-                    if let Some(c) = event.get("credits").and_then(|c| c.as_f64()) {
-                        credits = c;
-                    }
+        // 提取工具调用
+        if let Some(id) = event.get("toolUseId").and_then(|t| t.as_str()) {
+            let index = match self.tool_uses.iter().position(|(tool_id, _, _)| tool_id == id) {
+                Some(index) => index,
+                None => {
+                    self.tool_uses.push((id.to_string(), String::new(), String::new()));
+                    self.tool_uses.len() - 1
                 }
+            };
+            if let Some(name) = event.get("name").and_then(|n| n.as_str()) {
+                self.tool_uses[index].1 = name.to_string();
+            }
+            match event.get("input") {
+                Some(Value::String(input)) => self.tool_uses[index].2.push_str(input),
+                Some(input) if !input.is_null() => self.tool_uses[index].2 = input.to_string(),
+                _ => {}
+            }
+        }
+    }
+
+    /// 响应结束，构建统一的响应格式
+    fn finish(mut self, on_delta: &mut impl FnMut(KiroDelta)) -> Value {
+        if self.reasoning.is_empty() {
+            self.inline_thinking.finish(on_delta);
+            if let Some((thinking, rest)) = split_inline_thinking(&self.full_content) {
+                self.reasoning = thinking;
+                self.full_content = rest;
+            }
+        }
+
+        println!("[KiroAPI] 提取的完整内容长度: {}", self.full_content.len());
+        println!("[KiroAPI] Tokens - Input: {}, Output: {}, Credits: {}", self.input_tokens, self.output_tokens, self.credits);
+
+        let mut result = serde_json::json!({
+            "message": self.full_content,
+            "inputTokens": self.input_tokens,
+            "outputTokens": self.output_tokens
+        });
+        if self.metered {
+            result["credits"] = serde_json::json!(self.credits);
+        }
+        if let Some(reason) = self.stop_reason {
+            result["stopReason"] = Value::String(reason);
+        }
+        if !self.reasoning.is_empty() {
+            result["reasoning"] = Value::String(self.reasoning);
+        }
+        if let Some(signature) = self.reasoning_signature {
+            result["reasoningSignature"] = Value::String(signature);
+        }
+        if !self.tool_uses.is_empty() {
+            println!("[KiroAPI] 工具调用: {} 个", self.tool_uses.len());
+            result["toolUses"] = self
+                .tool_uses
+                .into_iter()
+                .map(|(id, name, input)| {
+                    // input 无法解析为 JSON 时保留原始字符串
+                    let input = if input.trim().is_empty() {
+                        serde_json::json!({})
+                    } else {
+                        serde_json::from_str(&input).unwrap_or(Value::String(input))
+                    };
+                    serde_json::json!({ "toolUseId": id, "name": name, "input": input })
+                })
+                .collect();
+        }
+        result
+    }
+}
+
+/// 从 Event Stream 消息头中读取 :event-type
+fn event_type(headers: &[u8]) -> Option<&str> {
+    let mut pos = 0;
+    while pos < headers.len() {
+        let name_len = *headers.get(pos)? as usize;
+        let name = headers.get(pos + 1..pos + 1 + name_len)?;
+        pos += 1 + name_len;
+        let value_type = *headers.get(pos)?;
+        pos += 1;
+        let value_len = match value_type {
+            0 | 1 => 0,
+            2 => 1,
+            3 => 2,
+            4 => 4,
+            5 | 8 => 8,
+            9 => 16,
+            6 | 7 => {
+                let len = u16::from_be_bytes([*headers.get(pos)?, *headers.get(pos + 1)?]) as usize;
+                pos += 2;
+                len
             }
+            _ => return None,
+        };
+        let value = headers.get(pos..pos + value_len)?;
+        pos += value_len;
+        if name == b":event-type" && value_type == 7 {
+            return std::str::from_utf8(value).ok();
         }
+    }
+    None
+}
 
-        offset += total_length;
+/// 开启思考模式后，模型把思考过程放在正文开头的 <thinking> 标签里，拆分为思考过程和正文
+///
+/// 标签未闭合（输出被截断）时全部视为思考过程
+fn split_inline_thinking(content: &str) -> Option<(String, String)> {
+    let rest = content.trim_start().strip_prefix("<thinking>")?;
+    match rest.split_once("</thinking>") {
+        Some((thinking, text)) => Some((thinking.trim().to_string(), text.trim_start().to_string())),
+        None => Some((rest.trim().to_string(), String::new())),
     }
+}
 
-    println!("[KiroAPI] 提取的完整内容: {}", full_content);
-    println!("[KiroAPI] Tokens - Input: {}, Output: {}, Credits: {}", input_tokens, output_tokens, credits);
+/// 流式拆分正文开头的 <thinking> 标签，输出与 split_inline_thinking 的结果一致
+#[derive(Default)]
+struct InlineThinkingSplitter {
+    state: InlineThinkingState,
+    /// 还不能确定归属的文本：可能是开始或结束标签的一部分
+    pending: String,
+}
 
-    // 构建统一的响应格式
-    let result = serde_json::json!({
-        "message": full_content,
-        "inputTokens": input_tokens,
-        "outputTokens": output_tokens,
-        "credits": credits
-    });
+#[derive(Default)]
+enum InlineThinkingState {
+    /// 还不确定正文是否以 <thinking> 开头
+    #[default]
+    Undecided,
+    /// 在标签内，skip_whitespace 表示还在跳过开头的空白
+    Thinking { skip_whitespace: bool },
+    /// 正文
+    Text { skip_whitespace: bool },
+}
 
-    Ok(result)
+impl InlineThinkingSplitter {
+    const OPEN: &'static str = "<thinking>";
+    const CLOSE: &'static str = "</thinking>";
+
+    fn push(&mut self, text: &str, on_delta: &mut impl FnMut(KiroDelta)) {
+        match self.state {
+            InlineThinkingState::Undecided => {
+                self.pending.push_str(text);
+                let trimmed = self.pending.trim_start();
+                if let Some(rest) = trimmed.strip_prefix(Self::OPEN) {
+                    let rest = rest.to_string();
+                    self.pending.clear();
+                    self.state = InlineThinkingState::Thinking { skip_whitespace: true };
+                    self.push(&rest, on_delta);
+                } else if !Self::OPEN.starts_with(trimmed) {
+                    self.state = InlineThinkingState::Text { skip_whitespace: false };
+                    on_delta(KiroDelta::Text(std::mem::take(&mut self.pending)));
+                }
+            }
+            InlineThinkingState::Thinking { skip_whitespace } => {
+                self.pending.push_str(if skip_whitespace { text.trim_start() } else { text });
+                if self.pending.is_empty() {
+                    return;
+                }
+                self.state = InlineThinkingState::Thinking { skip_whitespace: false };
+
+                if let Some((thinking, rest)) = self.pending.split_once(Self::CLOSE) {
+                    let (thinking, rest) = (thinking.to_string(), rest.to_string());
+                    self.pending.clear();
+                    if !thinking.is_empty() {
+                        on_delta(KiroDelta::Reasoning(thinking));
+                    }
+                    self.state = InlineThinkingState::Text { skip_whitespace: true };
+                    self.push(&rest, on_delta);
+                    return;
+                }
+
+                // 末尾可能是被拆开的结束标签，先留着
+                let held = (1..Self::CLOSE.len())
+                    .rev()
+                    .find(|&len| self.pending.ends_with(&Self::CLOSE[..len]))
+                    .unwrap_or(0);
+                let ready = self.pending.len() - held;
+                if ready > 0 {
+                    let thinking: String = self.pending.drain(..ready).collect();
+                    on_delta(KiroDelta::Reasoning(thinking));
+                }
+            }
+            InlineThinkingState::Text { skip_whitespace } => {
+                let text = if skip_whitespace { text.trim_start() } else { text };
+                if !text.is_empty() {
+                    self.state = InlineThinkingState::Text { skip_whitespace: false };
+                    on_delta(KiroDelta::Text(text.to_string()));
+                }
+            }
+        }
+    }
+
+    /// 正文结束，输出还留着的文本；标签未闭合时全部视为思考过程
+    fn finish(&mut self, on_delta: &mut impl FnMut(KiroDelta)) {
+        let pending = std::mem::take(&mut self.pending);
+        if pending.is_empty() {
+            return;
+        }
+        match self.state {
+            InlineThinkingState::Undecided => on_delta(KiroDelta::Text(pending)),
+            InlineThinkingState::Thinking { .. } => on_delta(KiroDelta::Reasoning(pending)),
+            InlineThinkingState::Text { .. } => {}
+        }
+    }
+}
+
+/// 上游是否因输出长度限制而停止
+pub fn stopped_for_length(kiro_response: &Value) -> bool {
+    kiro_response
+        .get("stopReason")
+        .and_then(|r| r.as_str())
+        .is_some_and(|reason| {
+            matches!(
+                reason.to_ascii_lowercase().as_str(),
+                "max_tokens" | "length" | "max_output_tokens"
+            )
+        })
 }
 
-/// 调用 Kiro API（流式）
-pub async fn call_kiro_api_stream(
+/// 调用 Kiro API，输出因长度被截断时自动追加"继续"轮次，最多 continue_rounds 轮
+///
+/// 各轮的文本和工具调用拼接为一个响应，token 和 credits 累加，`rounds` 记录实际调用上游的次数；
+/// 各轮的增量依次交给同一个 on_delta。继续请求失败时返回已拿到的内容
+#[allow(clippy::too_many_arguments)]
+pub async fn call_kiro_api_with_continuation(
+    client: &Client,
+    profile: &HeaderProfile,
     account: &ProxyAccount,
     request: &KiroRequest,
     model: &str,
     endpoint_index: usize,
-) -> Result<reqwest::Response, String> {
-    let client = Client::builder()
-        .timeout(Duration::from_secs(300))
-        .build()
-        .map_err(|e| format!("创建 HTTP 客户端失败: {}", e))?;
+    continue_rounds: u32,
+    on_delta: &mut impl FnMut(KiroDelta),
+) -> Result<Value, UpstreamError> {
+    let mut result = call_kiro_api_streaming(client, profile, account, request, model, endpoint_index, on_delta).await?;
+    let mut rounds = 1;
+
+    while rounds <= continue_rounds && stopped_for_length(&result) {
+        println!("[KiroAPI] 输出因长度限制被截断，自动继续第 {}/{} 轮", rounds, continue_rounds);
+        let previous = result.get("message").and_then(|m| m.as_str()).unwrap_or_default();
+        let follow_up = continuation_request(request, previous);
+        match call_kiro_api_streaming(client, profile, account, &follow_up, model, endpoint_index, on_delta).await {
+            Ok(next) => merge_continuation(&mut result, next),
+            Err(e) => {
+                println!("[KiroAPI] 继续请求失败，返回已有内容: {}", e);
+                break;
+            }
+        }
+        rounds += 1;
+    }
 
-    let (base_url, origin, amz_target) = KIRO_ENDPOINTS
-        .get(endpoint_index)
-        .ok_or("无效的端点索引")?;
+    result["rounds"] = serde_json::json!(rounds);
+    Ok(result)
+}
 
-    // 流式端点暂不支持
-    return Err("暂不支持流式请求".to_string());
+/// 构建继续请求：在原对话后附上已输出的内容，要求从截断处接着写
+fn continuation_request(request: &KiroRequest, previous_output: &str) -> KiroRequest {
+    let mut request = request.clone();
+    let message = &mut request.conversation_state.current_message.user_input_message;
+    message.content = format!(
+        "{}\n\nassistant: {}\n\nuser: Your previous reply was cut off. Continue exactly where it stopped, without repeating any text that was already written.",
+        message.content, previous_output
+    );
+    request
 }
 
-/// 获取可用模型列表
-pub async fn fetch_kiro_models(account: &ProxyAccount) -> Result<Vec<Value>, String> {
-    let client = Client::builder()
-        .timeout(Duration::from_secs(30))
-        .build()
-        .map_err(|e| format!("创建 HTTP 客户端失败: {}", e))?;
+/// 把继续轮次的结果拼接到已有结果上
+fn merge_continuation(result: &mut Value, next: Value) {
+    for key in ["message", "reasoning"] {
+        let text = format!(
+            "{}{}",
+            result.get(key).and_then(|m| m.as_str()).unwrap_or_default(),
+            next.get(key).and_then(|m| m.as_str()).unwrap_or_default()
+        );
+        if !text.is_empty() {
+            result[key] = Value::String(text);
+        }
+    }
 
-    let region = account.region.as_deref().unwrap_or("us-east-1");
-    let base_url = if region.starts_with("eu-") {
-        "https://q.eu-central-1.amazonaws.com"
-    } else {
-        "https://q.us-east-1.amazonaws.com"
-    };
+    for key in ["inputTokens", "outputTokens"] {
+        let total = result.get(key).and_then(|t| t.as_u64()).unwrap_or(0)
+            + next.get(key).and_then(|t| t.as_u64()).unwrap_or(0);
+        result[key] = serde_json::json!(total);
+    }
+    if result.get("credits").is_some() || next.get("credits").is_some() {
+        let total = result.get("credits").and_then(|c| c.as_f64()).unwrap_or(0.0)
+            + next.get("credits").and_then(|c| c.as_f64()).unwrap_or(0.0);
+        result["credits"] = serde_json::json!(total);
+    }
 
-    let url = format!("{}/ListAvailableModels?origin=AI_EDITOR&maxResults=50", base_url);
+    match next.get("stopReason") {
+        Some(reason) => result["stopReason"] = reason.clone(),
+        None => {
+            if let Some(obj) = result.as_object_mut() {
+                obj.remove("stopReason");
+            }
+        }
+    }
+    if let Some(tool_uses) = next.get("toolUses").and_then(|t| t.as_array()) {
+        let mut merged = result.get("toolUses").and_then(|t| t.as_array()).cloned().unwrap_or_default();
+        merged.extend(tool_uses.iter().cloned());
+        result["toolUses"] = Value::Array(merged);
+    }
+}
 
-    let mut headers = reqwest::header::HeaderMap::new();
-    headers.insert(
-        "Authorization",
-        format!("Bearer {}", account.access_token)
-            .parse()
-            .map_err(|e| format!("无效的 Authorization 头: {}", e))?,
-    );
-    headers.insert(
-        "Content-Type",
-        "application/json"
-            .parse()
-            .map_err(|e| format!("无效的 Content-Type 头: {}", e))?,
-    );
-    headers.insert(
-        "Accept",
-        "application/json"
-            .parse()
-            .map_err(|e| format!("无效的 Accept 头: {}", e))?,
-    );
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// 构建一条 Event Stream 消息，CRC 不参与解析，填 0
+    fn frame(event_type: &str, payload: Value) -> Vec<u8> {
+        let mut headers = vec![11u8];
+        headers.extend_from_slice(b":event-type");
+        headers.push(7);
+        headers.extend_from_slice(&(event_type.len() as u16).to_be_bytes());
+        headers.extend_from_slice(event_type.as_bytes());
+        let payload = payload.to_string().into_bytes();
+
+        let total = 12 + headers.len() + payload.len() + 4;
+        let mut message = Vec::new();
+        message.extend_from_slice(&(total as u32).to_be_bytes());
+        message.extend_from_slice(&(headers.len() as u32).to_be_bytes());
+        message.extend_from_slice(&[0; 4]);
+        message.extend_from_slice(&headers);
+        message.extend_from_slice(&payload);
+        message.extend_from_slice(&[0; 4]);
+        message
+    }
 
-    let response = client
-        .get(&url)
-        .headers(headers)
-        .send()
-        .await
-        .map_err(|e| format!("请求失败: {}", e))?;
+    /// 按 chunk_size 分块喂给解析器，返回收到的增量和最终结果
+    fn read(frames: &[Vec<u8>], chunk_size: usize) -> (Vec<KiroDelta>, Value) {
+        let bytes = frames.concat();
+        let mut deltas = Vec::new();
+        let mut reader = EventStreamReader::default();
+        for chunk in bytes.chunks(chunk_size) {
+            reader.feed(chunk, &mut |delta| deltas.push(delta));
+        }
+        let result = reader.finish(&mut |delta| deltas.push(delta));
+        (deltas, result)
+    }
 
-    let status = response.status();
-    if !status.is_success() {
-        return Err(format!("API 返回错误: {}", status.as_u16()));
+    fn joined(deltas: &[KiroDelta], reasoning: bool) -> String {
+        deltas
+            .iter()
+            .filter_map(|delta| match delta {
+                KiroDelta::Text(text) if !reasoning => Some(text.as_str()),
+                KiroDelta::Reasoning(text) if reasoning => Some(text.as_str()),
+                _ => None,
+            })
+            .collect()
     }
 
-    let result: Value = response
-        .json()
-        .await
-        .map_err(|e| format!("解析响应失败: {}", e))?;
+    fn kiro_request(content: &str) -> KiroRequest {
+        serde_json::from_value(json!({
+            "conversationState": {
+                "chatTriggerType": "MANUAL",
+                "currentMessage": {
+                    "userInputMessage": {
+                        "content": content,
+                        "userIntent": "",
+                        "userInputMessageContext": {
+                            "editorState": {
+                                "document": {
+                                    "relativeFilePath": "",
+                                    "programmingLanguage": { "languageName": "" },
+                                    "text": ""
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }))
+        .unwrap()
+    }
 
-    let models = result
-        .get("models")
-        .and_then(|m| m.as_array())
-        .map(|arr| arr.to_vec())
-        .unwrap_or_default();
+    #[test]
+    fn parses_messages_split_across_chunks() {
+        let frames = vec![
+            frame("assistantResponseEvent", json!({ "content": "Hel" })),
+            frame("assistantResponseEvent", json!({ "content": "lo" })),
+            frame("toolUseEvent", json!({ "toolUseId": "t1", "name": "search", "input": "{\"q\":" })),
+            frame("toolUseEvent", json!({ "toolUseId": "t1", "input": "\"kiro\"}" })),
+            frame("meteringEvent", json!({ "unit": "credit", "usage": 0.5 })),
+            frame("messageMetadataEvent", json!({ "inputTokens": 12, "outputTokens": 3, "stopReason": "end_turn" })),
+        ];
+
+        for chunk_size in [1, 7, 4096] {
+            let (deltas, result) = read(&frames, chunk_size);
+            assert_eq!(deltas, vec![KiroDelta::Text("Hel".into()), KiroDelta::Text("lo".into())]);
+            assert_eq!(result["message"], "Hello");
+            assert_eq!(result["inputTokens"], 12);
+            assert_eq!(result["outputTokens"], 3);
+            assert_eq!(result["credits"], 0.5);
+            assert_eq!(result["stopReason"], "end_turn");
+            assert_eq!(result["toolUses"], json!([{ "toolUseId": "t1", "name": "search", "input": { "q": "kiro" } }]));
+        }
+    }
+
+    #[test]
+    fn reasoning_events_are_forwarded_with_their_signature() {
+        let frames = vec![
+            frame("reasoningContentEvent", json!({ "reasoningText": { "text": "plan" } })),
+            frame("reasoningContentEvent", json!({ "reasoningText": { "signature": "sig" } })),
+            frame("assistantResponseEvent", json!({ "content": "<thinking>kept</thinking>" })),
+        ];
+        let (deltas, result) = read(&frames, 5);
+        assert_eq!(
+            deltas,
+            vec![
+                KiroDelta::Reasoning("plan".into()),
+                KiroDelta::ReasoningSignature("sig".into()),
+                KiroDelta::Text("<thinking>kept</thinking>".into()),
+            ]
+        );
+        assert_eq!(result["reasoning"], "plan");
+        assert_eq!(result["reasoningSignature"], "sig");
+        assert_eq!(result["message"], "<thinking>kept</thinking>");
+    }
 
-    Ok(models)
+    #[test]
+    fn inline_thinking_is_split_while_streaming() {
+        let cases = [
+            (vec!["  <thin", "king>\nplan", " steps</thi", "nking>\n\n", "Answer"], "plan steps", "Answer"),
+            (vec!["<thinking>cut ", "off"], "cut off", ""),
+            (vec!["  <thin"], "", "  <thin"),
+            (vec!["<b>bold</b>"], "", "<b>bold</b>"),
+        ];
+        for (pieces, reasoning, text) in cases {
+            let frames: Vec<_> = pieces
+                .iter()
+                .map(|piece| frame("assistantResponseEvent", json!({ "content": piece })))
+                .collect();
+            let (deltas, result) = read(&frames, 3);
+            assert_eq!(joined(&deltas, true), reasoning, "{:?}", pieces);
+            assert_eq!(joined(&deltas, false), text, "{:?}", pieces);
+            assert_eq!(result["message"], text);
+            assert_eq!(result.get("reasoning").and_then(|r| r.as_str()).unwrap_or_default(), reasoning);
+        }
+    }
+
+    #[test]
+    fn stopped_for_length_matches_length_stop_reasons() {
+        for reason in ["max_tokens", "LENGTH", "max_output_tokens"] {
+            assert!(stopped_for_length(&json!({ "stopReason": reason })), "{}", reason);
+        }
+        assert!(!stopped_for_length(&json!({ "stopReason": "end_turn" })));
+        assert!(!stopped_for_length(&json!({ "message": "hi" })));
+    }
+
+    #[test]
+    fn continuation_request_appends_the_previous_output() {
+        let request = kiro_request("user: write a poem");
+        let follow_up = continuation_request(&request, "Roses are");
+        let content = &follow_up.conversation_state.current_message.user_input_message.content;
+        assert!(content.starts_with("user: write a poem\n\nassistant: Roses are\n\nuser: "));
+        assert!(content.contains("Continue exactly where it stopped"));
+        // 原请求不变
+        assert_eq!(request.conversation_state.current_message.user_input_message.content, "user: write a poem");
+    }
+
+    #[test]
+    fn merge_continuation_concatenates_text_and_sums_usage() {
+        let mut result = json!({
+            "message": "Roses are",
+            "reasoning": "rhyme",
+            "inputTokens": 10,
+            "outputTokens": 4,
+            "stopReason": "max_tokens",
+            "toolUses": [{ "toolUseId": "t1" }]
+        });
+        merge_continuation(&mut result, json!({
+            "message": " red",
+            "inputTokens": 15,
+            "outputTokens": 2,
+            "credits": 0.25,
+            "toolUses": [{ "toolUseId": "t2" }]
+        }));
+
+        assert_eq!(result["message"], "Roses are red");
+        assert_eq!(result["reasoning"], "rhyme");
+        assert_eq!(result["inputTokens"], 25);
+        assert_eq!(result["outputTokens"], 6);
+        assert_eq!(result["credits"], 0.25);
+        // 最后一轮正常结束，不再视为被截断
+        assert!(result.get("stopReason").is_none());
+        assert_eq!(result["toolUses"], json!([{ "toolUseId": "t1" }, { "toolUseId": "t2" }]));
+    }
 }
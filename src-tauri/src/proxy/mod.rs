@@ -3,9 +3,22 @@ pub mod types;
 pub mod server;
 pub mod account_pool;
 pub mod translator;
+pub mod token_estimator;
+pub mod structured_output;
+pub mod context_window;
+pub mod upstream_error;
+pub mod pipeline;
+pub mod streaming;
+pub mod api_key;
 pub mod kiro_api;
+pub mod model_catalog;
 pub mod routes;
+pub mod account_source;
+pub mod token_refresh;
+pub mod config_watcher;
+#[cfg(feature = "gui")]
 pub mod commands;
 
 pub use server::ProxyServer;
+#[cfg(feature = "gui")]
 pub use commands::ProxyState;
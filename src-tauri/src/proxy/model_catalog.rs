@@ -0,0 +1,342 @@
+// 模型目录 - 按账号缓存上游模型列表，合并账号池的模型并用于按模型选择账号
+use super::types::ProxyAccount;
+use crate::http_client::HttpClients;
+use crate::model_list::{list_available_models, ModelInfo};
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// 模型列表缓存有效期
+pub const MODEL_CACHE_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// 获取失败后的重试间隔，避免每个请求都重新请求模型列表
+const FAILURE_RETRY_INTERVAL: Duration = Duration::from_secs(60);
+
+/// 单个账号的模型列表缓存
+struct CachedModels {
+    models: Vec<ModelInfo>,
+    fetched_at: Instant,
+}
+
+/// 模型目录
+pub struct ModelCatalog {
+    entries: Mutex<HashMap<String, CachedModels>>,
+    /// 最近一次获取失败的时间和错误信息
+    failures: Mutex<HashMap<String, (Instant, String)>>,
+    ttl: Duration,
+    /// 是否有后台刷新正在进行
+    refreshing: AtomicBool,
+}
+
+impl ModelCatalog {
+    /// 创建模型目录
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            entries: Mutex::new(HashMap::new()),
+            failures: Mutex::new(HashMap::new()),
+            ttl,
+            refreshing: AtomicBool::new(false),
+        }
+    }
+
+    /// 账号的缓存是否仍在有效期内，最近获取失败的账号在重试间隔内也不再请求
+    fn is_fresh(&self, account_id: &str) -> bool {
+        let entries = self.entries.lock().unwrap();
+        let failures = self.failures.lock().unwrap();
+        entries
+            .get(account_id)
+            .is_some_and(|entry| entry.fetched_at.elapsed() < self.ttl)
+            || failures
+                .get(account_id)
+                .is_some_and(|(failed_at, _)| failed_at.elapsed() < FAILURE_RETRY_INTERVAL)
+    }
+
+    /// 刷新过期或缺失的账号模型列表，多个账号并发请求
+    ///
+    /// 返回是否全部命中缓存；获取失败的账号保留旧缓存（如果有）
+    pub async fn refresh(&self, http_clients: &HttpClients, accounts: &[ProxyAccount]) -> bool {
+        let stale: Vec<&ProxyAccount> = accounts.iter().filter(|acc| !self.is_fresh(&acc.id)).collect();
+        if stale.is_empty() {
+            return true;
+        }
+
+        let fetches = stale.iter().map(|account| async move {
+            let client = http_clients.for_proxy(account.outbound_proxy.as_ref())?;
+            let profile = http_clients.header_profile(account.header_profile.as_ref());
+            let region = account.region.as_deref().unwrap_or("us-east-1");
+            list_available_models(&client, &profile, &account.access_token, region).await
+        });
+        let results = futures::future::join_all(fetches).await;
+
+        let mut entries = self.entries.lock().unwrap();
+        let mut failures = self.failures.lock().unwrap();
+        for (account, result) in stale.into_iter().zip(results) {
+            let label = account.email.as_deref().unwrap_or(&account.id);
+            match result {
+                Ok(models) => {
+                    failures.remove(&account.id);
+                    println!("[ModelCatalog] 账号 {} 可用模型 {} 个", label, models.len());
+                    entries.insert(
+                        account.id.clone(),
+                        CachedModels {
+                            models,
+                            fetched_at: Instant::now(),
+                        },
+                    );
+                }
+                Err(e) => {
+                    println!("[ModelCatalog] 获取账号 {} 的模型列表失败: {}", label, e);
+                    failures.insert(account.id.clone(), (Instant::now(), e));
+                }
+            }
+        }
+        false
+    }
+
+    /// 请求前确保模型列表可用
+    ///
+    /// 所有账号都没有缓存时等待获取；只是过期时在后台刷新，本次请求继续使用旧缓存
+    pub async fn ensure_fresh(self: &Arc<Self>, http_clients: &HttpClients, accounts: &[ProxyAccount]) {
+        if accounts.iter().all(|acc| self.is_fresh(&acc.id)) {
+            return;
+        }
+
+        let cold = {
+            let entries = self.entries.lock().unwrap();
+            !accounts.iter().any(|acc| entries.contains_key(&acc.id))
+        };
+        if cold {
+            self.refresh(http_clients, accounts).await;
+            return;
+        }
+
+        if self.refreshing.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        let catalog = self.clone();
+        let http_clients = http_clients.clone();
+        let accounts = accounts.to_vec();
+        tokio::spawn(async move {
+            catalog.refresh(&http_clients, &accounts).await;
+            catalog.refreshing.store(false, Ordering::Release);
+        });
+    }
+
+    /// 合并多个账号的模型列表，按模型 ID 去重，任一账号提供的模型都会列出
+    ///
+    /// 返回模型列表和是否全部来自缓存；所有账号都没有模型列表时返回错误
+    pub async fn models(
+        &self,
+        http_clients: &HttpClients,
+        accounts: &[ProxyAccount],
+    ) -> Result<(Vec<ModelInfo>, bool), String> {
+        let from_cache = self.refresh(http_clients, accounts).await;
+
+        let entries = self.entries.lock().unwrap();
+        let mut seen = HashSet::new();
+        let mut models = Vec::new();
+        let mut has_entry = false;
+        for account in accounts {
+            let Some(entry) = entries.get(&account.id) else {
+                continue;
+            };
+            has_entry = true;
+            for model in &entry.models {
+                if seen.insert(model.id.as_str()) {
+                    models.push(model.clone());
+                }
+            }
+        }
+
+        if !has_entry {
+            let failures = self.failures.lock().unwrap();
+            let error = accounts.iter().find_map(|acc| failures.get(&acc.id)).map(|(_, e)| e.clone());
+            return Err(error.unwrap_or_else(|| "没有账号返回可用模型".to_string()));
+        }
+        Ok((models, from_cache))
+    }
+
+    /// 支持指定模型的账号 ID
+    ///
+    /// 没有任何账号的模型列表包含该模型时返回 None，由上游决定是否可用
+    pub fn accounts_supporting(&self, model: &str) -> Option<HashSet<String>> {
+        let entries = self.entries.lock().unwrap();
+        let account_ids: HashSet<String> = entries
+            .iter()
+            .filter(|(_, entry)| {
+                entry
+                    .models
+                    .iter()
+                    .any(|m| m.id == model)
+            })
+            .map(|(account_id, _)| account_id.clone())
+            .collect();
+
+        if account_ids.is_empty() {
+            None
+        } else {
+            Some(account_ids)
+        }
+    }
+
+    /// 模型的计费倍率，取缓存中第一个提供该模型的账号
+    pub fn rate_multiplier(&self, model: &str) -> Option<f64> {
+        let entries = self.entries.lock().unwrap();
+        entries
+            .values()
+            .flat_map(|entry| &entry.models)
+            .find(|m| m.id == model)
+            .and_then(|m| m.rate_multiplier)
+    }
+
+    /// 模型的输入 token 上限，取缓存中第一个提供该模型的账号
+    pub fn max_input_tokens(&self, model: &str) -> Option<u64> {
+        let entries = self.entries.lock().unwrap();
+        entries
+            .values()
+            .flat_map(|entry| &entry.models)
+            .find(|m| m.id == model)
+            .and_then(|m| m.max_input_tokens)
+            .and_then(|limit| u64::try_from(limit).ok())
+            .filter(|&limit| limit > 0)
+    }
+
+    /// 移除账号的缓存
+    pub fn remove(&self, account_id: &str) {
+        self.entries.lock().unwrap().remove(account_id);
+        self.failures.lock().unwrap().remove(account_id);
+    }
+
+    /// 清空所有缓存
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+        self.failures.lock().unwrap().clear();
+    }
+}
+
+impl Default for ModelCatalog {
+    fn default() -> Self {
+        Self::new(MODEL_CACHE_TTL)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn model(id: &str, rate_multiplier: Option<f64>, max_input_tokens: Option<i64>) -> ModelInfo {
+        ModelInfo {
+            id: id.to_string(),
+            name: id.to_string(),
+            description: String::new(),
+            input_types: None,
+            max_input_tokens,
+            max_output_tokens: None,
+            rate_multiplier,
+            rate_unit: None,
+        }
+    }
+
+    /// 写入账号的缓存，age 为缓存已存在的时长
+    fn cache(catalog: &ModelCatalog, account_id: &str, models: Vec<ModelInfo>, age: Duration) {
+        catalog.entries.lock().unwrap().insert(
+            account_id.to_string(),
+            CachedModels {
+                models,
+                fetched_at: Instant::now() - age,
+            },
+        );
+    }
+
+    /// 出站代理无效的账号，获取模型列表时在本地失败，不会访问网络
+    fn failing_account(id: &str) -> ProxyAccount {
+        serde_json::from_value(json!({
+            "id": id,
+            "accessToken": "token",
+            "outboundProxy": { "url": "ftp://127.0.0.1:1" }
+        }))
+        .unwrap()
+    }
+
+    fn http_clients() -> HttpClients {
+        HttpClients::new(Default::default()).unwrap()
+    }
+
+    #[test]
+    fn finds_accounts_supporting_a_model() {
+        let catalog = ModelCatalog::default();
+        cache(&catalog, "a", vec![model("sonnet", None, None)], Duration::ZERO);
+        cache(&catalog, "b", vec![model("sonnet", None, None), model("opus", None, None)], Duration::ZERO);
+
+        let sonnet = catalog.accounts_supporting("sonnet").unwrap();
+        assert_eq!(sonnet, HashSet::from(["a".to_string(), "b".to_string()]));
+        assert_eq!(catalog.accounts_supporting("opus").unwrap(), HashSet::from(["b".to_string()]));
+        // 没有账号提供时不限制账号
+        assert_eq!(catalog.accounts_supporting("haiku"), None);
+    }
+
+    #[test]
+    fn reads_rate_multiplier_and_input_limit_from_the_cache() {
+        let catalog = ModelCatalog::default();
+        cache(
+            &catalog,
+            "a",
+            vec![model("sonnet", Some(1.3), Some(200_000)), model("auto", None, Some(0))],
+            Duration::ZERO,
+        );
+
+        assert_eq!(catalog.rate_multiplier("sonnet"), Some(1.3));
+        assert_eq!(catalog.max_input_tokens("sonnet"), Some(200_000));
+        assert_eq!(catalog.rate_multiplier("auto"), None);
+        // 0 表示上游未给出上限
+        assert_eq!(catalog.max_input_tokens("auto"), None);
+        assert_eq!(catalog.rate_multiplier("missing"), None);
+    }
+
+    #[test]
+    fn entries_expire_after_the_ttl() {
+        let catalog = ModelCatalog::new(Duration::from_secs(60));
+        cache(&catalog, "fresh", Vec::new(), Duration::from_secs(10));
+        cache(&catalog, "stale", Vec::new(), Duration::from_secs(61));
+        assert!(catalog.is_fresh("fresh"));
+        assert!(!catalog.is_fresh("stale"));
+        assert!(!catalog.is_fresh("missing"));
+
+        // 最近失败过的账号在重试间隔内也视为无需刷新
+        catalog.failures.lock().unwrap().insert("missing".to_string(), (Instant::now(), "error".to_string()));
+        assert!(catalog.is_fresh("missing"));
+    }
+
+    #[tokio::test]
+    async fn cold_cache_is_fetched_before_the_request() {
+        let catalog = Arc::new(ModelCatalog::default());
+        catalog.ensure_fresh(&http_clients(), &[failing_account("a")]).await;
+        assert!(catalog.failures.lock().unwrap().contains_key("a"));
+
+        let error = catalog.models(&http_clients(), &[failing_account("a")]).await.unwrap_err();
+        assert!(error.contains("不支持的代理协议"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn stale_cache_is_refreshed_in_the_background() {
+        let catalog = Arc::new(ModelCatalog::new(Duration::from_secs(60)));
+        cache(&catalog, "a", vec![model("sonnet", None, None)], Duration::from_secs(120));
+
+        catalog.ensure_fresh(&http_clients(), &[failing_account("a")]).await;
+        // 本次请求继续使用旧缓存
+        assert!(catalog.accounts_supporting("sonnet").is_some());
+
+        for _ in 0..100 {
+            if !catalog.refreshing.load(Ordering::Acquire) {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert!(!catalog.refreshing.load(Ordering::Acquire));
+        // 刷新失败时保留旧缓存
+        assert!(catalog.failures.lock().unwrap().contains_key("a"));
+        assert!(catalog.accounts_supporting("sonnet").is_some());
+    }
+}
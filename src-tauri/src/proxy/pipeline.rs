@@ -0,0 +1,651 @@
+// 请求流水线 - 各接口共用的认证、模型解析、账号选择、上游调用（含重试）和用量记录
+//
+// 路由只负责解析请求和转换响应格式，其余步骤都在这里完成，新接口接入后自动获得同样的行为
+use super::account_pool::AccountPool;
+use super::api_key;
+use super::context_window::{ContextWindow, OverflowPolicy};
+use super::kiro_api::{call_kiro_api, call_kiro_api_with_continuation, KiroDelta};
+use super::token_estimator::{estimate_text_tokens, fill_missing_credits, fill_missing_usage};
+use super::translator::strip_reasoning;
+use super::types::*;
+use super::upstream_error::{UpstreamError, UpstreamErrorKind};
+use crate::http_client::HttpClients;
+use serde_json::{json, Value};
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use tokio::sync::RwLock;
+use warp::http::StatusCode;
+use warp::reply::Response;
+use warp::Reply;
+
+/// 请求日志最多保留的条数
+const MAX_RECENT_LOGS: usize = 1000;
+
+/// 接口格式，决定接口开关和错误体的格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApiFormat {
+    OpenAI,
+    Claude,
+    Gemini,
+}
+
+impl ApiFormat {
+    /// 日志标签和接口名称
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::OpenAI => "OpenAI",
+            Self::Claude => "Claude",
+            Self::Gemini => "Gemini",
+        }
+    }
+
+    /// 接口是否启用
+    fn enabled(self, config: &ProxyConfig) -> bool {
+        match self {
+            Self::OpenAI => config.enable_openai,
+            Self::Claude => config.enable_claude,
+            Self::Gemini => config.enable_gemini,
+        }
+    }
+
+    /// 该格式的错误响应，错误类型按状态码确定
+    pub fn error(self, message: impl Into<String>, code: &str, status: StatusCode) -> Response {
+        let message = message.into();
+        let body = match self {
+            Self::OpenAI => {
+                let error_type = match status {
+                    StatusCode::UNAUTHORIZED => "authentication_error",
+                    StatusCode::FORBIDDEN => "permission_error",
+                    StatusCode::TOO_MANY_REQUESTS if code == "insufficient_quota" => "insufficient_quota",
+                    StatusCode::TOO_MANY_REQUESTS => "rate_limit_exceeded",
+                    status if status.is_server_error() => "server_error",
+                    _ => "invalid_request_error",
+                };
+                json!({
+                    "error": {
+                        "message": message,
+                        "type": error_type,
+                        "param": null,
+                        "code": code
+                    }
+                })
+            }
+            Self::Claude => {
+                let error_type = match status {
+                    StatusCode::UNAUTHORIZED => "authentication_error",
+                    StatusCode::FORBIDDEN => "permission_error",
+                    StatusCode::NOT_FOUND => "not_found_error",
+                    StatusCode::TOO_MANY_REQUESTS => "rate_limit_error",
+                    status if status.is_server_error() => "api_error",
+                    _ => "invalid_request_error",
+                };
+                json!({
+                    "type": "error",
+                    "error": {
+                        "type": error_type,
+                        "message": message
+                    }
+                })
+            }
+            Self::Gemini => {
+                let status_name = match status {
+                    StatusCode::BAD_REQUEST => "INVALID_ARGUMENT",
+                    StatusCode::UNAUTHORIZED => "UNAUTHENTICATED",
+                    StatusCode::FORBIDDEN => "PERMISSION_DENIED",
+                    StatusCode::NOT_FOUND => "NOT_FOUND",
+                    StatusCode::TOO_MANY_REQUESTS => "RESOURCE_EXHAUSTED",
+                    StatusCode::SERVICE_UNAVAILABLE => "UNAVAILABLE",
+                    StatusCode::GATEWAY_TIMEOUT => "DEADLINE_EXCEEDED",
+                    _ => "INTERNAL",
+                };
+                json!({
+                    "error": {
+                        "code": status.as_u16(),
+                        "message": message,
+                        "status": status_name
+                    }
+                })
+            }
+        };
+        warp::reply::with_status(warp::reply::json(&body), status).into_response()
+    }
+
+    /// 上游错误的响应，限流和过载时带上 Retry-After
+    pub fn upstream_error(self, error: &UpstreamError) -> Response {
+        let (status, body) = match self {
+            Self::OpenAI => error.openai_body(),
+            Self::Claude => error.anthropic_body(),
+            Self::Gemini => {
+                let status = StatusCode::from_u16(error.status).unwrap_or(StatusCode::BAD_GATEWAY);
+                return with_retry_after(self.error(error.message.clone(), "", status), error.retry_after);
+            }
+        };
+        let status = StatusCode::from_u16(status).unwrap_or(StatusCode::BAD_GATEWAY);
+        with_retry_after(warp::reply::with_status(warp::reply::json(&body), status).into_response(), error.retry_after)
+    }
+}
+
+/// 设置 Retry-After 头（秒）
+fn with_retry_after(mut response: Response, retry_after: Option<u64>) -> Response {
+    if let Some(seconds) = retry_after {
+        response.headers_mut().insert(warp::http::header::RETRY_AFTER, seconds.into());
+    }
+    response
+}
+
+/// 校验 API Key，返回匹配的 Key ID，未配置 Key 时放行并返回 None；不通过时返回错误信息和错误码
+pub fn check_api_key(config: &ProxyConfig, provided_key: Option<&str>) -> Result<Option<String>, (&'static str, &'static str)> {
+    let Some(api_keys) = config.api_keys.as_ref().filter(|keys| !keys.is_empty()) else {
+        return Ok(None);
+    };
+    let provided_key = provided_key.ok_or(("缺少 Authorization 头", "missing_authorization"))?;
+    api_keys
+        .iter()
+        .find(|k| k.enabled && api_key::verify(k, provided_key))
+        .map(|k| Some(k.id.clone()))
+        .ok_or(("无效的 API Key", "invalid_api_key"))
+}
+
+/// 按模型映射规则解析实际请求的模型
+///
+/// 只看启用且适用于当前 API Key 的规则，priority 小的优先；replace 和 alias 取第一个目标模型，
+/// loadbalance 按权重随机选择；没有匹配的规则时原样返回
+fn resolve_model(rules: &[ModelMappingRule], model: &str, api_key_id: Option<&str>) -> String {
+    let mut matched: Vec<&ModelMappingRule> = rules
+        .iter()
+        .filter(|rule| rule.enabled && rule.source_model == model && !rule.target_models.is_empty())
+        .filter(|rule| match rule.api_key_ids.as_deref() {
+            Some(ids) if !ids.is_empty() => api_key_id.is_some_and(|id| ids.iter().any(|allowed| allowed == id)),
+            _ => true,
+        })
+        .collect();
+    matched.sort_by_key(|rule| rule.priority);
+    let Some(rule) = matched.first() else {
+        return model.to_string();
+    };
+
+    let target = match rule.rule_type.as_str() {
+        "loadbalance" => {
+            let weights: Vec<u64> = (0..rule.target_models.len())
+                .map(|i| rule.weights.as_ref().and_then(|w| w.get(i)).map_or(1, |&w| u64::from(w)))
+                .collect();
+            let total: u64 = weights.iter().sum();
+            if total == 0 {
+                &rule.target_models[0]
+            } else {
+                let mut point = (uuid::Uuid::new_v4().as_u128() % total as u128) as u64;
+                let index = weights
+                    .iter()
+                    .position(|&w| {
+                        if point < w {
+                            return true;
+                        }
+                        point -= w;
+                        false
+                    })
+                    .unwrap_or(0);
+                &rule.target_models[index]
+            }
+        }
+        _ => &rule.target_models[0],
+    };
+    println!("[Pipeline] 模型映射 \"{}\": {} -> {}", rule.name, model, target);
+    target.clone()
+}
+
+/// 换一个账号重试可能成功的错误
+fn is_retryable(error: &UpstreamError, switch_on_quota: bool) -> bool {
+    match error.kind {
+        UpstreamErrorKind::RateLimited
+        | UpstreamErrorKind::Overloaded
+        | UpstreamErrorKind::Timeout
+        | UpstreamErrorKind::ServerError
+        | UpstreamErrorKind::Authentication
+        | UpstreamErrorKind::PermissionDenied => true,
+        UpstreamErrorKind::QuotaExhausted => switch_on_quota,
+        UpstreamErrorKind::InvalidRequest
+        | UpstreamErrorKind::ContextLengthExceeded
+        | UpstreamErrorKind::RequestTooLarge
+        | UpstreamErrorKind::NotFound => false,
+    }
+}
+
+/// 一次请求在流水线中的信息
+pub struct PipelineRequest<'a> {
+    pub format: ApiFormat,
+    /// 写入请求日志的路径
+    pub path: &'a str,
+    /// 客户端请求的模型
+    pub model: &'a str,
+    pub api_key_id: Option<&'a str>,
+    /// 上游未返回用量时使用的输入 token 估算
+    pub input_tokens: u64,
+}
+
+/// 上游调用成功的结果，用量已补全并记录
+pub struct Completion {
+    pub kiro_response: Value,
+    pub kiro_request: KiroRequest,
+    pub account: ProxyAccount,
+    /// 映射后实际请求上游的模型
+    pub model: String,
+}
+
+/// 一次请求的统计维度
+struct UsageContext<'a> {
+    path: &'a str,
+    model: &'a str,
+    account_id: &'a str,
+    api_key_id: Option<&'a str>,
+}
+
+/// 累加一次请求到用量汇总
+fn add_to_rollup(rollup: &mut UsageRollup, failed: bool, input_tokens: u64, output_tokens: u64, credits: f64, estimated: bool) {
+    rollup.requests += 1;
+    if failed {
+        rollup.failed_requests += 1;
+    }
+    rollup.input_tokens += input_tokens;
+    rollup.output_tokens += output_tokens;
+    rollup.credits += credits;
+    if estimated {
+        rollup.estimated_credits += credits;
+    }
+}
+
+/// 各接口共享的服务状态
+#[derive(Clone)]
+pub struct ProxyContext {
+    pub pool: Arc<AccountPool>,
+    pub stats: Arc<Mutex<ProxyStats>>,
+    pub session_stats: Arc<Mutex<SessionStats>>,
+    pub recent_logs: Arc<Mutex<Vec<RequestLog>>>,
+    pub config: Arc<RwLock<ProxyConfig>>,
+    pub http_clients: HttpClients,
+}
+
+impl ProxyContext {
+    /// 检查接口开关和 API Key，返回匹配的 Key ID；不通过时返回该格式的错误响应
+    pub async fn authorize(&self, format: ApiFormat, provided_key: Option<&str>) -> Result<Option<String>, Response> {
+        let config = self.config.read().await;
+        if !format.enabled(&config) {
+            return Err(format.error(
+                format!("{} API 未启用", format.name()),
+                "endpoint_disabled",
+                StatusCode::FORBIDDEN,
+            ));
+        }
+        check_api_key(&config, provided_key.map(|key| key.trim()))
+            .map_err(|(message, code)| format.error(message, code, StatusCode::UNAUTHORIZED))
+    }
+
+    /// 执行请求：解析模型映射 → 选择账号 → 转换请求 → 调用上游（失败时换账号重试）→ 记录用量
+    ///
+    /// translate 按上下文窗口把客户端请求转换为 Kiro 请求；返回的错误已是该格式的响应，可直接返回给客户端
+    pub async fn execute(
+        &self,
+        request: &PipelineRequest<'_>,
+        translate: impl FnOnce(&ContextWindow) -> Result<KiroRequest, String> + Send,
+    ) -> Result<Completion, Response> {
+        self.execute_streaming(request, translate, |_| {}).await
+    }
+
+    /// 同 execute，上游的增量输出到达时交给 on_delta（开启 strip_thinking 时不转发思考过程）
+    ///
+    /// 已经转发过输出的请求失败后不再换账号重试，否则客户端会收到重复的内容
+    pub async fn execute_streaming(
+        &self,
+        request: &PipelineRequest<'_>,
+        translate: impl FnOnce(&ContextWindow) -> Result<KiroRequest, String> + Send,
+        mut on_delta: impl FnMut(KiroDelta) + Send,
+    ) -> Result<Completion, Response> {
+        let format = request.format;
+        let (model, policy, continue_rounds, max_retries, switch_on_quota, strip_thinking) = {
+            let config = self.config.read().await;
+            (
+                resolve_model(config.model_mappings.as_deref().unwrap_or_default(), request.model, request.api_key_id),
+                OverflowPolicy::from_config(&config.context_overflow_policy),
+                config.auto_continue_rounds.unwrap_or(0),
+                config.max_retries.unwrap_or(0),
+                config.auto_switch_on_quota_exhausted.unwrap_or(true),
+                config.strip_thinking,
+            )
+        };
+
+        // 只在模型列表包含该模型的账号中轮询；没有账号的模型列表包含该模型时（例如获取失败）不做限制
+        let catalog = self.pool.model_catalog();
+        catalog.ensure_fresh(&self.http_clients, &self.pool.get_available_accounts()).await;
+        let supporting = catalog.accounts_supporting(&model);
+
+        // 转换为 Kiro 格式，超出上下文窗口时按配置裁剪对话
+        let window = ContextWindow {
+            max_input_tokens: catalog.max_input_tokens(&model),
+            policy,
+        };
+        let kiro_request = translate(&window)
+            .map_err(|e| format.error(e, "context_length_exceeded", StatusCode::BAD_REQUEST))?;
+
+        let mut tried = HashSet::new();
+        let mut attempt = 0;
+        // 重试中最近一次失败的错误和账号，重试次数未用完但账号已轮询一遍时返回给客户端
+        let mut last_failure: Option<(UpstreamError, String)> = None;
+        loop {
+            let account = self.pool.get_next_account_where(|acc| {
+                !tried.contains(&acc.id) && supporting.as_ref().is_none_or(|ids| ids.contains(&acc.id))
+            });
+            let Some(account) = account else {
+                let Some((error, account_id)) = last_failure else {
+                    return Err(format.error("没有可用账号", "no_accounts", StatusCode::SERVICE_UNAVAILABLE));
+                };
+                let usage = UsageContext {
+                    path: request.path,
+                    model: &model,
+                    account_id: &account_id,
+                    api_key_id: request.api_key_id,
+                };
+                self.record_result(&usage, Err(&error));
+                return Err(format.upstream_error(&error));
+            };
+            tried.insert(account.id.clone());
+
+            // 调用 Kiro API，输出被截断时按配置自动继续
+            let profile = self.http_clients.header_profile(account.header_profile.as_ref());
+            let mut streamed = false;
+            let mut forward = |delta: KiroDelta| {
+                if strip_thinking && !matches!(delta, KiroDelta::Text(_)) {
+                    return;
+                }
+                streamed = true;
+                on_delta(delta);
+            };
+            let result = match self.http_clients.for_proxy(account.outbound_proxy.as_ref()) {
+                Ok(client) => {
+                    call_kiro_api_with_continuation(&client, &profile, &account, &kiro_request, &model, 0, continue_rounds, &mut forward).await
+                }
+                Err(e) => Err(e.into()),
+            };
+
+            let usage = UsageContext {
+                path: request.path,
+                model: &model,
+                account_id: &account.id,
+                api_key_id: request.api_key_id,
+            };
+            match result {
+                Ok(mut kiro_response) => {
+                    self.pool.record_usage(&account.id);
+                    fill_missing_usage(&mut kiro_response, request.input_tokens);
+                    fill_missing_credits(&mut kiro_response, catalog.rate_multiplier(&model));
+                    if strip_thinking {
+                        strip_reasoning(&mut kiro_response);
+                    }
+                    self.record_result(&usage, Ok(&kiro_response));
+                    return Ok(Completion {
+                        kiro_response,
+                        kiro_request,
+                        account,
+                        model,
+                    });
+                }
+                Err(e) => {
+                    let is_quota = e.kind == UpstreamErrorKind::QuotaExhausted;
+                    self.pool.record_error(&account.id, is_quota && switch_on_quota);
+
+                    if !streamed && attempt < max_retries && is_retryable(&e, switch_on_quota) {
+                        attempt += 1;
+                        println!(
+                            "[{}] 账号 {} 调用失败，换账号重试 {}/{}: {}",
+                            format.name(),
+                            account.email.as_deref().unwrap_or(&account.id),
+                            attempt,
+                            max_retries,
+                            e
+                        );
+                        last_failure = Some((e, account.id.clone()));
+                        continue;
+                    }
+                    self.record_result(&usage, Err(&e));
+                    return Err(format.upstream_error(&e));
+                }
+            }
+        }
+    }
+
+    /// 用成功请求的账号再调用一次上游（例如结构化输出校验失败后的重新请求），同样补全并记录用量
+    pub async fn call_again(
+        &self,
+        request: &PipelineRequest<'_>,
+        completion: &Completion,
+        kiro_request: &KiroRequest,
+    ) -> Result<Value, UpstreamError> {
+        let strip_thinking = self.config.read().await.strip_thinking;
+        let account = &completion.account;
+        let profile = self.http_clients.header_profile(account.header_profile.as_ref());
+        let result = match self.http_clients.for_proxy(account.outbound_proxy.as_ref()) {
+            Ok(client) => call_kiro_api(&client, &profile, account, kiro_request, &completion.model, 0).await,
+            Err(e) => Err(e.into()),
+        };
+
+        let usage = UsageContext {
+            path: request.path,
+            model: &completion.model,
+            account_id: &account.id,
+            api_key_id: request.api_key_id,
+        };
+        match result {
+            Ok(mut kiro_response) => {
+                self.pool.record_usage(&account.id);
+                fill_missing_usage(
+                    &mut kiro_response,
+                    estimate_text_tokens(&kiro_request.conversation_state.current_message.user_input_message.content),
+                );
+                fill_missing_credits(&mut kiro_response, self.pool.model_catalog().rate_multiplier(&completion.model));
+                if strip_thinking {
+                    strip_reasoning(&mut kiro_response);
+                }
+                self.record_result(&usage, Ok(&kiro_response));
+                Ok(kiro_response)
+            }
+            Err(e) => {
+                self.record_result(&usage, Err(&e));
+                Err(e)
+            }
+        }
+    }
+
+    /// 记录一次上游调用的结果：更新统计和汇总，并写入请求日志
+    ///
+    /// 成功的响应需先经过 `fill_missing_credits`，由 `creditsEstimated` 区分估算和上游计量的 credits
+    fn record_result(&self, context: &UsageContext, result: Result<&Value, &UpstreamError>) {
+        let mut log = RequestLog {
+            time: chrono::Utc::now().to_rfc3339(),
+            path: context.path.to_string(),
+            model: Some(context.model.to_string()),
+            status: 200,
+            tokens: None,
+            input_tokens: None,
+            output_tokens: None,
+            credits: None,
+            credits_estimated: None,
+            error: None,
+            account_id: Some(context.account_id.to_string()),
+            api_key_id: context.api_key_id.map(|id| id.to_string()),
+        };
+
+        match result {
+            Ok(kiro_response) => {
+                let input_tokens = kiro_response.get("inputTokens").and_then(|t| t.as_u64()).unwrap_or(0);
+                let output_tokens = kiro_response.get("outputTokens").and_then(|t| t.as_u64()).unwrap_or(0);
+                // 模型倍率未知时没有估算值，日志中 credits 留空
+                let credits = kiro_response.get("credits").and_then(|c| c.as_f64());
+                let estimated = kiro_response.get("creditsEstimated").and_then(|e| e.as_bool()).unwrap_or(false);
+
+                self.update_stats(context, true, input_tokens, output_tokens, credits.unwrap_or(0.0), estimated);
+                log.tokens = Some(input_tokens + output_tokens);
+                log.input_tokens = Some(input_tokens);
+                log.output_tokens = Some(output_tokens);
+                log.credits = credits;
+                log.credits_estimated = Some(estimated);
+            }
+            Err(e) => {
+                self.update_stats(context, false, 0, 0, 0.0, false);
+                log.status = e.status;
+                log.error = Some(e.message.clone());
+            }
+        }
+
+        self.record_request(log);
+    }
+
+    /// 记录请求
+    fn record_request(&self, log: RequestLog) {
+        let mut logs = self.recent_logs.lock().unwrap();
+        logs.push(log);
+        if logs.len() > MAX_RECENT_LOGS {
+            logs.drain(0..100);
+        }
+    }
+
+    /// 更新统计和按模型、账号、API Key 的汇总
+    fn update_stats(
+        &self,
+        context: &UsageContext,
+        success: bool,
+        input_tokens: u64,
+        output_tokens: u64,
+        credits: f64,
+        estimated: bool,
+    ) {
+        let mut stats = self.stats.lock().unwrap();
+        let mut session_stats = self.session_stats.lock().unwrap();
+
+        if success {
+            stats.success_requests += 1;
+            session_stats.success_requests += 1;
+        } else {
+            stats.failed_requests += 1;
+            session_stats.failed_requests += 1;
+        }
+
+        stats.total_requests += 1;
+        stats.input_tokens += input_tokens;
+        stats.output_tokens += output_tokens;
+        stats.total_tokens += input_tokens + output_tokens;
+        stats.total_credits += credits;
+        if estimated {
+            stats.estimated_credits += credits;
+        }
+
+        session_stats.total_requests += 1;
+
+        let failed = !success;
+        add_to_rollup(stats.by_model.entry(context.model.to_string()).or_default(), failed, input_tokens, output_tokens, credits, estimated);
+        add_to_rollup(stats.by_account.entry(context.account_id.to_string()).or_default(), failed, input_tokens, output_tokens, credits, estimated);
+        if let Some(api_key_id) = context.api_key_id {
+            add_to_rollup(stats.by_api_key.entry(api_key_id.to_string()).or_default(), failed, input_tokens, output_tokens, credits, estimated);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn body_of(response: Response) -> (u16, Value) {
+        let status = response.status().as_u16();
+        let bytes = warp::hyper::body::to_bytes(response.into_body()).await.unwrap();
+        (status, serde_json::from_slice(&bytes).unwrap())
+    }
+
+    #[tokio::test]
+    async fn openai_errors_use_the_matching_error_type() {
+        let cases = [
+            (StatusCode::UNAUTHORIZED, "invalid_api_key", "authentication_error"),
+            (StatusCode::FORBIDDEN, "forbidden", "permission_error"),
+            (StatusCode::TOO_MANY_REQUESTS, "rate_limit_exceeded", "rate_limit_exceeded"),
+            (StatusCode::TOO_MANY_REQUESTS, "insufficient_quota", "insufficient_quota"),
+            (StatusCode::BAD_REQUEST, "invalid_format", "invalid_request_error"),
+            (StatusCode::SERVICE_UNAVAILABLE, "no_accounts", "server_error"),
+        ];
+        for (status, code, error_type) in cases {
+            let (actual, body) = body_of(ApiFormat::OpenAI.error("message", code, status)).await;
+            assert_eq!(actual, status.as_u16());
+            assert_eq!(body["error"]["type"], error_type, "{}", code);
+            assert_eq!(body["error"]["code"], code);
+        }
+    }
+
+    /// 出站代理无效的账号，调用时在本地失败，不会访问网络
+    fn failing_account(id: &str) -> ProxyAccount {
+        serde_json::from_value(json!({
+            "id": id,
+            "accessToken": "token",
+            "isAvailable": true,
+            "outboundProxy": { "url": "ftp://127.0.0.1:1" }
+        }))
+        .unwrap()
+    }
+
+    fn context(accounts: Vec<ProxyAccount>, max_retries: u32) -> ProxyContext {
+        let pool = AccountPool::new();
+        pool.add_accounts(accounts);
+        let config = ProxyConfig {
+            max_retries: Some(max_retries),
+            ..ProxyConfig::default()
+        };
+        ProxyContext {
+            pool: Arc::new(pool),
+            stats: Arc::default(),
+            session_stats: Arc::default(),
+            recent_logs: Arc::default(),
+            config: Arc::new(RwLock::new(config)),
+            http_clients: HttpClients::new(Default::default()).unwrap(),
+        }
+    }
+
+    #[tokio::test]
+    async fn returns_the_last_upstream_error_when_accounts_run_out_before_retries() {
+        let context = context(vec![failing_account("a"), failing_account("b")], 3);
+        let request = PipelineRequest {
+            format: ApiFormat::OpenAI,
+            path: "/v1/chat/completions",
+            model: "claude-sonnet-4",
+            api_key_id: None,
+            input_tokens: 1,
+        };
+        let claude_request: ClaudeRequest = serde_json::from_value(json!({
+            "model": "claude-sonnet-4",
+            "max_tokens": 16,
+            "messages": [{ "role": "user", "content": "hi" }]
+        }))
+        .unwrap();
+        let result = context
+            .execute(&request, |window| super::super::translator::claude_to_kiro(&claude_request, window))
+            .await;
+
+        let (status, body) = body_of(result.err().unwrap()).await;
+        assert_eq!(status, 500);
+        assert_eq!(body["error"]["code"], "api_call_failed");
+        assert!(body["error"]["message"].as_str().unwrap().contains("不支持的代理协议"));
+
+        let logs = context.recent_logs.lock().unwrap();
+        assert_eq!(logs.len(), 1);
+        assert_eq!(logs[0].status, 500);
+        assert_eq!(context.stats.lock().unwrap().failed_requests, 1);
+    }
+
+    #[tokio::test]
+    async fn upstream_errors_carry_retry_after() {
+        let error = UpstreamError::from_response(429, Some("ThrottlingException"), "{}", Some(12));
+        let response = ApiFormat::Claude.upstream_error(&error);
+        assert_eq!(response.headers()[warp::http::header::RETRY_AFTER], "12");
+        let (status, body) = body_of(response).await;
+        assert_eq!(status, 429);
+        assert_eq!(body["error"]["type"], "rate_limit_error");
+
+        let response = ApiFormat::Gemini.upstream_error(&error);
+        assert_eq!(response.headers()[warp::http::header::RETRY_AFTER], "12");
+        let (_, body) = body_of(response).await;
+        assert_eq!(body["error"]["status"], "RESOURCE_EXHAUSTED");
+    }
+}
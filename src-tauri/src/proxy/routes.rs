@@ -1,10 +1,24 @@
 // HTTP 路由处理
-use super::account_pool::AccountPool;
-use super::kiro_api::{call_kiro_api, fetch_kiro_models};
-use super::translator::{claude_to_kiro, kiro_to_claude_response, kiro_to_openai_response, openai_to_kiro};
+//
+// 各接口只负责解析请求和转换响应格式，认证、账号选择、上游调用和用量记录由 pipeline 统一完成
+use super::pipeline::{check_api_key, ApiFormat, Completion, PipelineRequest, ProxyContext};
+use super::streaming::{
+    stream_response, ChatStreamEncoder, ClaudeStreamEncoder, CompletionStreamEncoder, DeltaSink, GeminiStreamEncoder,
+    ResponsesStreamEncoder,
+};
+use super::structured_output::ResponseFormat;
+use super::token_estimator::{
+    add_usage, estimate_claude_input_tokens, estimate_completion_input_tokens, estimate_gemini_input_tokens,
+    estimate_openai_input_tokens, estimate_responses_input_tokens,
+};
+use super::translator::{
+    claude_to_kiro, completion_prompt, completions_to_kiro,
+    gemini_to_kiro, kiro_to_claude_response, kiro_to_completion_response,
+    kiro_model_to_anthropic, kiro_model_to_openai, kiro_to_gemini_response, kiro_to_openai_response, kiro_to_responses_response, openai_to_kiro, responses_to_kiro,
+};
 use super::types::*;
-use std::sync::{Arc, Mutex};
-use warp::Filter;
+use warp::http::StatusCode;
+use warp::{Filter, Reply};
 
 /// 创建健康检查路由
 pub fn health_route() -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
@@ -12,156 +26,96 @@ pub fn health_route() -> impl Filter<Extract = impl warp::Reply, Error = warp::R
         .map(|| warp::reply::json(&serde_json::json!({"status": "ok"})))
 }
 
-/// 创建模型列表路由
-pub fn models_route(
-    account_pool: Arc<AccountPool>,
-    config: Arc<tokio::sync::RwLock<ProxyConfig>>,
-) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
-    warp::path!("v1" / "models")
+/// 提取 Bearer Token
+fn bearer_token(auth_header: Option<&str>) -> Option<&str> {
+    auth_header.and_then(|h| h.strip_prefix("Bearer "))
+}
+
+/// 创建模型列表路由：`GET /v1/models` 和 `GET /v1/models/{id}`
+pub fn models_route(context: ProxyContext) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    let list = warp::path!("v1" / "models").map(|| None);
+    let single = warp::path!("v1" / "models" / String).map(Some);
+
+    list.or(single)
+        .unify()
         .and(warp::get())
         .and(warp::header::optional::<String>("authorization"))
-        .and(warp::any().map(move || account_pool.clone()))
-        .and(warp::any().map(move || config.clone()))
+        .and(warp::header::optional::<String>("x-api-key"))
+        .and(warp::header::optional::<String>("anthropic-version"))
+        .and(warp::any().map(move || context.clone()))
         .and_then(handle_models)
 }
 
-/// 处理模型列表请求
+/// 处理模型列表和单个模型查询
+///
+/// 带 `anthropic-version` 或 `x-api-key` 头时按 Anthropic 格式返回，否则按 OpenAI 格式
 async fn handle_models(
+    model_id: Option<String>,
     auth_header: Option<String>,
-    pool: Arc<AccountPool>,
-    config: Arc<tokio::sync::RwLock<ProxyConfig>>,
-) -> Result<impl warp::Reply, warp::Rejection> {
-    // 验证 API Key
-    let config_read = config.read().await;
-    let has_api_keys = config_read.api_keys.as_ref().map(|keys| !keys.is_empty()).unwrap_or(false);
-    
-    println!("[Models] Authorization 头: {:?}", auth_header);
-    println!("[Models] 是否配置了 API Keys: {}", has_api_keys);
-    
-    if has_api_keys {
-        let api_keys = config_read.api_keys.as_ref().unwrap();
-        
-        // 提取 Bearer Token
-        let provided_key = auth_header
-            .as_ref()
-            .and_then(|h| h.strip_prefix("Bearer "))
+    x_api_key: Option<String>,
+    anthropic_version: Option<String>,
+    context: ProxyContext,
+) -> Result<warp::reply::Response, warp::Rejection> {
+    let anthropic = anthropic_version.is_some() || x_api_key.is_some();
+    let api = if anthropic { ApiFormat::Claude } else { ApiFormat::OpenAI };
+
+    // 验证 API Key，模型列表不受接口开关限制
+    {
+        let config_read = context.config.read().await;
+        let provided_key = x_api_key
+            .as_deref()
+            .or_else(|| bearer_token(auth_header.as_deref()))
             .map(|s| s.trim());
-        
-        println!("[Models] 提取的 API Key: {:?}", provided_key);
-        
-        if provided_key.is_none() {
-            drop(config_read);
-            return Ok(warp::reply::with_status(
-                warp::reply::json(&serde_json::json!({
-                    "error": {
-                        "message": "缺少 Authorization 头",
-                        "type": "invalid_request_error",
-                        "code": "missing_authorization"
-                    }
-                })),
-                warp::http::StatusCode::UNAUTHORIZED,
-            ));
-        }
-        
-        let provided_key = provided_key.unwrap();
-        
-        // 验证 API Key 是否有效且启用
-        let valid_key = api_keys.iter().find(|k| {
-            println!("[Models] 检查 API Key: {} (enabled: {})", k.key, k.enabled);
-            k.enabled && k.key == provided_key
-        });
-        
-        println!("[Models] 验证结果: {}", valid_key.is_some());
-        
-        if valid_key.is_none() {
-            drop(config_read);
-            return Ok(warp::reply::with_status(
-                warp::reply::json(&serde_json::json!({
-                    "error": {
-                        "message": "无效的 API Key",
-                        "type": "invalid_request_error",
-                        "code": "invalid_api_key"
-                    }
-                })),
-                warp::http::StatusCode::UNAUTHORIZED,
-            ));
+        if let Err((message, code)) = check_api_key(&config_read, provided_key) {
+            return Ok(api.error(message, code, StatusCode::UNAUTHORIZED));
         }
     }
-    
-    drop(config_read);
-    
-    let accounts = pool.get_all_accounts();
+
+    // 合并所有可用账号的模型列表，按账号缓存
+    let accounts = context.pool.get_available_accounts();
     if accounts.is_empty() {
-        return Ok(warp::reply::with_status(
-            warp::reply::json(&serde_json::json!({
-                "error": {
-                    "message": "没有可用账号",
-                    "type": "server_error",
-                    "code": "no_accounts"
-                }
-            })),
-            warp::http::StatusCode::SERVICE_UNAVAILABLE,
-        ));
+        return Ok(api.error("没有可用账号", "no_accounts", StatusCode::SERVICE_UNAVAILABLE));
     }
 
-    match fetch_kiro_models(&accounts[0]).await {
-        Ok(kiro_models) => {
-            // 转换为 OpenAI 格式
-            let openai_models: Vec<serde_json::Value> = kiro_models
-                .iter()
-                .filter_map(|model| {
-                    let model_id = model.get("modelId")?.as_str()?;
-                    
-                    Some(serde_json::json!({
-                        "id": model_id,
-                        "object": "model",
-                        "created": chrono::Utc::now().timestamp(),
-                        "owned_by": "amazon",
-                        "permission": [],
-                        "root": model_id,
-                        "parent": null
-                    }))
-                })
-                .collect();
-
-            Ok(warp::reply::with_status(
-                warp::reply::json(&serde_json::json!({
-                    "object": "list",
-                    "data": openai_models
-                })),
-                warp::http::StatusCode::OK,
-            ))
-        }
-        Err(e) => Ok(warp::reply::with_status(
-            warp::reply::json(&serde_json::json!({
-                "error": {
-                    "message": e,
-                    "type": "server_error",
-                    "code": "fetch_models_failed"
-                }
-            })),
-            warp::http::StatusCode::INTERNAL_SERVER_ERROR,
-        )),
+    let kiro_models = match context.pool.model_catalog().models(&context.http_clients, &accounts).await {
+        Ok((kiro_models, _)) => kiro_models,
+        Err(e) => return Ok(api.error(e, "fetch_models_failed", StatusCode::INTERNAL_SERVER_ERROR)),
+    };
+
+    let convert = if anthropic { kiro_model_to_anthropic } else { kiro_model_to_openai };
+    let models: Vec<serde_json::Value> = kiro_models.iter().map(convert).collect();
+
+    // 查询单个模型
+    if let Some(model_id) = model_id {
+        return Ok(match models.into_iter().find(|m| m["id"] == model_id.as_str()) {
+            Some(model) => warp::reply::json(&model).into_response(),
+            None => api.error(format!("模型不存在: {}", model_id), "model_not_found", StatusCode::NOT_FOUND),
+        });
     }
+
+    let body = if anthropic {
+        serde_json::json!({
+            "data": models,
+            "has_more": false,
+            "first_id": models.first().map(|m| m["id"].clone()),
+            "last_id": models.last().map(|m| m["id"].clone())
+        })
+    } else {
+        serde_json::json!({
+            "object": "list",
+            "data": models
+        })
+    };
+    Ok(warp::reply::json(&body).into_response())
 }
 
 /// 创建 OpenAI Chat Completions 路由
-pub fn chat_completions_route(
-    account_pool: Arc<AccountPool>,
-    stats: Arc<Mutex<ProxyStats>>,
-    session_stats: Arc<Mutex<SessionStats>>,
-    recent_logs: Arc<Mutex<Vec<RequestLog>>>,
-    config: Arc<tokio::sync::RwLock<ProxyConfig>>,
-) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+pub fn chat_completions_route(context: ProxyContext) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
     warp::path!("v1" / "chat" / "completions")
         .and(warp::post())
         .and(warp::header::optional::<String>("authorization"))
         .and(warp::body::json())
-        .and(warp::any().map(move || account_pool.clone()))
-        .and(warp::any().map(move || stats.clone()))
-        .and(warp::any().map(move || session_stats.clone()))
-        .and(warp::any().map(move || recent_logs.clone()))
-        .and(warp::any().map(move || config.clone()))
+        .and(warp::any().map(move || context.clone()))
         .and_then(handle_chat_completions)
 }
 
@@ -169,539 +123,525 @@ pub fn chat_completions_route(
 async fn handle_chat_completions(
     auth_header: Option<String>,
     body: serde_json::Value,
-    pool: Arc<AccountPool>,
-    stats_arc: Arc<Mutex<ProxyStats>>,
-    session_stats_arc: Arc<Mutex<SessionStats>>,
-    recent_logs_arc: Arc<Mutex<Vec<RequestLog>>>,
-    config: Arc<tokio::sync::RwLock<ProxyConfig>>,
-) -> Result<impl warp::Reply, warp::Rejection> {
-    // 检查是否启用 OpenAI API
-    let config_read = config.read().await;
-    if !config_read.enable_openai {
-        drop(config_read);
-        return Ok(warp::reply::with_status(
-            warp::reply::json(&serde_json::json!({
-                "error": {
-                    "message": "OpenAI API 未启用",
-                    "type": "invalid_request_error",
-                    "code": "endpoint_disabled"
-                }
-            })),
-            warp::http::StatusCode::FORBIDDEN,
-        ));
-    }
-    
-    // 验证 API Key
-    let has_api_keys = config_read.api_keys.as_ref().map(|keys| !keys.is_empty()).unwrap_or(false);
-    
-    println!("[OpenAI] Authorization 头: {:?}", auth_header);
-    println!("[OpenAI] 是否配置了 API Keys: {}", has_api_keys);
-    
-    if has_api_keys {
-        let api_keys = config_read.api_keys.as_ref().unwrap();
-        
-        // 提取 Bearer Token
-        let provided_key = auth_header
-            .as_ref()
-            .and_then(|h| h.strip_prefix("Bearer "))
-            .map(|s| s.trim());
-        
-        println!("[OpenAI] 提取的 API Key: {:?}", provided_key);
-        
-        if provided_key.is_none() {
-            drop(config_read);
-            return Ok(warp::reply::with_status(
-                warp::reply::json(&serde_json::json!({
-                    "error": {
-                        "message": "缺少 Authorization 头",
-                        "type": "invalid_request_error",
-                        "code": "missing_authorization"
-                    }
-                })),
-                warp::http::StatusCode::UNAUTHORIZED,
-            ));
-        }
-        
-        let provided_key = provided_key.unwrap();
-        
-        // 验证 API Key 是否有效且启用
-        let valid_key = api_keys.iter().find(|k| {
-            println!("[OpenAI] 检查 API Key: {} (enabled: {})", k.key, k.enabled);
-            k.enabled && k.key == provided_key
-        });
-        
-        println!("[OpenAI] 验证结果: {}", valid_key.is_some());
-        
-        if valid_key.is_none() {
-            drop(config_read);
-            return Ok(warp::reply::with_status(
-                warp::reply::json(&serde_json::json!({
-                    "error": {
-                        "message": "无效的 API Key",
-                        "type": "invalid_request_error",
-                        "code": "invalid_api_key"
-                    }
-                })),
-                warp::http::StatusCode::UNAUTHORIZED,
-            ));
-        }
-    }
-    
-    drop(config_read);
-    
+    context: ProxyContext,
+) -> Result<warp::reply::Response, warp::Rejection> {
+    let api = ApiFormat::OpenAI;
+    let api_key_id = match context.authorize(api, bearer_token(auth_header.as_deref())).await {
+        Ok(api_key_id) => api_key_id,
+        Err(response) => return Ok(response),
+    };
+
     // 检查是否为流式请求
     let is_stream = body.get("stream")
         .and_then(|s| s.as_bool())
         .unwrap_or(false);
-    
+
+    // 解析请求
+    let openai_request: OpenAIChatRequest = match serde_json::from_value(body) {
+        Ok(req) => req,
+        Err(e) => {
+            return Ok(api.error(format!("无效的请求格式: {}", e), "invalid_format", StatusCode::BAD_REQUEST));
+        }
+    };
+
+    let response_format = match ResponseFormat::parse(openai_request.response_format.as_ref()) {
+        Ok(format) => format,
+        Err(e) => return Ok(api.error(e, "invalid_response_format", StatusCode::BAD_REQUEST)),
+    };
+    let retry_invalid_json = context.config.read().await.retry_invalid_json;
+    let input_tokens = estimate_openai_input_tokens(&openai_request);
+
+    // 流式请求：上游的输出边到达边转发
     if is_stream {
-        return Ok(warp::reply::with_status(
-            warp::reply::json(&serde_json::json!({
-                "error": {
-                    "message": "暂不支持流式请求",
-                    "type": "invalid_request_error",
-                    "code": "stream_not_supported"
-                }
-            })),
-            warp::http::StatusCode::BAD_REQUEST,
-        ));
+        let encoder = ChatStreamEncoder::new(&openai_request);
+        return Ok(stream_response(encoder, move |on_delta| async move {
+            let request = PipelineRequest {
+                format: api,
+                path: "/v1/chat/completions",
+                model: &openai_request.model,
+                api_key_id: api_key_id.as_deref(),
+                input_tokens,
+            };
+            execute_chat(&context, &request, &openai_request, response_format.as_ref(), retry_invalid_json, on_delta).await
+        })
+        .await);
+    }
+
+    let request = PipelineRequest {
+        format: api,
+        path: "/v1/chat/completions",
+        model: &openai_request.model,
+        api_key_id: api_key_id.as_deref(),
+        input_tokens,
+    };
+    let completion = match execute_chat(
+        &context,
+        &request,
+        &openai_request,
+        response_format.as_ref(),
+        retry_invalid_json,
+        Box::new(|_| {}),
+    )
+    .await
+    {
+        Ok(completion) => completion,
+        Err(response) => return Ok(response),
+    };
+
+    // 转换为 OpenAI 格式
+    match kiro_to_openai_response(&completion.kiro_response, &openai_request) {
+        Ok(openai_response) => Ok(warp::reply::json(&openai_response).into_response()),
+        Err(e) => Ok(api.error(
+            format!("响应转换失败: {}", e),
+            "response_conversion_failed",
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )),
     }
-    
+}
+
+/// 执行 Chat Completions 请求，结构化输出时校验 JSON，不通过时按配置重新请求一次
+///
+/// 结构化输出需要完整结果才能校验，不转发增量输出
+async fn execute_chat(
+    context: &ProxyContext,
+    request: &PipelineRequest<'_>,
+    openai_request: &OpenAIChatRequest,
+    response_format: Option<&ResponseFormat>,
+    retry_invalid_json: bool,
+    on_delta: DeltaSink,
+) -> Result<Completion, warp::reply::Response> {
+    let Some(format) = response_format else {
+        return context
+            .execute_streaming(request, |window| openai_to_kiro(openai_request, None, window), on_delta)
+            .await;
+    };
+
+    let mut completion = context
+        .execute(request, |window| openai_to_kiro(openai_request, Some(format), window))
+        .await?;
+    enforce_response_format(context, request, format, &mut completion, retry_invalid_json)
+        .await
+        .map_err(|e| request.format.error(e, "invalid_json_output", StatusCode::BAD_GATEWAY))?;
+    Ok(completion)
+}
+
+/// 按 response_format 校验输出并替换为规范化的 JSON
+///
+/// 校验不通过且允许重试时，用同一账号带上错误信息重新请求一次，两次调用的用量合并到响应中
+async fn enforce_response_format(
+    context: &ProxyContext,
+    request: &PipelineRequest<'_>,
+    format: &ResponseFormat,
+    completion: &mut Completion,
+    retry: bool,
+) -> Result<(), String> {
+    let output = completion.kiro_response.get("message").and_then(|m| m.as_str()).unwrap_or_default().to_string();
+    let error = match format.validate_output(&output) {
+        Ok(json) => {
+            completion.kiro_response["message"] = serde_json::json!(json);
+            return Ok(());
+        }
+        Err(e) => e,
+    };
+    if !retry {
+        return Err(error);
+    }
+
+    println!("[StructuredOutput] 输出校验失败，重新请求一次: {}", error);
+    let reask_request = format.reask_request(&completion.kiro_request, &output, &error);
+    let mut retried = context
+        .call_again(request, completion, &reask_request)
+        .await
+        .map_err(|e| format!("{}（重新请求失败: {}）", error, e))?;
+    add_usage(&mut retried, &completion.kiro_response);
+    completion.kiro_response = retried;
+
+    let output = completion.kiro_response.get("message").and_then(|m| m.as_str()).unwrap_or_default().to_string();
+    let json = format.validate_output(&output).map_err(|e| format!("重新请求后仍然无效，{}", e))?;
+    completion.kiro_response["message"] = serde_json::json!(json);
+    Ok(())
+}
+
+/// 创建 OpenAI Completions 路由（旧版 prompt 接口）
+pub fn completions_route(context: ProxyContext) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("v1" / "completions")
+        .and(warp::post())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::body::json())
+        .and(warp::any().map(move || context.clone()))
+        .and_then(handle_completions)
+}
+
+/// 创建 OpenAI Responses 路由
+pub fn responses_route(context: ProxyContext) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("v1" / "responses")
+        .and(warp::post())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::body::json())
+        .and(warp::any().map(move || context.clone()))
+        .and_then(handle_responses)
+}
+
+/// 处理 OpenAI Completions 请求
+///
+/// 上游不支持 stop 和 max_tokens，由转换器在本地截断；`stream: true` 时上游的输出边到达边转发，遇到停止序列前的文本先留着
+async fn handle_completions(
+    auth_header: Option<String>,
+    body: serde_json::Value,
+    context: ProxyContext,
+) -> Result<warp::reply::Response, warp::Rejection> {
+    let api = ApiFormat::OpenAI;
+    let api_key_id = match context.authorize(api, bearer_token(auth_header.as_deref())).await {
+        Ok(api_key_id) => api_key_id,
+        Err(response) => return Ok(response),
+    };
+
     // 解析请求
-    let openai_request: OpenAIChatRequest = match serde_json::from_value(body.clone()) {
+    let request: CompletionRequest = match serde_json::from_value(body) {
         Ok(req) => req,
         Err(e) => {
-            return Ok(warp::reply::with_status(
-                warp::reply::json(&serde_json::json!({
-                    "error": {
-                        "message": format!("无效的请求格式: {}", e),
-                        "type": "invalid_request_error",
-                        "code": "invalid_format"
-                    }
-                })),
-                warp::http::StatusCode::BAD_REQUEST,
-            ));
+            return Ok(api.error(format!("无效的请求格式: {}", e), "invalid_format", StatusCode::BAD_REQUEST));
         }
     };
-    
-    let model = openai_request.model.clone();
-    
-    // 转换为 Kiro 格式
-    let kiro_request = openai_to_kiro(&openai_request);
-    
-    // 获取账号
-    let account = match pool.get_next_account() {
-        Some(acc) => acc,
-        None => {
-            return Ok(warp::reply::with_status(
-                warp::reply::json(&serde_json::json!({
-                    "error": {
-                        "message": "没有可用账号",
-                        "type": "server_error",
-                        "code": "no_accounts"
-                    }
-                })),
-                warp::http::StatusCode::SERVICE_UNAVAILABLE,
-            ));
-        }
+    let prompt = match completion_prompt(&request) {
+        Ok(prompt) => prompt,
+        Err(e) => return Ok(api.error(e, "invalid_prompt", StatusCode::BAD_REQUEST)),
+    };
+    let is_stream = request.stream.unwrap_or(false);
+    let input_tokens = estimate_completion_input_tokens(&request);
+
+    if is_stream {
+        let encoder = CompletionStreamEncoder::new(&request);
+        return Ok(stream_response(encoder, move |on_delta| async move {
+            let pipeline_request = PipelineRequest {
+                format: api,
+                path: "/v1/completions",
+                model: &request.model,
+                api_key_id: api_key_id.as_deref(),
+                input_tokens,
+            };
+            context
+                .execute_streaming(&pipeline_request, |window| completions_to_kiro(&request, &prompt, window), on_delta)
+                .await
+        })
+        .await);
+    }
+
+    let pipeline_request = PipelineRequest {
+        format: api,
+        path: "/v1/completions",
+        model: &request.model,
+        api_key_id: api_key_id.as_deref(),
+        input_tokens,
+    };
+    let completion = match context
+        .execute(&pipeline_request, |window| completions_to_kiro(&request, &prompt, window))
+        .await
+    {
+        Ok(completion) => completion,
+        Err(response) => return Ok(response),
+    };
+
+    // 转换为 Completions 格式
+    match kiro_to_completion_response(&completion.kiro_response, &request) {
+        Ok(response) => Ok(warp::reply::json(&response).into_response()),
+        Err(e) => Ok(api.error(format!("响应转换失败: {}", e), "response_conversion_failed", StatusCode::INTERNAL_SERVER_ERROR)),
+    }
+}
+
+/// 处理 OpenAI Responses 请求
+///
+/// Responses API 属于 OpenAI 接口，使用相同的开关和 API Key；`stream: true` 时上游的输出边到达边转为 Responses 事件
+async fn handle_responses(
+    auth_header: Option<String>,
+    body: serde_json::Value,
+    context: ProxyContext,
+) -> Result<warp::reply::Response, warp::Rejection> {
+    let api = ApiFormat::OpenAI;
+    let api_key_id = match context.authorize(api, bearer_token(auth_header.as_deref())).await {
+        Ok(api_key_id) => api_key_id,
+        Err(response) => return Ok(response),
     };
-    
-    // 调用 Kiro API
-    let result = call_kiro_api(&account, &kiro_request, &model, 0).await;
-    
-    match result {
-        Ok(kiro_response) => {
-            // 转换为 OpenAI 格式
-            match kiro_to_openai_response(&kiro_response, &model) {
-                Ok(openai_response) => {
-                    // 提取 token 信息
-                    let input_tokens = kiro_response
-                        .get("inputTokens")
-                        .and_then(|t| t.as_u64())
-                        .unwrap_or(0);
-                    let output_tokens = kiro_response
-                        .get("outputTokens")
-                        .and_then(|t| t.as_u64())
-                        .unwrap_or(0);
-                    let credits = kiro_response
-                        .get("credits")
-                        .and_then(|c| c.as_f64())
-                        .unwrap_or(0.0);
-                    
-                    // 更新统计
-                    {
-                        let mut stats = stats_arc.lock().unwrap();
-                        let mut session_stats = session_stats_arc.lock().unwrap();
-                        
-                        stats.total_requests += 1;
-                        stats.success_requests += 1;
-                        stats.input_tokens += input_tokens;
-                        stats.output_tokens += output_tokens;
-                        stats.total_tokens += input_tokens + output_tokens;
-                        stats.total_credits += credits;
-                        
-                        session_stats.total_requests += 1;
-                        session_stats.success_requests += 1;
-                    }
-                    
-                    // 记录日志
-                    {
-                        let mut logs = recent_logs_arc.lock().unwrap();
-                        logs.push(RequestLog {
-                            time: chrono::Utc::now().to_rfc3339(),
-                            path: "/v1/chat/completions".to_string(),
-                            model: Some(model.clone()),
-                            status: 200,
-                            tokens: Some(input_tokens + output_tokens),
-                            input_tokens: Some(input_tokens),
-                            output_tokens: Some(output_tokens),
-                            credits: Some(credits),
-                            error: None,
-                        });
-                        if logs.len() > 1000 {
-                            logs.drain(0..100);
-                        }
-                    }
-                    
-                    Ok(warp::reply::with_status(
-                        warp::reply::json(&openai_response),
-                        warp::http::StatusCode::OK,
-                    ))
-                }
-                Err(e) => {
-                    Ok(warp::reply::with_status(
-                        warp::reply::json(&serde_json::json!({
-                            "error": {
-                                "message": format!("响应转换失败: {}", e),
-                                "type": "server_error",
-                                "code": "response_conversion_failed"
-                            }
-                        })),
-                        warp::http::StatusCode::INTERNAL_SERVER_ERROR,
-                    ))
-                }
-            }
+
+    // 解析请求
+    let request: ResponsesRequest = match serde_json::from_value(body) {
+        Ok(req) => req,
+        Err(e) => {
+            return Ok(api.error(format!("无效的请求格式: {}", e), "invalid_format", StatusCode::BAD_REQUEST));
         }
+    };
+    let is_stream = request.stream.unwrap_or(false);
+    let input_tokens = estimate_responses_input_tokens(&request);
+
+    if is_stream {
+        let encoder = ResponsesStreamEncoder::new(&request);
+        return Ok(stream_response(encoder, move |on_delta| async move {
+            let pipeline_request = PipelineRequest {
+                format: api,
+                path: "/v1/responses",
+                model: &request.model,
+                api_key_id: api_key_id.as_deref(),
+                input_tokens,
+            };
+            context
+                .execute_streaming(&pipeline_request, |window| responses_to_kiro(&request, window), on_delta)
+                .await
+        })
+        .await);
+    }
+
+    let pipeline_request = PipelineRequest {
+        format: api,
+        path: "/v1/responses",
+        model: &request.model,
+        api_key_id: api_key_id.as_deref(),
+        input_tokens,
+    };
+    let completion = match context
+        .execute(&pipeline_request, |window| responses_to_kiro(&request, window))
+        .await
+    {
+        Ok(completion) => completion,
+        Err(response) => return Ok(response),
+    };
+
+    // 转换为 Responses 格式
+    match kiro_to_responses_response(&completion.kiro_response, &request) {
+        Ok(response) => Ok(warp::reply::json(&response).into_response()),
+        Err(e) => Ok(api.error(format!("响应转换失败: {}", e), "response_conversion_failed", StatusCode::INTERNAL_SERVER_ERROR)),
+    }
+}
+
+/// 创建 Gemini generateContent / streamGenerateContent 路由
+///
+/// 路径形如 `/v1beta/models/{model}:generateContent`，模型和方法在同一个路径段中
+pub fn gemini_route(context: ProxyContext) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("v1beta" / "models" / String)
+        .and(warp::post())
+        .and(warp::header::optional::<String>("x-goog-api-key"))
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::query::<std::collections::HashMap<String, String>>())
+        .and(warp::body::json())
+        .and(warp::any().map(move || context.clone()))
+        .and_then(handle_gemini)
+}
+
+/// 处理 Gemini generateContent / streamGenerateContent 请求
+///
+/// API Key 可通过 `x-goog-api-key` 头、`key` 查询参数或 Bearer Token 提供；
+/// 流式请求的输出边到达边转发，`alt=sse` 时以 SSE 输出，否则逐步输出 JSON 数组
+async fn handle_gemini(
+    model_action: String,
+    goog_api_key: Option<String>,
+    auth_header: Option<String>,
+    query: std::collections::HashMap<String, String>,
+    body: serde_json::Value,
+    context: ProxyContext,
+) -> Result<warp::reply::Response, warp::Rejection> {
+    let api = ApiFormat::Gemini;
+
+    // 冒号可能被编码为 %3A
+    let model_action = model_action.replace("%3A", ":").replace("%3a", ":");
+    let (model, is_stream) = match model_action.rsplit_once(':') {
+        Some((model, "generateContent")) => (model.to_string(), false),
+        Some((model, "streamGenerateContent")) => (model.to_string(), true),
+        _ => return Ok(api.error(format!("不支持的方法: {}", model_action), "unsupported_method", StatusCode::NOT_FOUND)),
+    };
+
+    let provided_key = goog_api_key
+        .as_deref()
+        .or_else(|| query.get("key").map(|k| k.as_str()))
+        .or_else(|| bearer_token(auth_header.as_deref()));
+    let api_key_id = match context.authorize(api, provided_key).await {
+        Ok(api_key_id) => api_key_id,
+        Err(response) => return Ok(response),
+    };
+
+    // 解析请求
+    let request: GeminiRequest = match serde_json::from_value(body) {
+        Ok(req) => req,
         Err(e) => {
-            // 更新失败统计
-            {
-                let mut stats = stats_arc.lock().unwrap();
-                let mut session_stats = session_stats_arc.lock().unwrap();
-                
-                stats.total_requests += 1;
-                stats.failed_requests += 1;
-                
-                session_stats.total_requests += 1;
-                session_stats.failed_requests += 1;
-            }
-            
-            // 记录错误日志
-            {
-                let mut logs = recent_logs_arc.lock().unwrap();
-                logs.push(RequestLog {
-                    time: chrono::Utc::now().to_rfc3339(),
-                    path: "/v1/chat/completions".to_string(),
-                    model: Some(model.clone()),
-                    status: 500,
-                    tokens: None,
-                    input_tokens: None,
-                    output_tokens: None,
-                    credits: None,
-                    error: Some(e.clone()),
-                });
-                if logs.len() > 1000 {
-                    logs.drain(0..100);
-                }
-            }
-            
-            Ok(warp::reply::with_status(
-                warp::reply::json(&serde_json::json!({
-                    "error": {
-                        "message": e,
-                        "type": "server_error",
-                        "code": "api_call_failed"
-                    }
-                })),
-                warp::http::StatusCode::INTERNAL_SERVER_ERROR,
-            ))
+            return Ok(api.error(format!("无效的请求格式: {}", e), "invalid_format", StatusCode::BAD_REQUEST));
         }
+    };
+    if request.contents.is_empty() {
+        return Ok(api.error("contents 不能为空", "invalid_format", StatusCode::BAD_REQUEST));
+    }
+
+    let path = format!("/v1beta/models/{}", model_action);
+    let input_tokens = estimate_gemini_input_tokens(&request);
+
+    if is_stream {
+        let sse = query.get("alt").is_some_and(|alt| alt == "sse");
+        let encoder = GeminiStreamEncoder::new(&request, &model, sse);
+        return Ok(stream_response(encoder, move |on_delta| async move {
+            let pipeline_request = PipelineRequest {
+                format: api,
+                path: &path,
+                model: &model,
+                api_key_id: api_key_id.as_deref(),
+                input_tokens,
+            };
+            context
+                .execute_streaming(&pipeline_request, |window| gemini_to_kiro(&request, window), on_delta)
+                .await
+        })
+        .await);
+    }
+
+    let pipeline_request = PipelineRequest {
+        format: api,
+        path: &path,
+        model: &model,
+        api_key_id: api_key_id.as_deref(),
+        input_tokens,
+    };
+    let completion = match context
+        .execute(&pipeline_request, |window| gemini_to_kiro(&request, window))
+        .await
+    {
+        Ok(completion) => completion,
+        Err(response) => return Ok(response),
+    };
+
+    // 转换为 Gemini 格式
+    match kiro_to_gemini_response(&completion.kiro_response, &request, &model) {
+        Ok(response) => Ok(warp::reply::json(&response).into_response()),
+        Err(e) => Ok(api.error(format!("响应转换失败: {}", e), "response_conversion_failed", StatusCode::INTERNAL_SERVER_ERROR)),
     }
 }
 
+/// 提取 Claude 接口的 API Key：`x-api-key` 头或 Bearer Token
+fn claude_api_key<'a>(auth_header: Option<&'a str>, x_api_key: Option<&'a str>) -> Option<&'a str> {
+    x_api_key.or_else(|| {
+        auth_header.and_then(|h| {
+            h.strip_prefix("Bearer ")
+                .or_else(|| h.strip_prefix("x-api-key: "))
+        })
+    })
+}
+
 /// 创建 Claude Messages 路由
-pub fn claude_messages_route(
-    account_pool: Arc<AccountPool>,
-    stats: Arc<Mutex<ProxyStats>>,
-    session_stats: Arc<Mutex<SessionStats>>,
-    recent_logs: Arc<Mutex<Vec<RequestLog>>>,
-    config: Arc<tokio::sync::RwLock<ProxyConfig>>,
-) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+pub fn claude_messages_route(context: ProxyContext) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
     warp::path!("v1" / "messages")
         .or(warp::path("messages"))
         .unify()
         .and(warp::post())
         .and(warp::header::optional::<String>("authorization"))
+        .and(warp::header::optional::<String>("x-api-key"))
         .and(warp::body::json())
-        .and(warp::any().map(move || account_pool.clone()))
-        .and(warp::any().map(move || stats.clone()))
-        .and(warp::any().map(move || session_stats.clone()))
-        .and(warp::any().map(move || recent_logs.clone()))
-        .and(warp::any().map(move || config.clone()))
+        .and(warp::any().map(move || context.clone()))
         .and_then(handle_claude_messages)
 }
 
-/// 处理 Claude Messages 请求
-async fn handle_claude_messages(
+/// 创建 Claude Count Tokens 路由
+pub fn claude_count_tokens_route(context: ProxyContext) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("v1" / "messages" / "count_tokens")
+        .and(warp::post())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::header::optional::<String>("x-api-key"))
+        .and(warp::body::json())
+        .and(warp::any().map(move || context.clone()))
+        .and_then(handle_claude_count_tokens)
+}
+
+/// 处理 Claude Count Tokens 请求，本地估算输入 token，不调用上游
+async fn handle_claude_count_tokens(
     auth_header: Option<String>,
+    x_api_key: Option<String>,
     body: serde_json::Value,
-    pool: Arc<AccountPool>,
-    stats_arc: Arc<Mutex<ProxyStats>>,
-    session_stats_arc: Arc<Mutex<SessionStats>>,
-    recent_logs_arc: Arc<Mutex<Vec<RequestLog>>>,
-    config: Arc<tokio::sync::RwLock<ProxyConfig>>,
-) -> Result<impl warp::Reply, warp::Rejection> {
-    // 检查是否启用 Claude API
-    let config_read = config.read().await;
-    if !config_read.enable_claude {
-        drop(config_read);
-        return Ok(warp::reply::with_status(
-            warp::reply::json(&serde_json::json!({
-                "error": {
-                    "type": "invalid_request_error",
-                    "message": "Claude API 未启用"
-                }
-            })),
-            warp::http::StatusCode::FORBIDDEN,
-        ));
+    context: ProxyContext,
+) -> Result<warp::reply::Response, warp::Rejection> {
+    let api = ApiFormat::Claude;
+
+    // 验证 API Key，规则与 /v1/messages 相同
+    if let Err(response) = context
+        .authorize(api, claude_api_key(auth_header.as_deref(), x_api_key.as_deref()))
+        .await
+    {
+        return Ok(response);
     }
-    
-    // 验证 API Key
-    let has_api_keys = config_read.api_keys.as_ref().map(|keys| !keys.is_empty()).unwrap_or(false);
-    
-    println!("[Claude] Authorization 头: {:?}", auth_header);
-    println!("[Claude] 是否配置了 API Keys: {}", has_api_keys);
-    
-    if has_api_keys {
-        let api_keys = config_read.api_keys.as_ref().unwrap();
-        
-        // 提取 Bearer Token 或 x-api-key
-        let provided_key = auth_header
-            .as_ref()
-            .and_then(|h| {
-                h.strip_prefix("Bearer ")
-                    .or_else(|| h.strip_prefix("x-api-key: "))
-            })
-            .map(|s| s.trim());
-        
-        println!("[Claude] 提取的 API Key: {:?}", provided_key);
-        
-        if provided_key.is_none() {
-            drop(config_read);
-            return Ok(warp::reply::with_status(
-                warp::reply::json(&serde_json::json!({
-                    "error": {
-                        "type": "invalid_request_error",
-                        "message": "缺少 Authorization 头"
-                    }
-                })),
-                warp::http::StatusCode::UNAUTHORIZED,
-            ));
-        }
-        
-        let provided_key = provided_key.unwrap();
-        
-        // 验证 API Key 是否有效且启用
-        let valid_key = api_keys.iter().find(|k| {
-            println!("[Claude] 检查 API Key: {} (enabled: {})", k.key, k.enabled);
-            k.enabled && k.key == provided_key
-        });
-        
-        println!("[Claude] 验证结果: {}", valid_key.is_some());
-        
-        if valid_key.is_none() {
-            drop(config_read);
-            return Ok(warp::reply::with_status(
-                warp::reply::json(&serde_json::json!({
-                    "error": {
-                        "type": "invalid_request_error",
-                        "message": "无效的 API Key"
-                    }
-                })),
-                warp::http::StatusCode::UNAUTHORIZED,
-            ));
-        }
+
+    if !body.get("messages").is_some_and(|m| m.is_array()) {
+        return Ok(api.error("无效的请求格式: 缺少 messages", "invalid_format", StatusCode::BAD_REQUEST));
     }
-    
+
+    let input_tokens = estimate_claude_input_tokens(&body);
+    println!("[Claude] count_tokens 估算输入 token: {}", input_tokens);
+
+    Ok(warp::reply::json(&serde_json::json!({ "input_tokens": input_tokens })).into_response())
+}
+
+/// 处理 Claude Messages 请求
+async fn handle_claude_messages(
+    auth_header: Option<String>,
+    x_api_key: Option<String>,
+    body: serde_json::Value,
+    context: ProxyContext,
+) -> Result<warp::reply::Response, warp::Rejection> {
+    let api = ApiFormat::Claude;
+    let api_key_id = match context
+        .authorize(api, claude_api_key(auth_header.as_deref(), x_api_key.as_deref()))
+        .await
+    {
+        Ok(api_key_id) => api_key_id,
+        Err(response) => return Ok(response),
+    };
+
     // 检查是否为流式请求
     let is_stream = body.get("stream")
         .and_then(|s| s.as_bool())
         .unwrap_or(false);
-    
-    if is_stream {
-        return Ok(warp::reply::with_status(
-            warp::reply::json(&serde_json::json!({
-                "error": {
-                    "type": "invalid_request_error",
-                    "message": "暂不支持流式请求"
-                }
-            })),
-            warp::http::StatusCode::BAD_REQUEST,
-        ));
-    }
-    
+    let input_tokens = estimate_claude_input_tokens(&body);
+
     // 解析请求
-    let claude_request: ClaudeRequest = match serde_json::from_value(body.clone()) {
+    let claude_request: ClaudeRequest = match serde_json::from_value(body) {
         Ok(req) => req,
         Err(e) => {
-            return Ok(warp::reply::with_status(
-                warp::reply::json(&serde_json::json!({
-                    "error": {
-                        "type": "invalid_request_error",
-                        "message": format!("无效的请求格式: {}", e)
-                    }
-                })),
-                warp::http::StatusCode::BAD_REQUEST,
-            ));
+            return Ok(api.error(format!("无效的请求格式: {}", e), "invalid_format", StatusCode::BAD_REQUEST));
         }
     };
-    
-    let model = claude_request.model.clone();
-    
-    // 转换为 Kiro 格式
-    let kiro_request = claude_to_kiro(&claude_request);
-    
-    // 获取账号
-    let account = match pool.get_next_account() {
-        Some(acc) => acc,
-        None => {
-            return Ok(warp::reply::with_status(
-                warp::reply::json(&serde_json::json!({
-                    "error": {
-                        "type": "api_error",
-                        "message": "没有可用账号"
-                    }
-                })),
-                warp::http::StatusCode::SERVICE_UNAVAILABLE,
-            ));
-        }
+
+    // 流式请求：上游的输出边到达边转发
+    if is_stream {
+        let encoder = ClaudeStreamEncoder::new(&claude_request, input_tokens);
+        return Ok(stream_response(encoder, move |on_delta| async move {
+            let request = PipelineRequest {
+                format: api,
+                path: "/v1/messages",
+                model: &claude_request.model,
+                api_key_id: api_key_id.as_deref(),
+                input_tokens,
+            };
+            context
+                .execute_streaming(&request, |window| claude_to_kiro(&claude_request, window), on_delta)
+                .await
+        })
+        .await);
+    }
+
+    let request = PipelineRequest {
+        format: api,
+        path: "/v1/messages",
+        model: &claude_request.model,
+        api_key_id: api_key_id.as_deref(),
+        input_tokens,
     };
-    
-    // 调用 Kiro API
-    let result = call_kiro_api(&account, &kiro_request, &model, 0).await;
-    
-    match result {
-        Ok(kiro_response) => {
-            // 转换为 Claude 格式
-            match kiro_to_claude_response(&kiro_response, &model) {
-                Ok(claude_response) => {
-                    // 提取 token 信息
-                    let input_tokens = kiro_response
-                        .get("inputTokens")
-                        .and_then(|t| t.as_u64())
-                        .unwrap_or(0);
-                    let output_tokens = kiro_response
-                        .get("outputTokens")
-                        .and_then(|t| t.as_u64())
-                        .unwrap_or(0);
-                    let credits = kiro_response
-                        .get("credits")
-                        .and_then(|c| c.as_f64())
-                        .unwrap_or(0.0);
-                    
-                    // 更新统计
-                    {
-                        let mut stats = stats_arc.lock().unwrap();
-                        let mut session_stats = session_stats_arc.lock().unwrap();
-                        
-                        stats.total_requests += 1;
-                        stats.success_requests += 1;
-                        stats.input_tokens += input_tokens;
-                        stats.output_tokens += output_tokens;
-                        stats.total_tokens += input_tokens + output_tokens;
-                        stats.total_credits += credits;
-                        
-                        session_stats.total_requests += 1;
-                        session_stats.success_requests += 1;
-                    }
-                    
-                    // 记录日志
-                    {
-                        let mut logs = recent_logs_arc.lock().unwrap();
-                        logs.push(RequestLog {
-                            time: chrono::Utc::now().to_rfc3339(),
-                            path: "/v1/messages".to_string(),
-                            model: Some(model.clone()),
-                            status: 200,
-                            tokens: Some(input_tokens + output_tokens),
-                            input_tokens: Some(input_tokens),
-                            output_tokens: Some(output_tokens),
-                            credits: Some(credits),
-                            error: None,
-                        });
-                        if logs.len() > 1000 {
-                            logs.drain(0..100);
-                        }
-                    }
-                    
-                    Ok(warp::reply::with_status(
-                        warp::reply::json(&claude_response),
-                        warp::http::StatusCode::OK,
-                    ))
-                }
-                Err(e) => {
-                    Ok(warp::reply::with_status(
-                        warp::reply::json(&serde_json::json!({
-                            "error": {
-                                "type": "api_error",
-                                "message": format!("响应转换失败: {}", e)
-                            }
-                        })),
-                        warp::http::StatusCode::INTERNAL_SERVER_ERROR,
-                    ))
-                }
-            }
-        }
-        Err(e) => {
-            // 更新失败统计
-            {
-                let mut stats = stats_arc.lock().unwrap();
-                let mut session_stats = session_stats_arc.lock().unwrap();
-                
-                stats.total_requests += 1;
-                stats.failed_requests += 1;
-                
-                session_stats.total_requests += 1;
-                session_stats.failed_requests += 1;
-            }
-            
-            // 记录错误日志
-            {
-                let mut logs = recent_logs_arc.lock().unwrap();
-                logs.push(RequestLog {
-                    time: chrono::Utc::now().to_rfc3339(),
-                    path: "/v1/messages".to_string(),
-                    model: Some(model.clone()),
-                    status: 500,
-                    tokens: None,
-                    input_tokens: None,
-                    output_tokens: None,
-                    credits: None,
-                    error: Some(e.clone()),
-                });
-                if logs.len() > 1000 {
-                    logs.drain(0..100);
-                }
-            }
-            
-            Ok(warp::reply::with_status(
-                warp::reply::json(&serde_json::json!({
-                    "error": {
-                        "type": "api_error",
-                        "message": e
-                    }
-                })),
-                warp::http::StatusCode::INTERNAL_SERVER_ERROR,
-            ))
-        }
+    let completion = match context
+        .execute(&request, |window| claude_to_kiro(&claude_request, window))
+        .await
+    {
+        Ok(completion) => completion,
+        Err(response) => return Ok(response),
+    };
+
+    // 转换为 Claude 格式
+    match kiro_to_claude_response(&completion.kiro_response, &claude_request) {
+        Ok(claude_response) => Ok(warp::reply::json(&claude_response).into_response()),
+        Err(e) => Ok(api.error(
+            format!("响应转换失败: {}", e),
+            "response_conversion_failed",
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )),
     }
 }
@@ -1,13 +1,17 @@
 // HTTP 代理服务器
 use super::account_pool::AccountPool;
+use super::pipeline::ProxyContext;
 use super::routes;
 use super::types::*;
+use crate::http_client::HttpClients;
 use serde_json::Value;
+use std::net::SocketAddr;
 use std::sync::{Arc, Mutex};
-use tokio::sync::RwLock;
+use tokio::sync::{oneshot, RwLock};
 use warp::Filter;
 
 /// 代理服务器
+#[derive(Clone)]
 pub struct ProxyServer {
     config: Arc<RwLock<ProxyConfig>>,
     account_pool: Arc<AccountPool>,
@@ -15,12 +19,14 @@ pub struct ProxyServer {
     session_stats: Arc<Mutex<SessionStats>>,
     recent_logs: Arc<Mutex<Vec<RequestLog>>>,
     is_running: Arc<Mutex<bool>>,
-    shutdown_tx: Arc<Mutex<Option<tokio::sync::oneshot::Sender<()>>>>,
+    shutdown_tx: Arc<Mutex<Option<oneshot::Sender<()>>>>,
+    server_task: Arc<Mutex<Option<tokio::task::JoinHandle<()>>>>,
+    http_clients: HttpClients,
 }
 
 impl ProxyServer {
-    /// 创建新的代理服务器
-    pub fn new(config: ProxyConfig) -> Self {
+    /// 创建新的代理服务器，上游请求共用传入的 HTTP 客户端
+    pub fn new(config: ProxyConfig, http_clients: HttpClients) -> Self {
         Self {
             config: Arc::new(RwLock::new(config)),
             account_pool: Arc::new(AccountPool::new()),
@@ -32,6 +38,8 @@ impl ProxyServer {
             recent_logs: Arc::new(Mutex::new(Vec::new())),
             is_running: Arc::new(Mutex::new(false)),
             shutdown_tx: Arc::new(Mutex::new(None)),
+            server_task: Arc::new(Mutex::new(None)),
+            http_clients,
         }
     }
 
@@ -45,12 +53,14 @@ impl ProxyServer {
             *is_running = true;
         }
 
-        let config = self.config.read().await;
-        let port = config.port;
-        let host = config.host.clone();
-        let enable_openai = config.enable_openai;
-        let enable_claude = config.enable_claude;
-        drop(config);
+        let config = self.config.read().await.clone();
+        let addr = match listen_addr(&config) {
+            Ok(addr) => addr,
+            Err(e) => {
+                *self.is_running.lock().unwrap() = false;
+                return Err(e);
+            }
+        };
 
         // 重置会话统计
         {
@@ -61,63 +71,91 @@ impl ProxyServer {
             };
         }
 
-        println!("[ProxyServer] 启动服务器: {}:{}", host, port);
+        println!("[ProxyServer] 启动服务器: {}", addr);
 
-        // 创建关闭信号
-        let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
-        {
-            let mut tx = self.shutdown_tx.lock().unwrap();
-            *tx = Some(shutdown_tx);
+        let (shutdown_tx, handle) = match self.spawn_listener(addr) {
+            Ok(listener) => listener,
+            Err(e) => {
+                *self.is_running.lock().unwrap() = false;
+                return Err(e);
+            }
+        };
+        *self.shutdown_tx.lock().unwrap() = Some(shutdown_tx);
+        *self.server_task.lock().unwrap() = Some(handle);
+
+        println!("[ProxyServer] OpenAI API: {}", if config.enable_openai { "启用" } else { "禁用" });
+        println!("[ProxyServer] Claude API: {}", if config.enable_claude { "启用" } else { "禁用" });
+        println!("[ProxyServer] Gemini API: {}", if config.enable_gemini { "启用" } else { "禁用" });
+
+        Ok(())
+    }
+
+    /// 路由共用的请求处理上下文
+    fn context(&self) -> ProxyContext {
+        ProxyContext {
+            pool: self.account_pool.clone(),
+            stats: self.stats.clone(),
+            session_stats: self.session_stats.clone(),
+            recent_logs: self.recent_logs.clone(),
+            config: self.config.clone(),
+            http_clients: self.http_clients.clone(),
         }
+    }
 
-        // 克隆需要的数据
-        let account_pool = self.account_pool.clone();
-        let stats = self.stats.clone();
-        let session_stats = self.session_stats.clone();
-        let recent_logs = self.recent_logs.clone();
-        let config_arc = self.config.clone();
-
-        // 在后台线程启动 HTTP 服务器
-        tokio::spawn(async move {
-            use warp::Filter;
-            
-            // 创建所有路由
-            let health = routes::health_route();
-            let models = routes::models_route(account_pool.clone(), config_arc.clone());
-            let chat = routes::chat_completions_route(
-                account_pool.clone(),
-                stats.clone(),
-                session_stats.clone(),
-                recent_logs.clone(),
-                config_arc.clone(),
-            );
-            let messages = routes::claude_messages_route(
-                account_pool.clone(),
-                stats.clone(),
-                session_stats.clone(),
-                recent_logs.clone(),
-                config_arc.clone(),
-            );
-
-            let all_routes = health.or(models).or(chat).or(messages);
-
-            let addr: std::net::SocketAddr = format!("{}:{}", host, port)
-                .parse()
-                .expect("无效的地址");
-
-            let (_, server) = warp::serve(all_routes)
-                .bind_with_graceful_shutdown(addr, async {
-                    shutdown_rx.await.ok();
-                });
-
-            println!("[ProxyServer] HTTP 服务器已启动: {}", addr);
-            println!("[ProxyServer] OpenAI API: {}", if enable_openai { "启用" } else { "禁用" });
-            println!("[ProxyServer] Claude API: {}", if enable_claude { "启用" } else { "禁用" });
-            server.await;
-            println!("[ProxyServer] HTTP 服务器已停止");
+    /// 绑定监听地址并在后台运行 HTTP 服务器，返回关闭信号和任务句柄
+    fn spawn_listener(
+        &self,
+        addr: SocketAddr,
+    ) -> Result<(oneshot::Sender<()>, tokio::task::JoinHandle<()>), String> {
+        // 路由在每次请求时读取配置，开关、API Key 和模型映射的修改即时生效
+        let health = routes::health_route();
+        let context = self.context();
+        let models = routes::models_route(context.clone());
+        let chat = routes::chat_completions_route(context.clone());
+        let messages = routes::claude_messages_route(context.clone());
+        let completions = routes::completions_route(context.clone());
+        let responses = routes::responses_route(context.clone());
+        let gemini = routes::gemini_route(context.clone());
+        let count_tokens = routes::claude_count_tokens_route(context);
+
+        let all_routes = health.or(models).or(chat).or(completions).or(responses).or(count_tokens).or(messages).or(gemini);
+
+        let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
+        let (addr, server) = warp::serve(all_routes)
+            .try_bind_with_graceful_shutdown(addr, async {
+                shutdown_rx.await.ok();
+            })
+            .map_err(|e| bind_error(addr, e))?;
+
+        println!("[ProxyServer] HTTP 服务器已启动: {}", addr);
+        let is_running = self.is_running.clone();
+        let current_tx = self.shutdown_tx.clone();
+        let handle = tokio::spawn(async move {
+            let result = tokio::spawn(server).await;
+
+            // stop() 和切换地址都会先取走当前的关闭信号；信号仍在但接收端已释放，说明服务器自行退出
+            let unexpected = {
+                let mut tx = current_tx.lock().unwrap();
+                if tx.as_ref().is_some_and(|sender| sender.is_closed()) {
+                    tx.take();
+                    true
+                } else {
+                    false
+                }
+            };
+
+            if unexpected {
+                *is_running.lock().unwrap() = false;
+                match result {
+                    Ok(()) => println!("[ProxyServer] HTTP 服务器意外退出: {}", addr),
+                    Err(e) => println!("[ProxyServer] HTTP 服务器异常退出: {}: {}", addr, e),
+                }
+            } else {
+                println!("[ProxyServer] HTTP 服务器已停止: {}", addr);
+            }
         });
 
-        Ok(())
+        Ok((shutdown_tx, handle))
     }
 
     /// 停止服务器
@@ -143,6 +181,15 @@ impl ProxyServer {
         Ok(())
     }
 
+    /// 等待 HTTP 服务器处理完进行中的请求后退出，超时返回 false
+    pub async fn wait_stopped(&self, timeout: std::time::Duration) -> bool {
+        let handle = self.server_task.lock().unwrap().take();
+        match handle {
+            Some(handle) => tokio::time::timeout(timeout, handle).await.is_ok(),
+            None => true,
+        }
+    }
+
     /// 是否正在运行
     pub fn is_running(&self) -> bool {
         *self.is_running.lock().unwrap()
@@ -153,10 +200,33 @@ impl ProxyServer {
         self.config.read().await.clone()
     }
 
-    /// 更新配置
-    pub async fn update_config(&self, new_config: ProxyConfig) {
+    /// 更新配置，运行中修改监听地址时先绑定新地址再关闭旧监听，进行中的请求不受影响
+    pub async fn update_config(&self, new_config: ProxyConfig) -> Result<(), String> {
+        new_config.validate()?;
+
+        // 重新绑定时不持有配置锁，避免阻塞正在处理的请求
+        let (old_host, old_port) = {
+            let config = self.config.read().await;
+            (config.host.clone(), config.port)
+        };
+        let address_changed = old_host != new_config.host || old_port != new_config.port;
+
+        if address_changed && self.is_running() {
+            let addr = listen_addr(&new_config)?;
+            let (shutdown_tx, handle) = self.spawn_listener(addr)?;
+
+            let old_tx = self.shutdown_tx.lock().unwrap().replace(shutdown_tx);
+            *self.server_task.lock().unwrap() = Some(handle);
+            if let Some(sender) = old_tx {
+                let _ = sender.send(());
+            }
+            println!("[ProxyServer] 监听地址已切换: {}:{} -> {}", old_host, old_port, addr);
+        }
+
         let mut config = self.config.write().await;
+        log_config_changes(&config, &new_config);
         *config = new_config;
+        Ok(())
     }
 
     /// 获取统计信息
@@ -177,11 +247,23 @@ impl ProxyServer {
 
     /// 同步账号
     pub fn sync_accounts(&self, accounts: Vec<ProxyAccount>) -> usize {
+        self.http_clients
+            .retain_proxies(accounts.iter().filter_map(|acc| acc.outbound_proxy.as_ref()));
         self.account_pool.clear();
         self.account_pool.add_accounts(accounts);
         self.account_pool.get_all_accounts().len()
     }
 
+    /// 获取账号池
+    pub fn account_pool(&self) -> Arc<AccountPool> {
+        self.account_pool.clone()
+    }
+
+    /// 获取共享 HTTP 客户端
+    pub fn http_clients(&self) -> HttpClients {
+        self.http_clients.clone()
+    }
+
     /// 获取账号池信息
     pub fn get_accounts_info(&self) -> (Vec<ProxyAccount>, usize) {
         let accounts = self.account_pool.get_all_accounts();
@@ -189,56 +271,22 @@ impl ProxyServer {
         (accounts, available_count)
     }
 
-    /// 获取可用模型
-    pub async fn get_available_models(&self) -> Result<Vec<Value>, String> {
-        use super::kiro_api::fetch_kiro_models;
-        
-        let accounts = self.account_pool.get_all_accounts();
-        
-        if accounts.is_empty() {
+    /// 获取可用模型，合并账号池中所有可用账号的模型列表
+    ///
+    /// 返回模型列表和是否全部来自缓存
+    pub async fn get_available_models(&self) -> Result<(Vec<Value>, bool), String> {
+        if self.account_pool.get_all_accounts().is_empty() {
             return Err("账号池为空，请先同步账号".to_string());
         }
 
-        // 使用第一个可用账号获取模型列表
-        let account = accounts.first().ok_or("没有可用账号")?;
-        fetch_kiro_models(account).await
-    }
-
-    /// 记录请求
-    fn record_request(&self, log: RequestLog) {
-        let mut logs = self.recent_logs.lock().unwrap();
-        logs.push(log);
-        if logs.len() > 1000 {
-            logs.drain(0..100);
-        }
-    }
-
-    /// 更新统计
-    fn update_stats(
-        &self,
-        success: bool,
-        input_tokens: u64,
-        output_tokens: u64,
-        credits: f64,
-    ) {
-        let mut stats = self.stats.lock().unwrap();
-        let mut session_stats = self.session_stats.lock().unwrap();
-
-        if success {
-            stats.success_requests += 1;
-            session_stats.success_requests += 1;
-        } else {
-            stats.failed_requests += 1;
-            session_stats.failed_requests += 1;
+        let accounts = self.account_pool.get_available_accounts();
+        if accounts.is_empty() {
+            return Err("没有可用账号".to_string());
         }
 
-        stats.total_requests += 1;
-        stats.input_tokens += input_tokens;
-        stats.output_tokens += output_tokens;
-        stats.total_tokens += input_tokens + output_tokens;
-        stats.total_credits += credits;
-
-        session_stats.total_requests += 1;
+        let (models, from_cache) = self.account_pool.model_catalog().models(&self.http_clients, &accounts).await?;
+        let models = models.iter().map(super::translator::kiro_model_to_openai).collect();
+        Ok((models, from_cache))
     }
 
     /// 重置累计统计
@@ -251,5 +299,64 @@ impl ProxyServer {
         stats.total_requests = 0;
         stats.success_requests = 0;
         stats.failed_requests = 0;
+        stats.estimated_credits = 0.0;
+        stats.by_account.clear();
+        stats.by_api_key.clear();
+        stats.by_model.clear();
+    }
+}
+
+/// 解析监听地址
+fn listen_addr(config: &ProxyConfig) -> Result<SocketAddr, String> {
+    format!("{}:{}", config.host, config.port)
+        .parse()
+        .map_err(|_| format!("无效的监听地址: {}，请填写 IP 地址，例如 127.0.0.1 或 0.0.0.0", config.host))
+}
+
+/// 将绑定失败的底层错误转换为可读的提示
+fn bind_error(addr: SocketAddr, err: warp::Error) -> String {
+    use std::error::Error;
+    use std::io::ErrorKind;
+
+    let mut source = err.source();
+    while let Some(e) = source {
+        if let Some(io_err) = e.downcast_ref::<std::io::Error>() {
+            return match io_err.kind() {
+                ErrorKind::AddrInUse => format!("端口 {} 已被占用，请更换端口或关闭占用该端口的程序", addr.port()),
+                ErrorKind::PermissionDenied => format!("没有权限监听 {}，1024 以下的端口需要管理员权限", addr),
+                ErrorKind::AddrNotAvailable => format!("本机没有地址 {}，请改用 127.0.0.1 或 0.0.0.0", addr.ip()),
+                _ => format!("绑定地址 {} 失败: {}", addr, io_err),
+            };
+        }
+        source = e.source();
+    }
+
+    format!("绑定地址 {} 失败: {}", addr, err)
+}
+
+/// 输出热更新时发生变化的配置项
+fn log_config_changes(old: &ProxyConfig, new: &ProxyConfig) {
+    let switch = |enabled: bool| if enabled { "启用" } else { "禁用" };
+
+    if old.enable_openai != new.enable_openai {
+        println!("[ProxyServer] OpenAI API: {}", switch(new.enable_openai));
+    }
+    if old.enable_claude != new.enable_claude {
+        println!("[ProxyServer] Claude API: {}", switch(new.enable_claude));
+    }
+    if old.enable_gemini != new.enable_gemini {
+        println!("[ProxyServer] Gemini API: {}", switch(new.enable_gemini));
+    }
+    if old.log_requests != new.log_requests {
+        println!("[ProxyServer] 请求日志: {}", switch(new.log_requests));
+    }
+
+    let key_count = |c: &ProxyConfig| c.api_keys.as_ref().map(|k| k.len()).unwrap_or(0);
+    if key_count(old) != key_count(new) {
+        println!("[ProxyServer] API Keys: {} 个", key_count(new));
+    }
+    let mapping_count = |c: &ProxyConfig| c.model_mappings.as_ref().map(|m| m.len()).unwrap_or(0);
+    if mapping_count(old) != mapping_count(new) {
+        println!("[ProxyServer] 模型映射: {} 条", mapping_count(new));
     }
 }
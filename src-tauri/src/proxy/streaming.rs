@@ -0,0 +1,989 @@
+// 流式响应 - 上游的事件流边到达边转换为各接口格式的流式事件
+//
+// 请求在后台任务中执行，增量输出经通道转发给响应体；第一段输出前失败时返回普通的错误响应，
+// 之后失败时以该格式的错误事件结束流
+use super::kiro_api::KiroDelta;
+use super::pipeline::{ApiFormat, Completion};
+use super::translator::{
+    chat_output_limits, claude_output_limits, completion_output_limits, gemini_output_limits, kiro_to_claude_response,
+    kiro_to_completion_response, kiro_to_gemini_response, kiro_to_openai_response, kiro_to_responses_response,
+    responses_output_limits, responses_response_object, OutputLimits, StreamTruncator,
+};
+use super::types::*;
+use serde_json::{json, Value};
+use std::convert::Infallible;
+use std::future::Future;
+use tokio::sync::mpsc;
+use warp::http::StatusCode;
+use warp::hyper::Body;
+use warp::reply::Response;
+
+/// 接收上游增量输出的回调
+pub type DeltaSink = Box<dyn FnMut(KiroDelta) + Send>;
+
+/// 把上游的增量输出编码为某种接口格式的流式响应体
+pub trait StreamEncoder: Send + 'static {
+    /// 错误体的格式
+    fn format(&self) -> ApiFormat;
+
+    /// 响应体的 Content-Type
+    fn content_type(&self) -> &'static str {
+        "text/event-stream"
+    }
+
+    /// 本地截断的条件
+    fn limits(&self) -> OutputLimits;
+
+    /// 第一段输出前的事件
+    fn start(&mut self) -> String;
+
+    /// 正文增量，已按截断条件处理
+    fn text(&mut self, text: &str) -> String;
+
+    /// 思考过程增量
+    fn reasoning(&mut self, text: &str) -> String;
+
+    /// 思考过程的签名
+    fn reasoning_signature(&mut self, _signature: &str) -> String {
+        String::new()
+    }
+
+    /// 请求完成：补齐最终结果中 emitted 之后的正文，输出结束事件
+    fn finish(&mut self, completion: &Completion, emitted: &str) -> Result<String, String>;
+
+    /// 输出开始后请求失败，body 为该格式的错误体
+    fn error(&mut self, body: &Value) -> String;
+}
+
+enum StreamMessage {
+    Delta(KiroDelta),
+    Done(Box<Result<Completion, Response>>),
+}
+
+/// 以流式响应执行请求
+///
+/// run 返回执行请求的 future，上游的增量输出交给传入的 DeltaSink
+pub async fn stream_response<E, F, Fut>(encoder: E, run: F) -> Response
+where
+    E: StreamEncoder,
+    F: FnOnce(DeltaSink) -> Fut,
+    Fut: Future<Output = Result<Completion, Response>> + Send + 'static,
+{
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+    let delta_sender = sender.clone();
+    let task = run(Box::new(move |delta| {
+        let _ = delta_sender.send(StreamMessage::Delta(delta));
+    }));
+    // 客户端断开后继续执行到结束，用量照常记录
+    tokio::spawn(async move {
+        let _ = sender.send(StreamMessage::Done(Box::new(task.await)));
+    });
+
+    // 等到第一段输出或请求结束，在此之前失败时保留错误响应的状态码
+    let first = match receiver.recv().await {
+        Some(StreamMessage::Done(result)) => match *result {
+            Err(response) => return response,
+            completed => Some(StreamMessage::Done(Box::new(completed))),
+        },
+        first => first,
+    };
+
+    let content_type = encoder.content_type();
+    let relay = Relay {
+        truncator: StreamTruncator::new(encoder.limits()),
+        encoder,
+        receiver,
+        pending: first,
+        started: false,
+        done: false,
+    };
+    let body = futures::stream::unfold(relay, |mut relay| async move {
+        let chunk = relay.next_chunk().await?;
+        Some((Ok::<_, Infallible>(chunk), relay))
+    });
+
+    warp::http::Response::builder()
+        .header("content-type", content_type)
+        .header("cache-control", "no-cache")
+        .body(Body::wrap_stream(body))
+        .unwrap_or_default()
+}
+
+/// 把通道里的消息依次编码为响应体的数据块
+struct Relay<E> {
+    encoder: E,
+    truncator: StreamTruncator,
+    receiver: mpsc::UnboundedReceiver<StreamMessage>,
+    /// 已收到但还没编码的第一条消息
+    pending: Option<StreamMessage>,
+    started: bool,
+    done: bool,
+}
+
+impl<E: StreamEncoder> Relay<E> {
+    async fn next_chunk(&mut self) -> Option<String> {
+        if self.done {
+            return None;
+        }
+        let message = match self.pending.take() {
+            Some(message) => Some(message),
+            None => self.receiver.recv().await,
+        };
+
+        let mut chunk = String::new();
+        if !self.started {
+            self.started = true;
+            chunk.push_str(&self.encoder.start());
+        }
+        match message {
+            Some(StreamMessage::Delta(KiroDelta::Text(text))) => {
+                let text = self.truncator.push(&text);
+                if !text.is_empty() {
+                    chunk.push_str(&self.encoder.text(&text));
+                }
+            }
+            Some(StreamMessage::Delta(KiroDelta::Reasoning(text))) => chunk.push_str(&self.encoder.reasoning(&text)),
+            Some(StreamMessage::Delta(KiroDelta::ReasoningSignature(signature))) => {
+                chunk.push_str(&self.encoder.reasoning_signature(&signature))
+            }
+            Some(StreamMessage::Done(result)) => {
+                self.done = true;
+                match *result {
+                    Ok(completion) => self.finish(&completion, &mut chunk).await,
+                    Err(response) => chunk.push_str(&self.error_event(response).await),
+                }
+            }
+            None => {
+                self.done = true;
+                let response = self.encoder.format().error("请求意外中断", "api_call_failed", StatusCode::INTERNAL_SERVER_ERROR);
+                chunk.push_str(&self.error_event(response).await);
+            }
+        }
+        Some(chunk)
+    }
+
+    /// 请求完成，输出结束事件；转换失败时输出错误事件
+    async fn finish(&mut self, completion: &Completion, chunk: &mut String) {
+        match self.encoder.finish(completion, self.truncator.emitted()) {
+            Ok(events) => chunk.push_str(&events),
+            Err(e) => {
+                let response = self.encoder.format().error(
+                    format!("响应转换失败: {}", e),
+                    "response_conversion_failed",
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                );
+                chunk.push_str(&self.error_event(response).await);
+            }
+        }
+    }
+
+    /// 把错误响应的响应体编码为错误事件
+    async fn error_event(&mut self, response: Response) -> String {
+        let body = warp::hyper::body::to_bytes(response.into_body())
+            .await
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or(Value::Null);
+        self.encoder.error(&body)
+    }
+}
+
+/// 最终结果中尚未输出的正文；已输出的文本总是最终正文的前缀
+fn remaining_text<'a>(content: &'a str, emitted: &str) -> &'a str {
+    content.strip_prefix(emitted).unwrap_or_default()
+}
+
+/// SSE 事件
+fn sse_event(event_type: &str, data: &Value) -> String {
+    format!("event: {}\ndata: {}\n\n", event_type, data)
+}
+
+/// Chat Completions 的流式数据块，以 `[DONE]` 结束
+pub struct ChatStreamEncoder {
+    request: OpenAIChatRequest,
+    id: String,
+    created: i64,
+    /// 是否输出过思考过程；结构化输出不做增量输出，思考过程在结束时补上
+    reasoning_sent: bool,
+}
+
+impl ChatStreamEncoder {
+    pub fn new(request: &OpenAIChatRequest) -> Self {
+        Self {
+            request: request.clone(),
+            id: format!("chatcmpl-{}", uuid::Uuid::new_v4()),
+            created: chrono::Utc::now().timestamp(),
+            reasoning_sent: false,
+        }
+    }
+
+    fn chunk(&self, delta: Value, finish_reason: Value) -> String {
+        let chunk = json!({
+            "id": self.id,
+            "object": "chat.completion.chunk",
+            "created": self.created,
+            "model": self.request.model,
+            "choices": [{
+                "index": 0,
+                "delta": delta,
+                "finish_reason": finish_reason
+            }]
+        });
+        format!("data: {}\n\n", chunk)
+    }
+}
+
+impl StreamEncoder for ChatStreamEncoder {
+    fn format(&self) -> ApiFormat {
+        ApiFormat::OpenAI
+    }
+
+    fn limits(&self) -> OutputLimits {
+        chat_output_limits(&self.request)
+    }
+
+    fn start(&mut self) -> String {
+        self.chunk(json!({ "role": "assistant", "content": "" }), Value::Null)
+    }
+
+    fn text(&mut self, text: &str) -> String {
+        self.chunk(json!({ "content": text }), Value::Null)
+    }
+
+    fn reasoning(&mut self, text: &str) -> String {
+        self.reasoning_sent = true;
+        self.chunk(json!({ "reasoning_content": text }), Value::Null)
+    }
+
+    fn finish(&mut self, completion: &Completion, emitted: &str) -> Result<String, String> {
+        let response = kiro_to_openai_response(&completion.kiro_response, &self.request)?;
+        let choice = &response["choices"][0];
+
+        let mut chunks = String::new();
+        if let Some(reasoning) = choice["message"]["reasoning_content"].as_str().filter(|r| !r.is_empty() && !self.reasoning_sent) {
+            chunks.push_str(&self.reasoning(reasoning));
+        }
+        let rest = remaining_text(choice["message"]["content"].as_str().unwrap_or_default(), emitted);
+        if !rest.is_empty() {
+            chunks.push_str(&self.text(rest));
+        }
+        chunks.push_str(&self.chunk(json!({}), choice["finish_reason"].clone()));
+        chunks.push_str("data: [DONE]\n\n");
+        Ok(chunks)
+    }
+
+    fn error(&mut self, body: &Value) -> String {
+        format!("data: {}\n\ndata: [DONE]\n\n", body)
+    }
+}
+
+/// Completions 的流式数据块，以 `[DONE]` 结束；旧版接口没有思考过程字段
+pub struct CompletionStreamEncoder {
+    request: CompletionRequest,
+    id: String,
+    created: i64,
+}
+
+impl CompletionStreamEncoder {
+    pub fn new(request: &CompletionRequest) -> Self {
+        Self {
+            request: request.clone(),
+            id: format!("cmpl-{}", uuid::Uuid::new_v4()),
+            created: chrono::Utc::now().timestamp(),
+        }
+    }
+
+    fn chunk(&self, text: &str, finish_reason: Value) -> String {
+        let chunk = json!({
+            "id": self.id,
+            "object": "text_completion",
+            "created": self.created,
+            "model": self.request.model,
+            "choices": [{
+                "text": text,
+                "index": 0,
+                "logprobs": null,
+                "finish_reason": finish_reason
+            }]
+        });
+        format!("data: {}\n\n", chunk)
+    }
+}
+
+impl StreamEncoder for CompletionStreamEncoder {
+    fn format(&self) -> ApiFormat {
+        ApiFormat::OpenAI
+    }
+
+    fn limits(&self) -> OutputLimits {
+        completion_output_limits(&self.request)
+    }
+
+    fn start(&mut self) -> String {
+        String::new()
+    }
+
+    fn text(&mut self, text: &str) -> String {
+        self.chunk(text, Value::Null)
+    }
+
+    fn reasoning(&mut self, _text: &str) -> String {
+        String::new()
+    }
+
+    fn finish(&mut self, completion: &Completion, emitted: &str) -> Result<String, String> {
+        let response = kiro_to_completion_response(&completion.kiro_response, &self.request)?;
+        let choice = &response["choices"][0];
+
+        let mut chunks = String::new();
+        let rest = remaining_text(choice["text"].as_str().unwrap_or_default(), emitted);
+        if !rest.is_empty() {
+            chunks.push_str(&self.text(rest));
+        }
+        chunks.push_str(&self.chunk("", choice["finish_reason"].clone()));
+        chunks.push_str("data: [DONE]\n\n");
+        Ok(chunks)
+    }
+
+    fn error(&mut self, body: &Value) -> String {
+        format!("data: {}\n\ndata: [DONE]\n\n", body)
+    }
+}
+
+/// streamGenerateContent 的数据块：`alt=sse` 时为 SSE，否则为逐步输出的 JSON 数组
+///
+/// 正文增量各为一个数据块，最后一块带函数调用、finishReason 和用量
+pub struct GeminiStreamEncoder {
+    request: GeminiRequest,
+    model: String,
+    sse: bool,
+    /// 已输出的数据块数
+    chunks: usize,
+}
+
+impl GeminiStreamEncoder {
+    pub fn new(request: &GeminiRequest, model: &str, sse: bool) -> Self {
+        Self {
+            request: request.clone(),
+            model: model.to_string(),
+            sse,
+            chunks: 0,
+        }
+    }
+
+    fn chunk(&mut self, data: &Value) -> String {
+        let separator = match (self.sse, self.chunks) {
+            (true, _) => "",
+            (false, 0) => "[",
+            (false, _) => ",",
+        };
+        self.chunks += 1;
+        if self.sse {
+            format!("data: {}\n\n", data)
+        } else {
+            format!("{}{}", separator, data)
+        }
+    }
+
+    /// JSON 数组的结尾
+    fn close(&self) -> &'static str {
+        if self.sse {
+            ""
+        } else {
+            "]"
+        }
+    }
+}
+
+impl StreamEncoder for GeminiStreamEncoder {
+    fn format(&self) -> ApiFormat {
+        ApiFormat::Gemini
+    }
+
+    fn content_type(&self) -> &'static str {
+        if self.sse {
+            "text/event-stream"
+        } else {
+            "application/json"
+        }
+    }
+
+    fn limits(&self) -> OutputLimits {
+        gemini_output_limits(&self.request)
+    }
+
+    fn start(&mut self) -> String {
+        String::new()
+    }
+
+    fn text(&mut self, text: &str) -> String {
+        let data = json!({
+            "candidates": [{
+                "content": { "role": "model", "parts": [{ "text": text }] },
+                "index": 0
+            }],
+            "modelVersion": self.model
+        });
+        self.chunk(&data)
+    }
+
+    fn reasoning(&mut self, _text: &str) -> String {
+        String::new()
+    }
+
+    fn finish(&mut self, completion: &Completion, emitted: &str) -> Result<String, String> {
+        let mut response = kiro_to_gemini_response(&completion.kiro_response, &self.request, &self.model)?;
+
+        // 最后一块只带尚未输出的正文和函数调用
+        let parts = response["candidates"][0]["content"]["parts"].as_array().cloned().unwrap_or_default();
+        let text: String = parts.iter().filter_map(|part| part["text"].as_str()).collect();
+        let rest = remaining_text(&text, emitted);
+        let mut last_parts = Vec::new();
+        if !rest.is_empty() {
+            last_parts.push(json!({ "text": rest }));
+        }
+        last_parts.extend(parts.into_iter().filter(|part| part.get("text").is_none()));
+        response["candidates"][0]["content"]["parts"] = Value::Array(last_parts);
+
+        let mut chunks = self.chunk(&response);
+        chunks.push_str(self.close());
+        Ok(chunks)
+    }
+
+    fn error(&mut self, body: &Value) -> String {
+        let mut chunks = self.chunk(body);
+        chunks.push_str(self.close());
+        chunks
+    }
+}
+
+/// Messages 的流式事件：思考过程和正文各为一个内容块
+pub struct ClaudeStreamEncoder {
+    request: ClaudeRequest,
+    id: String,
+    input_tokens: u64,
+    /// 已开始的内容块数
+    blocks: usize,
+    /// 当前打开的内容块类型
+    open: Option<&'static str>,
+}
+
+impl ClaudeStreamEncoder {
+    /// input_tokens 为本地估算值，message_start 时上游用量还未返回
+    pub fn new(request: &ClaudeRequest, input_tokens: u64) -> Self {
+        Self {
+            request: request.clone(),
+            id: format!("msg_{}", uuid::Uuid::new_v4()),
+            input_tokens,
+            blocks: 0,
+            open: None,
+        }
+    }
+
+    /// 切换到指定类型的内容块，必要时结束当前块并开始新块
+    fn open_block(&mut self, kind: &'static str) -> String {
+        if self.open == Some(kind) {
+            return String::new();
+        }
+        let mut events = self.close_block();
+        let content_block = match kind {
+            "thinking" => json!({ "type": "thinking", "thinking": "" }),
+            _ => json!({ "type": "text", "text": "" }),
+        };
+        events.push_str(&sse_event(
+            "content_block_start",
+            &json!({ "type": "content_block_start", "index": self.blocks, "content_block": content_block }),
+        ));
+        self.blocks += 1;
+        self.open = Some(kind);
+        events
+    }
+
+    fn close_block(&mut self) -> String {
+        match self.open.take() {
+            Some(_) => sse_event("content_block_stop", &json!({ "type": "content_block_stop", "index": self.blocks - 1 })),
+            None => String::new(),
+        }
+    }
+
+    fn delta(&self, delta: Value) -> String {
+        sse_event(
+            "content_block_delta",
+            &json!({ "type": "content_block_delta", "index": self.blocks - 1, "delta": delta }),
+        )
+    }
+}
+
+impl StreamEncoder for ClaudeStreamEncoder {
+    fn format(&self) -> ApiFormat {
+        ApiFormat::Claude
+    }
+
+    fn limits(&self) -> OutputLimits {
+        claude_output_limits(&self.request)
+    }
+
+    fn start(&mut self) -> String {
+        sse_event(
+            "message_start",
+            &json!({
+                "type": "message_start",
+                "message": {
+                    "id": self.id,
+                    "type": "message",
+                    "role": "assistant",
+                    "content": [],
+                    "model": self.request.model,
+                    "stop_reason": null,
+                    "stop_sequence": null,
+                    "usage": { "input_tokens": self.input_tokens, "output_tokens": 0 }
+                }
+            }),
+        )
+    }
+
+    fn text(&mut self, text: &str) -> String {
+        let mut events = self.open_block("text");
+        events.push_str(&self.delta(json!({ "type": "text_delta", "text": text })));
+        events
+    }
+
+    fn reasoning(&mut self, text: &str) -> String {
+        let mut events = self.open_block("thinking");
+        events.push_str(&self.delta(json!({ "type": "thinking_delta", "thinking": text })));
+        events
+    }
+
+    fn reasoning_signature(&mut self, signature: &str) -> String {
+        if self.open != Some("thinking") || signature.is_empty() {
+            return String::new();
+        }
+        self.delta(json!({ "type": "signature_delta", "signature": signature }))
+    }
+
+    fn finish(&mut self, completion: &Completion, emitted: &str) -> Result<String, String> {
+        let response = kiro_to_claude_response(&completion.kiro_response, &self.request)?;
+        let content = response["content"]
+            .as_array()
+            .and_then(|blocks| blocks.iter().find(|block| block["type"] == "text"))
+            .and_then(|block| block["text"].as_str())
+            .unwrap_or_default();
+
+        // 没有任何输出时也返回一个空的文本块
+        let mut events = String::new();
+        let rest = remaining_text(content, emitted);
+        if !rest.is_empty() || self.blocks == 0 {
+            events.push_str(&self.open_block("text"));
+        }
+        if !rest.is_empty() {
+            events.push_str(&self.delta(json!({ "type": "text_delta", "text": rest })));
+        }
+        events.push_str(&self.close_block());
+        events.push_str(&sse_event(
+            "message_delta",
+            &json!({
+                "type": "message_delta",
+                "delta": {
+                    "stop_reason": response["stop_reason"],
+                    "stop_sequence": response["stop_sequence"]
+                },
+                "usage": response["usage"]
+            }),
+        ));
+        events.push_str(&sse_event("message_stop", &json!({ "type": "message_stop" })));
+        Ok(events)
+    }
+
+    fn error(&mut self, body: &Value) -> String {
+        sse_event("error", body)
+    }
+}
+
+/// Responses 的流式事件：正文为一个 message 输出项，工具调用在结束时依次输出
+pub struct ResponsesStreamEncoder {
+    request: ResponsesRequest,
+    /// 进行中的响应对象，id 和 created_at 沿用到最终结果
+    response: Value,
+    message_id: String,
+    sequence_number: usize,
+    /// message 输出项是否已开始
+    message_open: bool,
+}
+
+impl ResponsesStreamEncoder {
+    pub fn new(request: &ResponsesRequest) -> Self {
+        Self {
+            request: request.clone(),
+            response: responses_response_object(request),
+            message_id: format!("msg_{}", uuid::Uuid::new_v4().simple()),
+            sequence_number: 0,
+            message_open: false,
+        }
+    }
+
+    fn event(&mut self, event_type: &str, mut data: Value) -> String {
+        data["type"] = json!(event_type);
+        data["sequence_number"] = json!(self.sequence_number);
+        self.sequence_number += 1;
+        sse_event(event_type, &data)
+    }
+
+    /// 开始 message 输出项和它的 output_text 内容块
+    fn open_message(&mut self) -> String {
+        if self.message_open {
+            return String::new();
+        }
+        self.message_open = true;
+        let item = json!({
+            "type": "message",
+            "id": self.message_id,
+            "status": "in_progress",
+            "role": "assistant",
+            "content": []
+        });
+        let mut events = self.event("response.output_item.added", json!({ "output_index": 0, "item": item }));
+        events.push_str(&self.event(
+            "response.content_part.added",
+            json!({
+                "item_id": self.message_id,
+                "output_index": 0,
+                "content_index": 0,
+                "part": { "type": "output_text", "text": "", "annotations": [] }
+            }),
+        ));
+        events
+    }
+
+    fn text_delta(&mut self, text: &str) -> String {
+        let data = json!({ "item_id": self.message_id, "output_index": 0, "content_index": 0, "delta": text });
+        self.event("response.output_text.delta", data)
+    }
+}
+
+impl StreamEncoder for ResponsesStreamEncoder {
+    fn format(&self) -> ApiFormat {
+        ApiFormat::OpenAI
+    }
+
+    fn limits(&self) -> OutputLimits {
+        responses_output_limits(&self.request)
+    }
+
+    fn start(&mut self) -> String {
+        let response = self.response.clone();
+        let mut events = self.event("response.created", json!({ "response": response }));
+        events.push_str(&self.event("response.in_progress", json!({ "response": response })));
+        events
+    }
+
+    fn text(&mut self, text: &str) -> String {
+        let mut events = self.open_message();
+        events.push_str(&self.text_delta(text));
+        events
+    }
+
+    fn reasoning(&mut self, _text: &str) -> String {
+        String::new()
+    }
+
+    fn finish(&mut self, completion: &Completion, emitted: &str) -> Result<String, String> {
+        let mut response = kiro_to_responses_response(&completion.kiro_response, &self.request)?;
+        response["id"] = self.response["id"].clone();
+        response["created_at"] = self.response["created_at"].clone();
+
+        let mut events = String::new();
+        let items = response["output"].as_array_mut().map(std::mem::take).unwrap_or_default();
+        let mut output = Vec::new();
+        for (output_index, mut item) in items.into_iter().enumerate() {
+            if item["type"] == "message" {
+                item["id"] = json!(self.message_id);
+                let part = item["content"][0].clone();
+                let text = part["text"].as_str().unwrap_or_default();
+                events.push_str(&self.open_message());
+                let rest = remaining_text(text, emitted);
+                if !rest.is_empty() {
+                    events.push_str(&self.text_delta(rest));
+                }
+                let location = json!({ "item_id": self.message_id, "output_index": output_index, "content_index": 0 });
+                let mut done = location.clone();
+                done["text"] = json!(text);
+                events.push_str(&self.event("response.output_text.done", done));
+                let mut done = location;
+                done["part"] = part;
+                events.push_str(&self.event("response.content_part.done", done));
+            } else {
+                let mut added = item.clone();
+                added["status"] = json!("in_progress");
+                added["arguments"] = json!("");
+                events.push_str(&self.event("response.output_item.added", json!({ "output_index": output_index, "item": added })));
+                for (event_type, key) in [("response.function_call_arguments.delta", "delta"), ("response.function_call_arguments.done", "arguments")] {
+                    let mut data = json!({ "item_id": item["id"], "output_index": output_index });
+                    data[key] = item["arguments"].clone();
+                    events.push_str(&self.event(event_type, data));
+                }
+            }
+            events.push_str(&self.event("response.output_item.done", json!({ "output_index": output_index, "item": item })));
+            output.push(item);
+        }
+        response["output"] = Value::Array(output);
+
+        let done_event = match response["status"].as_str() {
+            Some("incomplete") => "response.incomplete",
+            _ => "response.completed",
+        };
+        events.push_str(&self.event(done_event, json!({ "response": response })));
+        Ok(events)
+    }
+
+    fn error(&mut self, body: &Value) -> String {
+        let error = &body["error"];
+        let data = json!({ "code": error["code"], "message": error["message"], "param": null });
+        self.event("error", data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proxy::context_window::{ContextWindow, OverflowPolicy};
+    use crate::proxy::translator::claude_to_kiro;
+
+    fn claude_request(extra: Value) -> ClaudeRequest {
+        let mut request = json!({
+            "model": "claude-sonnet-4",
+            "max_tokens": 1024,
+            "messages": [{ "role": "user", "content": "hi" }]
+        });
+        for (key, value) in extra.as_object().cloned().unwrap_or_default() {
+            request[key] = value;
+        }
+        serde_json::from_value(request).unwrap()
+    }
+
+    fn chat_request(extra: Value) -> OpenAIChatRequest {
+        let mut request = json!({
+            "model": "claude-sonnet-4",
+            "messages": [{ "role": "user", "content": "hi" }]
+        });
+        for (key, value) in extra.as_object().cloned().unwrap_or_default() {
+            request[key] = value;
+        }
+        serde_json::from_value(request).unwrap()
+    }
+
+    fn completion(kiro_response: Value) -> Completion {
+        let window = ContextWindow {
+            max_input_tokens: None,
+            policy: OverflowPolicy::Reject,
+        };
+        Completion {
+            kiro_response,
+            kiro_request: claude_to_kiro(&claude_request(json!({})), &window).unwrap(),
+            account: serde_json::from_value(json!({ "id": "a", "accessToken": "token" })).unwrap(),
+            model: "claude-sonnet-4".to_string(),
+        }
+    }
+
+    /// 依次发送增量后以 result 结束，返回状态码和响应体
+    async fn run_stream(
+        encoder: impl StreamEncoder,
+        deltas: Vec<KiroDelta>,
+        result: Result<Completion, Response>,
+    ) -> (u16, String) {
+        let response = stream_response(encoder, move |mut on_delta| async move {
+            for delta in deltas {
+                on_delta(delta);
+            }
+            result
+        })
+        .await;
+        let status = response.status().as_u16();
+        let bytes = warp::hyper::body::to_bytes(response.into_body()).await.unwrap();
+        (status, String::from_utf8(bytes.to_vec()).unwrap())
+    }
+
+    /// 解析 SSE 的 data 行
+    fn data_lines(body: &str) -> Vec<Value> {
+        body.lines()
+            .filter_map(|line| line.strip_prefix("data: "))
+            .filter(|data| *data != "[DONE]")
+            .map(|data| serde_json::from_str(data).unwrap())
+            .collect()
+    }
+
+    fn texts(deltas: &[&str]) -> Vec<KiroDelta> {
+        deltas.iter().map(|text| KiroDelta::Text(text.to_string())).collect()
+    }
+
+    #[tokio::test]
+    async fn claude_stream_forwards_deltas_and_stops_at_stop_sequence() {
+        let request = claude_request(json!({ "stop_sequences": ["END"] }));
+        let mut deltas = vec![KiroDelta::Reasoning("plan".into()), KiroDelta::ReasoningSignature("sig".into())];
+        deltas.extend(texts(&["Hello E", "ND world"]));
+        let done = completion(json!({
+            "message": "Hello END world",
+            "reasoning": "plan",
+            "reasoningSignature": "sig",
+            "inputTokens": 5,
+            "outputTokens": 4
+        }));
+
+        let (status, body) = run_stream(ClaudeStreamEncoder::new(&request, 3), deltas, Ok(done)).await;
+        assert_eq!(status, 200);
+        let events = data_lines(&body);
+        let types: Vec<&str> = events.iter().map(|e| e["type"].as_str().unwrap()).collect();
+        assert_eq!(
+            types,
+            [
+                "message_start",
+                "content_block_start",
+                "content_block_delta",
+                "content_block_delta",
+                "content_block_stop",
+                "content_block_start",
+                "content_block_delta",
+                "content_block_stop",
+                "message_delta",
+                "message_stop",
+            ]
+        );
+        assert_eq!(events[0]["message"]["usage"]["input_tokens"], 3);
+        assert_eq!(events[2]["delta"]["thinking"], "plan");
+        assert_eq!(events[3]["delta"]["signature"], "sig");
+        assert_eq!(events[5]["index"], 1);
+        assert_eq!(events[6]["delta"]["text"], "Hello ");
+        assert_eq!(events[8]["delta"]["stop_reason"], "stop_sequence");
+        assert_eq!(events[8]["delta"]["stop_sequence"], "END");
+    }
+
+    #[tokio::test]
+    async fn chat_stream_is_a_prefix_of_the_final_truncated_content() {
+        let content = "The quick brown fox jumps over the lazy dog. STOP here";
+        let request = chat_request(json!({ "stop": ["STOP"], "max_tokens": 8 }));
+        let expected = kiro_to_openai_response(&json!({ "message": content }), &request).unwrap();
+        let expected = expected["choices"][0]["message"]["content"].as_str().unwrap().to_string();
+
+        for chunk_size in [1, 3, 10, content.len()] {
+            let pieces: Vec<&str> = content
+                .as_bytes()
+                .chunks(chunk_size)
+                .map(|chunk| std::str::from_utf8(chunk).unwrap())
+                .collect();
+            let done = completion(json!({ "message": content }));
+            let (_, body) = run_stream(ChatStreamEncoder::new(&request), texts(&pieces), Ok(done)).await;
+
+            let chunks = data_lines(&body);
+            let streamed: String = chunks.iter().filter_map(|c| c["choices"][0]["delta"]["content"].as_str()).collect();
+            assert_eq!(streamed, expected, "chunk size {}", chunk_size);
+            assert_eq!(chunks.last().unwrap()["choices"][0]["finish_reason"], "length");
+            assert!(body.ends_with("data: [DONE]\n\n"));
+        }
+    }
+
+    #[tokio::test]
+    async fn responses_stream_emits_text_deltas_then_function_calls() {
+        let request: ResponsesRequest = serde_json::from_value(json!({ "model": "claude-sonnet-4", "input": "hi" })).unwrap();
+        let done = completion(json!({
+            "message": "Hello world",
+            "toolUses": [{ "toolUseId": "call_1", "name": "search", "input": { "q": "kiro" } }]
+        }));
+        let (_, body) = run_stream(ResponsesStreamEncoder::new(&request), texts(&["Hello", " world"]), Ok(done)).await;
+
+        let events = data_lines(&body);
+        let types: Vec<&str> = events.iter().map(|e| e["type"].as_str().unwrap()).collect();
+        assert_eq!(
+            types,
+            [
+                "response.created",
+                "response.in_progress",
+                "response.output_item.added",
+                "response.content_part.added",
+                "response.output_text.delta",
+                "response.output_text.delta",
+                "response.output_text.done",
+                "response.content_part.done",
+                "response.output_item.done",
+                "response.output_item.added",
+                "response.function_call_arguments.delta",
+                "response.function_call_arguments.done",
+                "response.output_item.done",
+                "response.completed",
+            ]
+        );
+        for (index, event) in events.iter().enumerate() {
+            assert_eq!(event["sequence_number"], index);
+        }
+        assert_eq!(events[4]["delta"], "Hello");
+        assert_eq!(events[6]["text"], "Hello world");
+        assert_eq!(events[9]["output_index"], 1);
+        assert_eq!(events[11]["arguments"], "{\"q\":\"kiro\"}");
+
+        let response = &events[13]["response"];
+        assert_eq!(response["id"], events[0]["response"]["id"]);
+        assert_eq!(response["output"][0]["id"], events[2]["item"]["id"]);
+        assert_eq!(response["output"][1]["call_id"], "call_1");
+    }
+
+    #[tokio::test]
+    async fn completion_stream_sends_text_chunks_then_the_finish_reason() {
+        let request: CompletionRequest =
+            serde_json::from_value(json!({ "model": "claude-sonnet-4", "prompt": "Once", "stop": "\n" })).unwrap();
+        let done = completion(json!({ "message": " upon a time\nThe end" }));
+        let (_, body) = run_stream(CompletionStreamEncoder::new(&request), texts(&[" upon", " a time\nThe", " end"]), Ok(done)).await;
+
+        let chunks = data_lines(&body);
+        let streamed: Vec<&str> = chunks.iter().map(|c| c["choices"][0]["text"].as_str().unwrap()).collect();
+        assert_eq!(streamed, [" upon", " a time", ""]);
+        assert_eq!(chunks.last().unwrap()["choices"][0]["finish_reason"], "stop");
+        assert!(chunks.iter().all(|c| c["id"] == chunks[0]["id"] && c["object"] == "text_completion"));
+        assert!(body.ends_with("data: [DONE]\n\n"));
+    }
+
+    #[tokio::test]
+    async fn gemini_stream_writes_a_json_array_or_sse_chunks() {
+        let request: GeminiRequest = serde_json::from_value(json!({
+            "contents": [{ "role": "user", "parts": [{ "text": "hi" }] }]
+        }))
+        .unwrap();
+        let kiro_response = json!({
+            "message": "Hello world",
+            "toolUses": [{ "toolUseId": "t1", "name": "search", "input": { "q": "kiro" } }]
+        });
+
+        let encoder = GeminiStreamEncoder::new(&request, "claude-sonnet-4", false);
+        let (_, body) = run_stream(encoder, texts(&["Hello", " world"]), Ok(completion(kiro_response.clone()))).await;
+        let chunks: Vec<Value> = serde_json::from_str(&body).unwrap();
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks[0]["candidates"][0]["content"]["parts"][0]["text"], "Hello");
+        assert_eq!(chunks[1]["candidates"][0]["content"]["parts"][0]["text"], " world");
+        let last = &chunks[2]["candidates"][0];
+        assert_eq!(last["content"]["parts"], json!([{ "functionCall": { "id": "t1", "name": "search", "args": { "q": "kiro" } } }]));
+        assert_eq!(last["finishReason"], "STOP");
+
+        let encoder = GeminiStreamEncoder::new(&request, "claude-sonnet-4", true);
+        let (_, body) = run_stream(encoder, texts(&["Hello"]), Ok(completion(kiro_response))).await;
+        let chunks = data_lines(&body);
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[1]["candidates"][0]["content"]["parts"][0]["text"], " world");
+        assert!(chunks[1]["usageMetadata"].is_object());
+    }
+
+    #[tokio::test]
+    async fn errors_before_any_output_keep_the_status_code() {
+        let error = ApiFormat::Claude.error("busy", "overloaded", StatusCode::TOO_MANY_REQUESTS);
+        let (status, body) = run_stream(ClaudeStreamEncoder::new(&claude_request(json!({})), 1), Vec::new(), Err(error)).await;
+        assert_eq!(status, 429);
+        assert_eq!(serde_json::from_str::<Value>(&body).unwrap()["error"]["message"], "busy");
+    }
+
+    #[tokio::test]
+    async fn errors_after_output_end_the_stream_with_an_error_event() {
+        let error = ApiFormat::OpenAI.error("upstream failed", "api_call_failed", StatusCode::BAD_GATEWAY);
+        let (status, body) = run_stream(ChatStreamEncoder::new(&chat_request(json!({}))), texts(&["Hi"]), Err(error)).await;
+        assert_eq!(status, 200);
+        let chunks = data_lines(&body);
+        assert_eq!(chunks[1]["choices"][0]["delta"]["content"], "Hi");
+        assert_eq!(chunks.last().unwrap()["error"]["message"], "upstream failed");
+        assert!(body.ends_with("data: [DONE]\n\n"));
+    }
+}
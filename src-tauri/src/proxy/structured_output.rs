@@ -0,0 +1,395 @@
+// 结构化输出 - 处理 OpenAI response_format，注入格式要求并按 JSON Schema 校验模型输出
+use super::types::KiroRequest;
+use serde_json::Value;
+
+/// 校验失败时最多列出的错误数量
+const MAX_REPORTED_ERRORS: usize = 5;
+
+/// Schema 的最大嵌套深度，防止循环 $ref 无限递归
+const MAX_SCHEMA_DEPTH: usize = 64;
+
+/// 单次校验最多检查的 Schema 节点数，防止 anyOf 和 $ref 组合导致指数级展开
+const MAX_SCHEMA_STEPS: usize = 10_000;
+
+/// 客户端要求的输出格式
+#[derive(Debug, Clone)]
+pub enum ResponseFormat {
+    /// 任意 JSON 对象
+    JsonObject,
+    /// 符合指定 JSON Schema 的 JSON
+    JsonSchema { name: String, schema: Value },
+}
+
+impl ResponseFormat {
+    /// 解析 response_format，`text` 或未设置时返回 None
+    pub fn parse(value: Option<&Value>) -> Result<Option<Self>, String> {
+        let Some(value) = value.filter(|v| !v.is_null()) else {
+            return Ok(None);
+        };
+        match value.get("type").and_then(|t| t.as_str()) {
+            Some("text") => Ok(None),
+            Some("json_object") => Ok(Some(Self::JsonObject)),
+            Some("json_schema") => {
+                let spec = value
+                    .get("json_schema")
+                    .ok_or("response_format 缺少 json_schema 字段")?;
+                let name = spec
+                    .get("name")
+                    .and_then(|n| n.as_str())
+                    .unwrap_or("response")
+                    .to_string();
+                let schema = spec.get("schema").cloned().unwrap_or_else(|| serde_json::json!({}));
+                if !schema.is_object() && !schema.is_boolean() {
+                    return Err("response_format.json_schema.schema 必须是对象".to_string());
+                }
+                Ok(Some(Self::JsonSchema { name, schema }))
+            }
+            Some(other) => Err(format!("不支持的 response_format 类型: {}", other)),
+            None => Err("response_format 缺少 type 字段".to_string()),
+        }
+    }
+
+    /// 格式要求：首次请求时合并进系统提示词，重试时追加到用户输入末尾
+    pub fn instructions(&self) -> String {
+        match self {
+            Self::JsonObject => {
+                "Respond with a single valid JSON object only. Do not wrap it in Markdown code fences and do not add any text before or after it.".to_string()
+            }
+            Self::JsonSchema { name, schema } => format!(
+                "Respond with a single valid JSON value that conforms to the JSON Schema \"{}\" below. Do not wrap it in Markdown code fences and do not add any text before or after it.\n\n{}",
+                name,
+                serde_json::to_string_pretty(schema).unwrap_or_default()
+            ),
+        }
+    }
+
+    /// 解析并校验模型输出，成功时返回规范化后的 JSON 文本
+    pub fn validate_output(&self, text: &str) -> Result<String, String> {
+        let value: Value = serde_json::from_str(extract_json_text(text))
+            .map_err(|e| format!("输出不是有效的 JSON: {}", e))?;
+
+        match self {
+            Self::JsonObject => {
+                if !value.is_object() {
+                    return Err("输出不是 JSON 对象".to_string());
+                }
+            }
+            Self::JsonSchema { schema, .. } => {
+                let mut validator = Validator::new(schema);
+                let mut errors = Vec::new();
+                validator.validate(schema, &value, "$", 0, &mut errors);
+                if validator.exhausted {
+                    return Err("JSON Schema 嵌套过深、存在循环引用或过于复杂，无法校验".to_string());
+                }
+                if !errors.is_empty() {
+                    errors.truncate(MAX_REPORTED_ERRORS);
+                    return Err(format!("输出不符合 JSON Schema: {}", errors.join("; ")));
+                }
+            }
+        }
+
+        Ok(value.to_string())
+    }
+
+    /// 构建重新请求：在原对话后附上上次的输出和校验错误
+    pub fn reask_request(&self, request: &KiroRequest, previous_output: &str, error: &str) -> KiroRequest {
+        let mut request = request.clone();
+        let message = &mut request.conversation_state.current_message.user_input_message;
+        message.content = format!(
+            "{}\n\nassistant: {}\n\nuser: Your previous reply was rejected: {}. Reply again with the corrected JSON only.\n\n{}",
+            message.content,
+            previous_output,
+            error,
+            self.instructions()
+        );
+        request
+    }
+}
+
+/// 去掉模型常加的 Markdown 代码块包裹
+fn extract_json_text(text: &str) -> &str {
+    let trimmed = text.trim();
+    let Some(rest) = trimmed.strip_prefix("```") else {
+        return trimmed;
+    };
+    // 跳过 ```json 这样的语言标记
+    let body = rest.split_once('\n').map(|(_, body)| body).unwrap_or(rest);
+    body.trim_end().strip_suffix("```").unwrap_or(body).trim()
+}
+
+/// 解析本地 $ref（#/$defs/... 或 #/definitions/...）
+fn resolve_ref<'a>(root: &'a Value, reference: &str) -> Option<&'a Value> {
+    let pointer = reference.strip_prefix('#')?;
+    root.pointer(pointer)
+}
+
+/// JSON 值是否符合 Schema 中的类型名
+fn type_matches(expected: &str, value: &Value) -> bool {
+    match expected {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        "number" => value.is_number(),
+        "integer" => value.as_f64().is_some_and(|n| n.fract() == 0.0),
+        _ => true,
+    }
+}
+
+/// JSON Schema 校验器，记录已检查的节点数
+struct Validator<'a> {
+    root: &'a Value,
+    steps: usize,
+    /// 超出深度或节点数限制
+    exhausted: bool,
+}
+
+impl<'a> Validator<'a> {
+    fn new(root: &'a Value) -> Self {
+        Self {
+            root,
+            steps: 0,
+            exhausted: false,
+        }
+    }
+
+    /// 按 JSON Schema 的常用关键字校验，错误写入 errors
+    ///
+    /// 支持 type、enum、const、properties、required、additionalProperties、items、
+    /// anyOf/oneOf/allOf、长度和数值范围以及本地 $ref，不认识的关键字忽略
+    fn validate(&mut self, schema: &Value, value: &Value, path: &str, depth: usize, errors: &mut Vec<String>) {
+        self.steps += 1;
+        if self.exhausted || depth > MAX_SCHEMA_DEPTH || self.steps > MAX_SCHEMA_STEPS {
+            self.exhausted = true;
+            errors.push(format!("{} 的 Schema 无法校验", path));
+            return;
+        }
+
+        let schema = match schema {
+            Value::Bool(true) => return,
+            Value::Bool(false) => {
+                errors.push(format!("{} 不允许出现", path));
+                return;
+            }
+            Value::Object(schema) => schema,
+            _ => return,
+        };
+
+        if let Some(reference) = schema.get("$ref").and_then(|r| r.as_str()) {
+            match resolve_ref(self.root, reference) {
+                Some(target) => self.validate(target, value, path, depth + 1, errors),
+                None => errors.push(format!("{} 引用了不存在的定义 {}", path, reference)),
+            }
+        }
+
+        match schema.get("type") {
+            Some(Value::String(expected)) if !type_matches(expected, value) => {
+                errors.push(format!("{} 应为 {}", path, expected));
+                return;
+            }
+            Some(Value::Array(expected)) if !expected.iter().filter_map(|t| t.as_str()).any(|t| type_matches(t, value)) => {
+                let names: Vec<&str> = expected.iter().filter_map(|t| t.as_str()).collect();
+                errors.push(format!("{} 应为 {}", path, names.join(" 或 ")));
+                return;
+            }
+            _ => {}
+        }
+
+        if let Some(options) = schema.get("enum").and_then(|e| e.as_array()) {
+            if !options.contains(value) {
+                errors.push(format!("{} 不在允许的取值中", path));
+            }
+        }
+        if let Some(expected) = schema.get("const") {
+            if expected != value {
+                errors.push(format!("{} 应为 {}", path, expected));
+            }
+        }
+
+        if let Some(all_of) = schema.get("allOf").and_then(|a| a.as_array()) {
+            for sub in all_of {
+                self.validate(sub, value, path, depth + 1, errors);
+            }
+        }
+        if let Some(options) = schema.get("anyOf").and_then(|a| a.as_array()) {
+            if self.count_matches(options, value, path, depth) == 0 {
+                errors.push(format!("{} 不符合 anyOf 中的任何一项", path));
+            }
+        }
+        if let Some(options) = schema.get("oneOf").and_then(|a| a.as_array()) {
+            match self.count_matches(options, value, path, depth) {
+                0 => errors.push(format!("{} 不符合 oneOf 中的任何一项", path)),
+                1 => {}
+                count => errors.push(format!("{} 同时符合 oneOf 中的 {} 项", path, count)),
+            }
+        }
+
+        match value {
+            Value::Object(object) => {
+                let properties = schema.get("properties").and_then(|p| p.as_object());
+                if let Some(required) = schema.get("required").and_then(|r| r.as_array()) {
+                    for key in required.iter().filter_map(|k| k.as_str()) {
+                        if !object.contains_key(key) {
+                            errors.push(format!("{} 缺少必填字段 {}", path, key));
+                        }
+                    }
+                }
+                for (key, item) in object {
+                    let item_path = format!("{}.{}", path, key);
+                    match properties.and_then(|p| p.get(key)) {
+                        Some(sub) => self.validate(sub, item, &item_path, depth + 1, errors),
+                        None => match schema.get("additionalProperties") {
+                            Some(Value::Bool(false)) => errors.push(format!("{} 不允许额外字段", item_path)),
+                            Some(sub @ Value::Object(_)) => self.validate(sub, item, &item_path, depth + 1, errors),
+                            _ => {}
+                        },
+                    }
+                }
+            }
+            Value::Array(items) => {
+                if let Some(min) = schema.get("minItems").and_then(|m| m.as_u64()) {
+                    if (items.len() as u64) < min {
+                        errors.push(format!("{} 至少需要 {} 项", path, min));
+                    }
+                }
+                if let Some(max) = schema.get("maxItems").and_then(|m| m.as_u64()) {
+                    if items.len() as u64 > max {
+                        errors.push(format!("{} 最多 {} 项", path, max));
+                    }
+                }
+                if let Some(sub) = schema.get("items") {
+                    for (index, item) in items.iter().enumerate() {
+                        self.validate(sub, item, &format!("{}[{}]", path, index), depth + 1, errors);
+                    }
+                }
+            }
+            Value::String(text) => {
+                let length = text.chars().count() as u64;
+                if let Some(min) = schema.get("minLength").and_then(|m| m.as_u64()) {
+                    if length < min {
+                        errors.push(format!("{} 长度至少为 {}", path, min));
+                    }
+                }
+                if let Some(max) = schema.get("maxLength").and_then(|m| m.as_u64()) {
+                    if length > max {
+                        errors.push(format!("{} 长度最多为 {}", path, max));
+                    }
+                }
+            }
+            Value::Number(number) => {
+                let number = number.as_f64().unwrap_or_default();
+                if let Some(min) = schema.get("minimum").and_then(|m| m.as_f64()) {
+                    if number < min {
+                        errors.push(format!("{} 不能小于 {}", path, min));
+                    }
+                }
+                if let Some(max) = schema.get("maximum").and_then(|m| m.as_f64()) {
+                    if number > max {
+                        errors.push(format!("{} 不能大于 {}", path, max));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// 符合的子 Schema 数量
+    fn count_matches(&mut self, options: &[Value], value: &Value, path: &str, depth: usize) -> usize {
+        options
+            .iter()
+            .filter(|sub| {
+                let mut sub_errors = Vec::new();
+                self.validate(sub, value, path, depth + 1, &mut sub_errors);
+                sub_errors.is_empty()
+            })
+            .count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn validate(schema: Value, value: Value) -> Result<String, String> {
+        ResponseFormat::JsonSchema {
+            name: "test".to_string(),
+            schema,
+        }
+        .validate_output(&value.to_string())
+    }
+
+    #[test]
+    fn extract_json_text_strips_code_fences() {
+        assert_eq!(extract_json_text("  {\"a\":1}  "), "{\"a\":1}");
+        assert_eq!(extract_json_text("```json\n{\"a\":1}\n```"), "{\"a\":1}");
+        assert_eq!(extract_json_text("```\n[1, 2]\n```\n"), "[1, 2]");
+        assert_eq!(extract_json_text("```json\n{\"a\":1}"), "{\"a\":1}");
+    }
+
+    #[test]
+    fn validates_types_required_and_additional_properties() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "name": { "type": "string", "minLength": 1 },
+                "age": { "type": ["integer", "null"], "minimum": 0 }
+            },
+            "required": ["name"],
+            "additionalProperties": false
+        });
+        assert!(validate(schema.clone(), json!({ "name": "a", "age": 3 })).is_ok());
+        assert!(validate(schema.clone(), json!({ "name": "a", "age": null })).is_ok());
+        assert!(validate(schema.clone(), json!({ "age": 3 })).unwrap_err().contains("缺少必填字段 name"));
+        assert!(validate(schema.clone(), json!({ "name": "a", "age": 1.5 })).is_err());
+        assert!(validate(schema.clone(), json!({ "name": "" })).is_err());
+        assert!(validate(schema, json!({ "name": "a", "extra": 1 })).unwrap_err().contains("不允许额外字段"));
+    }
+
+    #[test]
+    fn follows_local_refs() {
+        let schema = json!({
+            "$defs": { "item": { "type": "string", "enum": ["x", "y"] } },
+            "type": "array",
+            "items": { "$ref": "#/$defs/item" },
+            "maxItems": 2
+        });
+        assert!(validate(schema.clone(), json!(["x", "y"])).is_ok());
+        assert!(validate(schema.clone(), json!(["z"])).is_err());
+        assert!(validate(schema, json!(["x", "x", "x"])).is_err());
+    }
+
+    #[test]
+    fn one_of_requires_exactly_one_match() {
+        let schema = json!({ "oneOf": [{ "type": "integer" }, { "type": "number" }, { "type": "string" }] });
+        assert!(validate(schema.clone(), json!("a")).is_ok());
+        assert!(validate(schema.clone(), json!(1)).unwrap_err().contains("同时符合 oneOf"));
+        assert!(validate(schema, json!(true)).is_err());
+
+        let any_of = json!({ "anyOf": [{ "type": "integer" }, { "type": "number" }] });
+        assert!(validate(any_of, json!(1)).is_ok());
+    }
+
+    #[test]
+    fn circular_refs_are_rejected_instead_of_overflowing() {
+        let err = validate(json!({ "$ref": "#" }), json!({})).unwrap_err();
+        assert!(err.contains("无法校验"));
+
+        let schema = json!({ "$defs": { "a": { "$ref": "#/$defs/a" } }, "$ref": "#/$defs/a" });
+        assert!(validate(schema, json!(1)).is_err());
+
+        let schema = json!({ "anyOf": [{ "$ref": "#" }, { "$ref": "#" }] });
+        assert!(validate(schema, json!(1)).is_err());
+    }
+
+    #[test]
+    fn json_object_format_requires_object() {
+        assert_eq!(
+            ResponseFormat::JsonObject.validate_output("```json\n{ \"a\": 1 }\n```").unwrap(),
+            "{\"a\":1}"
+        );
+        assert!(ResponseFormat::JsonObject.validate_output("[1]").is_err());
+        assert!(ResponseFormat::JsonObject.validate_output("not json").is_err());
+    }
+}
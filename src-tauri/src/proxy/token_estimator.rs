@@ -0,0 +1,326 @@
+// Token 估算 - 本地近似计算，用于 count_tokens 和上游未返回用量时的补充
+use super::types::{CompletionRequest, GeminiRequest, OpenAIChatRequest, ResponsesRequest};
+use serde_json::Value;
+
+/// 每条消息的格式开销（角色标记、分隔符）
+const MESSAGE_OVERHEAD: u64 = 4;
+
+/// 每个请求的固定开销
+const REQUEST_OVERHEAD: u64 = 3;
+
+/// 每个工具定义的格式开销
+const TOOL_OVERHEAD: u64 = 8;
+
+/// 图片按约 1092x1092 估算（宽 x 高 / 750）
+const IMAGE_TOKENS: u64 = 1600;
+
+/// 估算文本的 token 数
+///
+/// 英文和数字按约 4 个字符 1 个 token，标点单独计 1 个，
+/// 中日韩文字和其他非 ASCII 字符按每个字符 1 个 token，空白不计。
+pub fn estimate_text_tokens(text: &str) -> u64 {
+    let mut tokens = 0u64;
+    let mut word_len = 0u64;
+
+    for c in text.chars() {
+        if c.is_ascii_alphanumeric() {
+            word_len += 1;
+            continue;
+        }
+        tokens += word_len.div_ceil(4);
+        word_len = 0;
+
+        if c.is_whitespace() {
+            continue;
+        }
+        tokens += 1;
+    }
+
+    tokens + word_len.div_ceil(4)
+}
+
+/// 按估算的 token 数截断文本，未超出时返回 None
+pub fn truncate_to_token_limit(text: &str, max_tokens: u64) -> Option<&str> {
+    if estimate_text_tokens(text) <= max_tokens {
+        return None;
+    }
+
+    // 前缀的估算值随长度单调不减，二分查找最长的合规前缀
+    let boundaries: Vec<usize> = text.char_indices().map(|(i, _)| i).chain([text.len()]).collect();
+    let (mut low, mut high) = (0, boundaries.len() - 1);
+    while low < high {
+        let mid = (low + high).div_ceil(2);
+        if estimate_text_tokens(&text[..boundaries[mid]]) <= max_tokens {
+            low = mid;
+        } else {
+            high = mid - 1;
+        }
+    }
+    Some(&text[..boundaries[low]])
+}
+
+/// 估算消息内容：字符串或内容块数组（Claude 内容块或 OpenAI 内容片段）
+fn estimate_claude_content(content: &Value) -> u64 {
+    match content {
+        Value::String(text) => estimate_text_tokens(text),
+        Value::Array(blocks) => blocks.iter().map(estimate_claude_block).sum(),
+        Value::Null => 0,
+        other => estimate_json_tokens(other),
+    }
+}
+
+/// 估算单个内容块
+fn estimate_claude_block(block: &Value) -> u64 {
+    let text_field = |key: &str| {
+        block
+            .get(key)
+            .and_then(|v| v.as_str())
+            .map(estimate_text_tokens)
+            .unwrap_or(0)
+    };
+
+    match block.get("type").and_then(|t| t.as_str()) {
+        // Responses API 使用 input_text / output_text
+        Some("text") | Some("input_text") | Some("output_text") => text_field("text"),
+        Some("thinking") => text_field("thinking"),
+        Some("image") | Some("document") | Some("input_image") | Some("input_file") => IMAGE_TOKENS,
+        Some("tool_use") => {
+            text_field("name") + block.get("input").map(estimate_json_tokens).unwrap_or(0)
+        }
+        Some("tool_result") => {
+            MESSAGE_OVERHEAD + block.get("content").map(estimate_claude_content).unwrap_or(0)
+        }
+        _ => match block {
+            Value::String(text) => estimate_text_tokens(text),
+            other => estimate_json_tokens(other),
+        },
+    }
+}
+
+/// 按紧凑 JSON 文本估算
+fn estimate_json_tokens(value: &Value) -> u64 {
+    estimate_text_tokens(&value.to_string())
+}
+
+/// 估算 Claude Messages 请求的输入 token（system、messages、tools）
+///
+/// 直接读取请求 JSON，兼容字符串和内容块数组两种写法
+pub fn estimate_claude_input_tokens(body: &Value) -> u64 {
+    let mut tokens = REQUEST_OVERHEAD;
+
+    if let Some(system) = body.get("system") {
+        tokens += MESSAGE_OVERHEAD + estimate_claude_content(system);
+    }
+
+    if let Some(messages) = body.get("messages").and_then(|m| m.as_array()) {
+        for message in messages {
+            tokens += MESSAGE_OVERHEAD;
+            tokens += message.get("content").map(estimate_claude_content).unwrap_or(0);
+        }
+    }
+
+    if let Some(tools) = body.get("tools").and_then(|t| t.as_array()) {
+        for tool in tools {
+            tokens += TOOL_OVERHEAD + estimate_json_tokens(tool);
+        }
+    }
+
+    tokens
+}
+
+/// 估算 OpenAI Chat 请求的输入 token
+pub fn estimate_openai_input_tokens(request: &OpenAIChatRequest) -> u64 {
+    REQUEST_OVERHEAD
+        + request
+            .messages
+            .iter()
+            .map(|msg| MESSAGE_OVERHEAD + estimate_text_tokens(&msg.role) + estimate_claude_content(&msg.content))
+            .sum::<u64>()
+}
+
+/// 估算 OpenAI Responses 请求的输入 token（instructions、input、tools）
+pub fn estimate_responses_input_tokens(request: &ResponsesRequest) -> u64 {
+    let mut tokens = REQUEST_OVERHEAD;
+
+    if let Some(instructions) = &request.instructions {
+        tokens += MESSAGE_OVERHEAD + estimate_text_tokens(instructions);
+    }
+
+    match &request.input {
+        Value::Array(items) => {
+            for item in items {
+                let text_field = |key: &str| {
+                    item.get(key).and_then(|v| v.as_str()).map(estimate_text_tokens).unwrap_or(0)
+                };
+                tokens += MESSAGE_OVERHEAD;
+                tokens += match item.get("type").and_then(|t| t.as_str()).unwrap_or("message") {
+                    "function_call" => text_field("name") + text_field("arguments"),
+                    "function_call_output" => item.get("output").map(estimate_claude_content).unwrap_or(0),
+                    _ => item.get("content").map(estimate_claude_content).unwrap_or(0),
+                };
+            }
+        }
+        other => tokens += MESSAGE_OVERHEAD + estimate_claude_content(other),
+    }
+
+    for tool in request.tools.iter().flatten() {
+        tokens += TOOL_OVERHEAD + estimate_json_tokens(tool);
+    }
+
+    tokens
+}
+
+/// 估算 OpenAI Completions 请求的输入 token（prompt、suffix）
+pub fn estimate_completion_input_tokens(request: &CompletionRequest) -> u64 {
+    REQUEST_OVERHEAD
+        + estimate_claude_content(&request.prompt)
+        + request.suffix.as_deref().map(estimate_text_tokens).unwrap_or(0)
+}
+
+/// 估算 Gemini generateContent 请求的输入 token（systemInstruction、contents、tools）
+pub fn estimate_gemini_input_tokens(request: &GeminiRequest) -> u64 {
+    let mut tokens = REQUEST_OVERHEAD;
+
+    let contents = request.system_instruction.iter().chain(&request.contents);
+    for content in contents {
+        tokens += MESSAGE_OVERHEAD;
+        for part in &content.parts {
+            tokens += match part.get("text").and_then(|t| t.as_str()) {
+                Some(text) => estimate_text_tokens(text),
+                None if part.get("inlineData").is_some() || part.get("fileData").is_some() => IMAGE_TOKENS,
+                None => estimate_json_tokens(part),
+            };
+        }
+    }
+
+    let declarations = request.tools.iter().flatten().flat_map(|tool| tool.function_declarations.iter().flatten());
+    for declaration in declarations {
+        tokens += TOOL_OVERHEAD + estimate_json_tokens(&serde_json::to_value(declaration).unwrap_or_default());
+    }
+
+    tokens
+}
+
+/// 上游未返回计量信息时，按模型倍率估算 credits 并标记 creditsEstimated
+///
+/// 倍率来自模型列表（ListAvailableModels）的 rateMultiplier，即该模型每次请求消耗的 credits（单位为 rateUnit），
+/// 自动继续的每一轮都按一次请求计；模型列表中没有该模型时无从估算，credits 留空，只标记为估算
+pub fn fill_missing_credits(kiro_response: &mut Value, rate_multiplier: Option<f64>) {
+    let Some(obj) = kiro_response.as_object_mut() else {
+        return;
+    };
+    if obj.get("credits").and_then(|c| c.as_f64()).is_some() {
+        obj.insert("creditsEstimated".to_string(), Value::Bool(false));
+        return;
+    }
+
+    obj.insert("creditsEstimated".to_string(), Value::Bool(true));
+    let Some(rate_multiplier) = rate_multiplier else {
+        println!("[TokenEstimator] 上游未返回计量信息，模型倍率未知，不估算 credits");
+        return;
+    };
+    let rounds = obj.get("rounds").and_then(|r| r.as_u64()).unwrap_or(1).max(1);
+    let credits = rate_multiplier * rounds as f64;
+    println!("[TokenEstimator] 上游未返回计量信息，按倍率估算 credits 为 {}", credits);
+    obj.insert("credits".to_string(), Value::from(credits));
+}
+
+/// 把另一次上游调用（如结构化输出的重新请求）的用量累加到 target，任一次为估算值时标记为估算
+pub fn add_usage(target: &mut Value, other: &Value) {
+    for key in ["inputTokens", "outputTokens"] {
+        let total = target.get(key).and_then(|t| t.as_u64()).unwrap_or(0)
+            + other.get(key).and_then(|t| t.as_u64()).unwrap_or(0);
+        target[key] = Value::from(total);
+    }
+    // 两次都没有 credits（模型倍率未知）时保持留空
+    let credits: Vec<f64> = [&*target, other].iter().filter_map(|v| v.get("credits").and_then(|c| c.as_f64())).collect();
+    if !credits.is_empty() {
+        target["credits"] = Value::from(credits.iter().sum::<f64>());
+    }
+    let estimated = [&*target, other]
+        .iter()
+        .any(|v| v.get("creditsEstimated").and_then(|e| e.as_bool()).unwrap_or(false));
+    target["creditsEstimated"] = Value::Bool(estimated);
+}
+
+/// 上游未返回用量时，按估算值补充 inputTokens 和 outputTokens
+pub fn fill_missing_usage(kiro_response: &mut Value, estimated_input_tokens: u64) {
+    let Some(obj) = kiro_response.as_object_mut() else {
+        return;
+    };
+    let is_missing = |obj: &serde_json::Map<String, Value>, key: &str| {
+        obj.get(key).and_then(|t| t.as_u64()).unwrap_or(0) == 0
+    };
+
+    if is_missing(obj, "inputTokens") {
+        println!("[TokenEstimator] 上游未返回输入 token，估算为 {}", estimated_input_tokens);
+        obj.insert("inputTokens".to_string(), Value::from(estimated_input_tokens));
+    }
+    if is_missing(obj, "outputTokens") {
+        // 思考过程同样计入输出
+        let output_tokens: u64 = ["message", "reasoning"]
+            .iter()
+            .filter_map(|key| obj.get(*key).and_then(|m| m.as_str()))
+            .map(estimate_text_tokens)
+            .sum();
+        println!("[TokenEstimator] 上游未返回输出 token，估算为 {}", output_tokens);
+        obj.insert("outputTokens".to_string(), Value::from(output_tokens));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn estimates_text_tokens_by_word_punctuation_and_cjk() {
+        assert_eq!(estimate_text_tokens(""), 0);
+        assert_eq!(estimate_text_tokens("   \n"), 0);
+        // "hello" 5 个字符 → 2，"world" → 2，逗号和感叹号各 1
+        assert_eq!(estimate_text_tokens("hello, world!"), 6);
+        assert_eq!(estimate_text_tokens("你好世界"), 4);
+        assert_eq!(estimate_text_tokens("abcd1234"), 2);
+    }
+
+    #[test]
+    fn truncates_to_the_longest_prefix_within_the_limit() {
+        assert_eq!(truncate_to_token_limit("one two", 5), None);
+        let truncated = truncate_to_token_limit("one two three four", 2).unwrap();
+        assert_eq!(truncated, "one two ");
+        assert!(estimate_text_tokens(truncated) <= 2);
+        assert_eq!(truncate_to_token_limit("你好世界", 3), Some("你好世"));
+    }
+
+    #[test]
+    fn fills_zero_or_missing_usage_with_estimates() {
+        let mut response = json!({ "message": "hello world", "reasoning": "plan", "inputTokens": 0 });
+        fill_missing_usage(&mut response, 42);
+        assert_eq!(response["inputTokens"], 42);
+        // 正文 4 + 思考过程 1
+        assert_eq!(response["outputTokens"], 5);
+
+        let mut response = json!({ "message": "hello world", "inputTokens": 7, "outputTokens": 9 });
+        fill_missing_usage(&mut response, 42);
+        assert_eq!(response["inputTokens"], 7);
+        assert_eq!(response["outputTokens"], 9);
+    }
+
+    #[test]
+    fn keeps_metered_credits_and_estimates_missing_ones_from_the_multiplier() {
+        let mut response = json!({ "credits": 0.0 });
+        fill_missing_credits(&mut response, Some(2.0));
+        assert_eq!(response["credits"], 0.0);
+        assert_eq!(response["creditsEstimated"], false);
+
+        let mut response = json!({ "rounds": 3 });
+        fill_missing_credits(&mut response, Some(1.5));
+        assert_eq!(response["credits"], 4.5);
+        assert_eq!(response["creditsEstimated"], true);
+
+        let mut response = json!({});
+        fill_missing_credits(&mut response, None);
+        assert!(response.get("credits").is_none());
+        assert_eq!(response["creditsEstimated"], true);
+    }
+}
@@ -0,0 +1,103 @@
+// 账号池 Token 自动刷新
+use super::account_pool::AccountPool;
+use super::account_source::{save_refreshed_tokens, RefreshedToken};
+use crate::auth::refresh_access_token;
+use crate::http_client::HttpClients;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// 距离过期不足该时间（毫秒）即刷新
+const REFRESH_AHEAD_MS: i64 = 5 * 60 * 1000;
+
+/// 刷新账号池中即将过期的 Token，返回成功刷新的数量
+///
+/// 指定了账号文件时把新令牌写回文件，OIDC 的 refresh token 会轮换，重启后要用新的值
+pub async fn refresh_expiring_accounts(
+    pool: &AccountPool,
+    http_clients: &HttpClients,
+    accounts_path: Option<&Path>,
+) -> usize {
+    let now = chrono::Utc::now().timestamp_millis();
+    let mut refreshed = Vec::new();
+
+    for account in pool.get_all_accounts() {
+        let expires_at = account.expires_at.unwrap_or(0);
+        if expires_at - now > REFRESH_AHEAD_MS {
+            continue;
+        }
+
+        let label = account.email.as_deref().unwrap_or(&account.id);
+
+        // 社交登录账号没有 OIDC 客户端凭证，无法在此刷新；过期后暂停使用，避免每个请求都失败
+        let (Some(refresh_token), Some(client_id), Some(client_secret)) = (
+            account.refresh_token.as_deref(),
+            account.client_id.as_deref(),
+            account.client_secret.as_deref(),
+        ) else {
+            if expires_at <= now && account.is_available {
+                println!(
+                    "[TokenRefresh] 账号 {} 已过期且没有 OIDC 客户端凭证，无法自动刷新，已暂停使用，请在管理器中刷新后重新导出",
+                    label
+                );
+                pool.mark_needs_refresh(&account.id);
+            }
+            continue;
+        };
+
+        let region = account.region.as_deref().unwrap_or("us-east-1");
+
+        let result = match http_clients.for_proxy(account.outbound_proxy.as_ref()) {
+            Ok(client) => refresh_access_token(&client, refresh_token, client_id, client_secret, region).await,
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(token) => {
+                let expires_at = chrono::Utc::now().timestamp_millis() + token.expires_in as i64 * 1000;
+                pool.update_token(&account.id, token.access_token.clone(), token.refresh_token.clone(), Some(expires_at));
+                println!("[TokenRefresh] 账号 {} 刷新成功", label);
+                refreshed.push(RefreshedToken {
+                    account_id: account.id.clone(),
+                    access_token: token.access_token,
+                    refresh_token: token.refresh_token,
+                    expires_at,
+                });
+            }
+            Err(e) => {
+                println!("[TokenRefresh] 账号 {} 刷新失败: {}", label, e);
+                // 已过期的账号暂停使用，等待下次刷新
+                if expires_at <= now {
+                    pool.mark_needs_refresh(&account.id);
+                }
+            }
+        }
+    }
+
+    if let Some(path) = accounts_path.filter(|_| !refreshed.is_empty()) {
+        match save_refreshed_tokens(path, &refreshed) {
+            Ok(()) => println!("[TokenRefresh] 已将 {} 个账号的新令牌写回 {:?}", refreshed.len(), path),
+            Err(e) => println!("[TokenRefresh] 写回账号文件失败: {}", e),
+        }
+    }
+
+    refreshed.len()
+}
+
+/// 启动后台刷新任务，`accounts_path` 为刷新结果写回的账号文件
+pub fn spawn_token_refresher(
+    pool: Arc<AccountPool>,
+    http_clients: HttpClients,
+    accounts_path: Option<PathBuf>,
+    interval: Duration,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            let count = refresh_expiring_accounts(&pool, &http_clients, accounts_path.as_deref()).await;
+            if count > 0 {
+                println!("[TokenRefresh] 本轮刷新 {} 个账号", count);
+            }
+        }
+    })
+}
@@ -1,16 +1,34 @@
 // API 格式转换器
+use super::context_window::{ContextWindow, Turn};
+use super::kiro_api::stopped_for_length;
+use super::structured_output::ResponseFormat;
+use super::token_estimator::{estimate_text_tokens, truncate_to_token_limit};
 use super::types::*;
+use crate::model_list::ModelInfo;
 use serde_json::{json, Value};
 
-/// OpenAI 格式转换为 Kiro 格式
-pub fn openai_to_kiro(request: &OpenAIChatRequest) -> KiroRequest {
-    // 合并所有消息为一个字符串
-    let content = request
-        .messages
-        .iter()
-        .map(|msg| format!("{}: {}", msg.role, msg.content))
-        .collect::<Vec<_>>()
-        .join("\n\n");
+/// 系统提示词所在轮次的助手回复
+const SYSTEM_PROMPT_ACK: &str = "Understood. I will follow these instructions.";
+
+/// 构建 Kiro 请求，对话消息合并为一条用户输入
+///
+/// Kiro 的 conversationState 没有系统提示词字段，历史消息也只有 user 和 assistant 两种角色，
+/// 所以系统提示词只能作为历史中的第一轮对话发送：用 <system_instructions> 标签包裹标明是指令，
+/// 并附上助手的确认回复；没有其他消息时直接作为用户输入
+fn build_kiro_request(system: Option<String>, content: String, tools: Option<Vec<KiroTool>>) -> KiroRequest {
+    let (content, history) = match system {
+        Some(system) if !content.trim().is_empty() => (
+            content,
+            vec![
+                KiroHistoryMessage::User {
+                    content: format!("<system_instructions>\n{}\n</system_instructions>", system),
+                },
+                KiroHistoryMessage::Assistant { content: SYSTEM_PROMPT_ACK.to_string() },
+            ],
+        ),
+        Some(system) => (system, Vec::new()),
+        None => (content, Vec::new()),
+    };
 
     KiroRequest {
         conversation_state: KiroConversationState {
@@ -27,61 +45,215 @@ pub fn openai_to_kiro(request: &OpenAIChatRequest) -> KiroRequest {
                                 text: String::new(),
                             },
                         },
+                        tools,
                     },
                     user_intent: "SUGGEST_ALTERNATE_IMPLEMENTATION".to_string(),
                 },
             },
             chat_trigger_type: "MANUAL".to_string(),
+            history,
         },
     }
 }
 
-/// Claude 格式转换为 Kiro 格式
-pub fn claude_to_kiro(request: &ClaudeRequest) -> KiroRequest {
-    let mut content = String::new();
-    
-    // 添加系统消息
-    if let Some(system) = &request.system {
-        content.push_str(&format!("System: {}\n\n", system));
+/// 按上下文窗口裁剪对话后构建 Kiro 请求
+///
+/// prefix（思考模式标记等）放在对话开头，和系统提示词、工具定义一样不参与裁剪
+fn build_fitted_request(
+    window: &ContextWindow,
+    system: Option<String>,
+    prefix: String,
+    turns: Vec<Turn>,
+    tools: Option<Vec<KiroTool>>,
+) -> Result<KiroRequest, String> {
+    let tool_tokens = tools
+        .as_ref()
+        .map(|tools| estimate_text_tokens(&serde_json::to_string(tools).unwrap_or_default()))
+        .unwrap_or(0);
+    let fixed_tokens = estimate_text_tokens(&prefix) + system.as_deref().map(estimate_text_tokens).unwrap_or(0) + tool_tokens;
+    let turns = window.fit(turns, fixed_tokens)?;
+
+    let content = prefix + &turns.into_iter().map(|turn| turn.text).collect::<Vec<_>>().join("\n\n");
+    Ok(build_kiro_request(system, content, tools))
+}
+
+/// 按出现顺序合并多段系统提示词，忽略空白段落
+fn merge_system_prompts(prompts: impl IntoIterator<Item = String>) -> Option<String> {
+    let prompts: Vec<String> = prompts
+        .into_iter()
+        .map(|p| p.trim().to_string())
+        .filter(|p| !p.is_empty())
+        .collect();
+    if prompts.is_empty() {
+        None
+    } else {
+        Some(prompts.join("\n\n"))
     }
-    
-    // 添加对话消息
-    for msg in &request.messages {
-        content.push_str(&format!("{}: {}\n\n", msg.role, msg.content));
+}
+
+/// 提取 Claude system 的文本，兼容字符串和内容块数组；Kiro 没有提示词缓存，cache_control 忽略
+fn claude_system_prompts(system: &Value) -> Vec<String> {
+    match system {
+        Value::String(text) => vec![text.clone()],
+        Value::Array(blocks) => blocks
+            .iter()
+            .filter_map(|block| match block {
+                Value::String(text) => Some(text.clone()),
+                _ => block.get("text").and_then(|t| t.as_str()).map(str::to_string),
+            })
+            .collect(),
+        _ => Vec::new(),
     }
+}
 
-    KiroRequest {
-        conversation_state: KiroConversationState {
-            current_message: KiroMessage {
-                user_input_message: KiroUserInputMessage {
-                    content,
-                    user_input_message_context: KiroUserInputMessageContext {
-                        editor_state: KiroEditorState {
-                            document: KiroDocument {
-                                relative_file_path: "untitled.txt".to_string(),
-                                programming_language: KiroProgrammingLanguage {
-                                    language_name: "plaintext".to_string(),
-                                },
-                                text: String::new(),
-                            },
-                        },
-                    },
-                    user_intent: "SUGGEST_ALTERNATE_IMPLEMENTATION".to_string(),
-                },
-            },
-            chat_trigger_type: "MANUAL".to_string(),
-        },
+/// 提取 Claude 消息内容的文本，兼容字符串和内容块数组
+///
+/// thinking 块是客户端回传的上一轮思考过程，不再发给上游；工具调用和结果转为文本
+fn claude_content_text(content: &Value) -> String {
+    match content {
+        Value::String(text) => text.clone(),
+        Value::Array(blocks) => blocks
+            .iter()
+            .filter_map(|block| {
+                let field = |key: &str| block.get(key).and_then(|v| v.as_str()).unwrap_or_default();
+                match block.get("type").and_then(|t| t.as_str()) {
+                    Some("text") => Some(field("text").to_string()),
+                    Some("tool_use") => Some(format!(
+                        "[调用工具 {} ({})] {}",
+                        field("name"),
+                        field("id"),
+                        block.get("input").map(|input| input.to_string()).unwrap_or_default()
+                    )),
+                    Some("tool_result") => Some(format!(
+                        "[工具结果 ({})] {}",
+                        field("tool_use_id"),
+                        claude_content_text(block.get("content").unwrap_or(&Value::Null))
+                    )),
+                    Some("thinking") | Some("redacted_thinking") => None,
+                    Some(other) => Some(format!("[{}]", other)),
+                    None => None,
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("\n"),
+        _ => String::new(),
     }
 }
 
-/// Kiro 响应转换为 OpenAI 格式
-pub fn kiro_to_openai_response(
-    kiro_response: &Value,
-    model: &str,
-) -> Result<Value, String> {
-    // 打印原始响应用于调试
-    println!("[Translator] Kiro 原始响应: {}", serde_json::to_string_pretty(kiro_response).unwrap_or_default());
+/// 消息是否包含工具结果，上下文超限时 summarize 策略只截断这类消息
+fn is_claude_tool_result(content: &Value) -> bool {
+    content
+        .as_array()
+        .is_some_and(|blocks| blocks.iter().any(|block| block.get("type").and_then(|t| t.as_str()) == Some("tool_result")))
+}
+
+/// Kiro 对话接口没有采样参数，temperature 和 top_p 无法传给上游，只记录日志
+fn log_unsupported_sampling(temperature: Option<f32>, top_p: Option<f32>) {
+    if temperature.is_some() || top_p.is_some() {
+        println!(
+            "[Translator] 上游不支持采样参数，已忽略 temperature={:?} top_p={:?}",
+            temperature, top_p
+        );
+    }
+}
+
+/// 未指定 budget_tokens 时的思考预算
+const DEFAULT_THINKING_BUDGET: u32 = 10000;
+
+/// Kiro 没有思考参数，通过提示词标签开启思考模式，模型会把思考过程放在 <thinking> 标签里输出
+fn thinking_prompt(budget_tokens: u32) -> String {
+    format!(
+        "<thinking_mode>enabled</thinking_mode>\n<max_thinking_length>{}</max_thinking_length>\n\n",
+        budget_tokens
+    )
+}
+
+/// OpenAI reasoning_effort 对应的思考预算
+fn reasoning_effort_budget(effort: &str) -> Option<u32> {
+    match effort {
+        "low" => Some(4096),
+        "medium" => Some(DEFAULT_THINKING_BUDGET),
+        "high" => Some(24576),
+        _ => None,
+    }
+}
+
+/// OpenAI 格式转换为 Kiro 格式
+///
+/// system 和 developer 消息按顺序合并为系统提示词，指定 response_format 时在末尾追加格式要求；
+/// 超出上下文窗口且无法裁剪时返回错误信息
+pub fn openai_to_kiro(
+    request: &OpenAIChatRequest,
+    response_format: Option<&ResponseFormat>,
+    window: &ContextWindow,
+) -> Result<KiroRequest, String> {
+    log_unsupported_sampling(request.temperature, request.top_p);
+
+    let (system_messages, messages): (Vec<_>, Vec<_>) = request
+        .messages
+        .iter()
+        .partition(|msg| matches!(msg.role.as_str(), "system" | "developer"));
+    let system = merge_system_prompts(
+        system_messages
+            .iter()
+            .map(|msg| openai_content_text(&msg.content))
+            .chain(response_format.map(|format| format.instructions())),
+    );
+
+    // 对话消息合并为一个字符串
+    let turns = messages
+        .iter()
+        .map(|msg| {
+            let text = format!("{}: {}", msg.role, openai_content_text(&msg.content));
+            if msg.role == "tool" {
+                Turn::tool_result(text)
+            } else {
+                Turn::message(text)
+            }
+        })
+        .collect();
+    let prefix = request
+        .reasoning_effort
+        .as_deref()
+        .and_then(reasoning_effort_budget)
+        .map(thinking_prompt)
+        .unwrap_or_default();
+
+    build_fitted_request(window, system, prefix, turns, None)
+}
+
+/// Claude 格式转换为 Kiro 格式
+pub fn claude_to_kiro(request: &ClaudeRequest, window: &ContextWindow) -> Result<KiroRequest, String> {
+    log_unsupported_sampling(request.temperature, request.top_p);
+
+    // 开启扩展思考
+    let prefix = request
+        .thinking
+        .as_ref()
+        .filter(|t| t.thinking_type == "enabled")
+        .map(|thinking| thinking_prompt(thinking.budget_tokens.unwrap_or(DEFAULT_THINKING_BUDGET)))
+        .unwrap_or_default();
     
+    // 添加对话消息
+    let turns = request
+        .messages
+        .iter()
+        .map(|msg| {
+            let text = format!("{}: {}", msg.role, claude_content_text(&msg.content));
+            if is_claude_tool_result(&msg.content) {
+                Turn::tool_result(text)
+            } else {
+                Turn::message(text)
+            }
+        })
+        .collect();
+
+    let system = merge_system_prompts(request.system.as_ref().map(claude_system_prompts).unwrap_or_default());
+    build_fitted_request(window, system, prefix, turns, None)
+}
+
+/// 从 Kiro 响应中提取文本内容
+fn extract_kiro_content(kiro_response: &Value) -> Result<String, String> {
     // 尝试从不同字段提取内容
     let content = if let Some(assistant_resp) = kiro_response.get("assistantResponseEvent") {
         // 流式响应格式
@@ -98,7 +270,7 @@ pub fn kiro_to_openai_response(
             .collect::<Vec<_>>()
             .join("")
     } else if let Some(choices) = kiro_response.get("choices").and_then(|c| c.as_array()) {
-        // 如果已经是 OpenAI 格式
+        // 如果已经是 OpenAI 格式的 choices
         choices
             .first()
             .and_then(|choice| choice.get("message"))
@@ -120,31 +292,41 @@ pub fn kiro_to_openai_response(
         println!("[Translator] 警告: 无法从 Kiro 响应中提取内容");
     }
 
+    Ok(content)
+}
+
+/// Kiro 响应转换为 OpenAI 格式，按 stop 和 max_tokens 在本地截断
+pub fn kiro_to_openai_response(
+    kiro_response: &Value,
+    request: &OpenAIChatRequest,
+) -> Result<Value, String> {
+    let content = extract_kiro_content(kiro_response)?;
+    let (content, truncation, output_tokens) = truncate_completion(kiro_response, &content, &chat_output_limits(request));
+
     // 提取 token 信息
     let input_tokens = kiro_response
         .get("inputTokens")
         .or_else(|| kiro_response.get("usage").and_then(|u| u.get("prompt_tokens")))
         .and_then(|t| t.as_u64())
         .unwrap_or(0);
-    
-    let output_tokens = kiro_response
-        .get("outputTokens")
-        .or_else(|| kiro_response.get("usage").and_then(|u| u.get("completion_tokens")))
-        .and_then(|t| t.as_u64())
-        .unwrap_or(0);
+
+    let mut message = json!({
+        "role": "assistant",
+        "content": content
+    });
+    if let Some(reasoning) = kiro_response.get("reasoning").and_then(|r| r.as_str()) {
+        message["reasoning_content"] = json!(reasoning);
+    }
 
     Ok(json!({
         "id": format!("chatcmpl-{}", uuid::Uuid::new_v4()),
         "object": "chat.completion",
         "created": chrono::Utc::now().timestamp(),
-        "model": model,
+        "model": request.model,
         "choices": [{
             "index": 0,
-            "message": {
-                "role": "assistant",
-                "content": content
-            },
-            "finish_reason": "stop"
+            "message": message,
+            "finish_reason": truncation.finish_reason()
         }],
         "usage": {
             "prompt_tokens": input_tokens,
@@ -154,75 +336,51 @@ pub fn kiro_to_openai_response(
     }))
 }
 
-/// Kiro 响应转换为 Claude 格式
+/// Kiro 响应转换为 Claude 格式，按 stop_sequences 和 max_tokens 在本地截断
 pub fn kiro_to_claude_response(
     kiro_response: &Value,
-    model: &str,
+    request: &ClaudeRequest,
 ) -> Result<Value, String> {
-    // 打印原始响应用于调试
-    println!("[Translator] Kiro 原始响应: {}", serde_json::to_string_pretty(kiro_response).unwrap_or_default());
-    
-    // 尝试从不同字段提取内容
-    let content = if let Some(assistant_resp) = kiro_response.get("assistantResponseEvent") {
-        // 流式响应格式
-        assistant_resp.get("content").and_then(|c| c.as_str()).unwrap_or("").to_string()
-    } else if let Some(message) = kiro_response.get("message").and_then(|m| m.as_str()) {
-        message.to_string()
-    } else if let Some(text) = kiro_response.get("text").and_then(|t| t.as_str()) {
-        text.to_string()
-    } else if let Some(content_arr) = kiro_response.get("content").and_then(|c| c.as_array()) {
-        // 如果是 content 数组，提取所有 text
-        content_arr
-            .iter()
-            .filter_map(|item| item.get("text").and_then(|t| t.as_str()))
-            .collect::<Vec<_>>()
-            .join("")
-    } else if let Some(choices) = kiro_response.get("choices").and_then(|c| c.as_array()) {
-        // 如果是 OpenAI 格式的 choices
-        choices
-            .first()
-            .and_then(|choice| choice.get("message"))
-            .and_then(|msg| msg.get("content"))
-            .and_then(|c| c.as_str())
-            .unwrap_or("")
-            .to_string()
-    } else if let Some(output) = kiro_response.get("Output") {
-        // AWS 错误响应格式
-        if let Some(error_msg) = output.get("message").and_then(|m| m.as_str()) {
-            return Err(format!("Kiro API 错误: {}", error_msg));
-        }
-        String::new()
-    } else {
-        String::new()
+    let content = extract_kiro_content(kiro_response)?;
+    let (content, truncation, output_tokens) = truncate_completion(kiro_response, &content, &claude_output_limits(request));
+    let (stop_reason, stop_sequence) = match &truncation {
+        Truncation::None => ("end_turn", None),
+        Truncation::StopSequence(stop) => ("stop_sequence", Some(stop.clone())),
+        Truncation::MaxTokens => ("max_tokens", None),
     };
 
-    if content.is_empty() {
-        println!("[Translator] 警告: 无法从 Kiro 响应中提取内容");
-    }
-
     let input_tokens = kiro_response
         .get("inputTokens")
         .or_else(|| kiro_response.get("usage").and_then(|u| u.get("input_tokens")))
         .and_then(|t| t.as_u64())
         .unwrap_or(0);
-    
-    let output_tokens = kiro_response
-        .get("outputTokens")
-        .or_else(|| kiro_response.get("usage").and_then(|u| u.get("output_tokens")))
-        .and_then(|t| t.as_u64())
-        .unwrap_or(0);
+
+    // 思考过程作为 thinking 内容块放在正文前
+    let mut blocks = Vec::new();
+    // 上游没有返回签名时不带 signature 字段，空签名会被客户端当作无效签名
+    if let Some(reasoning) = kiro_response.get("reasoning").and_then(|r| r.as_str()) {
+        let mut block = json!({
+            "type": "thinking",
+            "thinking": reasoning
+        });
+        if let Some(signature) = kiro_response.get("reasoningSignature").and_then(|s| s.as_str()).filter(|s| !s.is_empty()) {
+            block["signature"] = json!(signature);
+        }
+        blocks.push(block);
+    }
+    blocks.push(json!({
+        "type": "text",
+        "text": content
+    }));
 
     Ok(json!({
         "id": format!("msg_{}", uuid::Uuid::new_v4()),
         "type": "message",
         "role": "assistant",
-        "content": [{
-            "type": "text",
-            "text": content
-        }],
-        "model": model,
-        "stop_reason": "end_turn",
-        "stop_sequence": null,
+        "content": blocks,
+        "model": request.model,
+        "stop_reason": stop_reason,
+        "stop_sequence": stop_sequence,
         "usage": {
             "input_tokens": input_tokens,
             "output_tokens": output_tokens
@@ -230,6 +388,643 @@ pub fn kiro_to_claude_response(
     }))
 }
 
+/// 提取 OpenAI 消息和 Responses 输入项中的文本，兼容字符串和内容块数组
+fn openai_content_text(content: &Value) -> String {
+    match content {
+        Value::String(text) => text.clone(),
+        Value::Array(parts) => parts
+            .iter()
+            .filter_map(|part| match part {
+                Value::String(text) => Some(text.clone()),
+                _ => match part.get("type").and_then(|t| t.as_str()) {
+                    Some("input_text") | Some("output_text") | Some("text") | Some("refusal") => part
+                        .get("text")
+                        .or_else(|| part.get("refusal"))
+                        .and_then(|t| t.as_str())
+                        .map(|t| t.to_string()),
+                    Some(other) => Some(format!("[{}]", other)),
+                    None => None,
+                },
+            })
+            .collect::<Vec<_>>()
+            .join("\n"),
+        Value::Null => String::new(),
+        other => other.to_string(),
+    }
+}
+
+/// 构建 Kiro 工具定义，未提供参数时使用空对象 schema
+fn kiro_tool(name: &str, description: Option<&str>, parameters: Option<Value>) -> KiroTool {
+    KiroTool {
+        tool_specification: KiroToolSpecification {
+            name: name.to_string(),
+            description: description.unwrap_or_default().to_string(),
+            input_schema: KiroInputSchema {
+                json: parameters.unwrap_or_else(|| json!({ "type": "object", "properties": {} })),
+            },
+        },
+    }
+}
+
+/// Responses 函数工具转换为 Kiro 工具，其他内置工具 Kiro 不支持，跳过
+fn responses_tools_to_kiro(tools: &[Value]) -> Option<Vec<KiroTool>> {
+    let kiro_tools: Vec<KiroTool> = tools
+        .iter()
+        .filter_map(|tool| {
+            let tool_type = tool.get("type").and_then(|t| t.as_str()).unwrap_or("function");
+            if tool_type != "function" {
+                println!("[Translator] 跳过不支持的工具类型: {}", tool_type);
+                return None;
+            }
+            // 兼容 Chat Completions 的 { type, function: {...} } 写法
+            let spec = tool.get("function").unwrap_or(tool);
+            let name = spec.get("name").and_then(|n| n.as_str())?;
+            Some(kiro_tool(
+                name,
+                spec.get("description").and_then(|d| d.as_str()),
+                spec.get("parameters").cloned(),
+            ))
+        })
+        .collect();
+
+    if kiro_tools.is_empty() {
+        None
+    } else {
+        Some(kiro_tools)
+    }
+}
+
+/// OpenAI Responses 格式转换为 Kiro 格式
+pub fn responses_to_kiro(request: &ResponsesRequest, window: &ContextWindow) -> Result<KiroRequest, String> {
+    log_unsupported_sampling(request.temperature, request.top_p);
+
+    let mut system_prompts: Vec<String> = request.instructions.iter().cloned().collect();
+    let mut parts = Vec::new();
+
+    match &request.input {
+        Value::String(text) => parts.push(Turn::message(format!("user: {}", text))),
+        Value::Array(items) => {
+            for item in items {
+                let text = |key: &str| item.get(key).and_then(|v| v.as_str()).unwrap_or_default();
+                match item.get("type").and_then(|t| t.as_str()).unwrap_or("message") {
+                    "message" => {
+                        let content = openai_content_text(item.get("content").unwrap_or(&Value::Null));
+                        match text("role") {
+                            "system" | "developer" => system_prompts.push(content),
+                            "" => parts.push(Turn::message(format!("user: {}", content))),
+                            role => parts.push(Turn::message(format!("{}: {}", role, content))),
+                        }
+                    }
+                    "function_call" => parts.push(Turn::message(format!(
+                        "assistant: [调用工具 {} ({})] {}",
+                        text("name"),
+                        text("call_id"),
+                        text("arguments")
+                    ))),
+                    "function_call_output" => {
+                        let output = openai_content_text(item.get("output").unwrap_or(&Value::Null));
+                        parts.push(Turn::tool_result(format!("tool ({}): {}", text("call_id"), output)));
+                    }
+                    // 推理内容等其他输入项不转发
+                    other => println!("[Translator] 跳过输入项: {}", other),
+                }
+            }
+        }
+        _ => {}
+    }
+
+    let tools = request.tools.as_deref().and_then(responses_tools_to_kiro);
+    build_fitted_request(window, merge_system_prompts(system_prompts), String::new(), parts, tools)
+}
+
+/// Kiro 响应转换为 OpenAI Responses 格式
+pub fn kiro_to_responses_response(
+    kiro_response: &Value,
+    request: &ResponsesRequest,
+) -> Result<Value, String> {
+    let content = extract_kiro_content(kiro_response)?;
+    let (content, truncation, output_tokens) = truncate_completion(kiro_response, &content, &responses_output_limits(request));
+    let status = match truncation {
+        Truncation::MaxTokens => "incomplete",
+        _ => "completed",
+    };
+    let mut output = Vec::new();
+
+    if !content.is_empty() {
+        output.push(json!({
+            "type": "message",
+            "id": format!("msg_{}", uuid::Uuid::new_v4().simple()),
+            "status": status,
+            "role": "assistant",
+            "content": [{
+                "type": "output_text",
+                "text": content,
+                "annotations": []
+            }]
+        }));
+    }
+
+    if let Some(tool_uses) = kiro_response.get("toolUses").and_then(|t| t.as_array()) {
+        for tool_use in tool_uses {
+            let arguments = match tool_use.get("input") {
+                Some(Value::String(raw)) => raw.clone(),
+                Some(input) => input.to_string(),
+                None => "{}".to_string(),
+            };
+            output.push(json!({
+                "type": "function_call",
+                "id": format!("fc_{}", uuid::Uuid::new_v4().simple()),
+                "call_id": tool_use.get("toolUseId").cloned().unwrap_or(Value::Null),
+                "name": tool_use.get("name").cloned().unwrap_or(Value::Null),
+                "arguments": arguments,
+                "status": "completed"
+            }));
+        }
+    }
+
+    let input_tokens = kiro_response.get("inputTokens").and_then(|t| t.as_u64()).unwrap_or(0);
+
+    let mut response = responses_response_object(request);
+    response["status"] = json!(status);
+    if let Truncation::MaxTokens = truncation {
+        response["incomplete_details"] = json!({ "reason": "max_output_tokens" });
+    }
+    response["output"] = Value::Array(output);
+    response["usage"] = json!({
+        "input_tokens": input_tokens,
+        "output_tokens": output_tokens,
+        "total_tokens": input_tokens + output_tokens
+    });
+    Ok(response)
+}
+
+/// 进行中的 Responses 响应对象：还没有输出和用量
+pub fn responses_response_object(request: &ResponsesRequest) -> Value {
+    json!({
+        "id": format!("resp_{}", uuid::Uuid::new_v4().simple()),
+        "object": "response",
+        "created_at": chrono::Utc::now().timestamp(),
+        "status": "in_progress",
+        "error": null,
+        "incomplete_details": null,
+        "instructions": request.instructions,
+        "max_output_tokens": request.max_output_tokens,
+        "model": request.model,
+        "output": [],
+        "parallel_tool_calls": true,
+        "temperature": request.temperature,
+        "top_p": request.top_p,
+        "tools": request.tools.clone().unwrap_or_default(),
+        "usage": null
+    })
+}
+
+/// 提取 Completions 请求的 prompt，多个 prompt 需要多次调用上游，暂不支持
+pub fn completion_prompt(request: &CompletionRequest) -> Result<String, String> {
+    match &request.prompt {
+        Value::String(prompt) => Ok(prompt.clone()),
+        Value::Array(prompts) => match prompts.as_slice() {
+            [Value::String(prompt)] => Ok(prompt.clone()),
+            [] => Err("prompt 不能为空".to_string()),
+            items if items.iter().all(Value::is_number) => Err("暂不支持 token 数组形式的 prompt".to_string()),
+            _ => Err("暂不支持多个 prompt".to_string()),
+        },
+        Value::Null => Err("缺少 prompt".to_string()),
+        _ => Err("prompt 必须是字符串或字符串数组".to_string()),
+    }
+}
+
+/// OpenAI Completions 格式转换为 Kiro 格式
+///
+/// Kiro 只有对话接口，这里把续写（或带 suffix 的补全）要求写进用户输入；
+/// prompt 只有一轮，超出上下文窗口时无法裁剪，直接返回错误信息
+pub fn completions_to_kiro(request: &CompletionRequest, prompt: &str, window: &ContextWindow) -> Result<KiroRequest, String> {
+    log_unsupported_sampling(request.temperature, request.top_p);
+
+    let content = match request.suffix.as_deref().filter(|s| !s.is_empty()) {
+        Some(suffix) => format!(
+            "Fill in the text at <FILL>. Reply with only the text that replaces <FILL>, without any explanation.\n\n{}<FILL>{}",
+            prompt, suffix
+        ),
+        None => format!(
+            "Continue the following text. Reply with only the continuation, without repeating the text or adding any explanation.\n\n{}",
+            prompt
+        ),
+    };
+
+    build_fitted_request(window, None, String::new(), vec![Turn::message(content)], None)
+}
+
+/// 解析 stop 参数，兼容字符串和字符串数组
+fn completion_stop_sequences(stop: &Option<Value>) -> Vec<String> {
+    match stop {
+        Some(Value::String(stop)) => vec![stop.clone()],
+        Some(Value::Array(stops)) => stops
+            .iter()
+            .filter_map(|s| s.as_str())
+            .map(str::to_string)
+            .collect(),
+        _ => Vec::new(),
+    }
+    .into_iter()
+    .filter(|s| !s.is_empty())
+    .collect()
+}
+
+/// 本地截断的条件：停止序列和最大输出 token 数
+#[derive(Debug, Clone, Default)]
+pub struct OutputLimits {
+    pub stops: Vec<String>,
+    pub max_tokens: Option<u32>,
+}
+
+/// Chat Completions 请求的截断条件
+pub fn chat_output_limits(request: &OpenAIChatRequest) -> OutputLimits {
+    // 结构化输出已校验并规范化为完整的 JSON，本地截断会破坏 JSON，此时不截断
+    if matches!(ResponseFormat::parse(request.response_format.as_ref()), Ok(Some(_))) {
+        return OutputLimits::default();
+    }
+    OutputLimits {
+        stops: completion_stop_sequences(&request.stop),
+        max_tokens: request.max_completion_tokens.or(request.max_tokens),
+    }
+}
+
+/// Messages 请求的截断条件
+pub fn claude_output_limits(request: &ClaudeRequest) -> OutputLimits {
+    OutputLimits {
+        stops: request.stop_sequences.iter().flatten().filter(|s| !s.is_empty()).cloned().collect(),
+        // max_tokens 缺省时反序列化为 0，视为不限制
+        max_tokens: Some(request.max_tokens).filter(|&max| max > 0),
+    }
+}
+
+/// Responses 请求的截断条件，Responses 接口没有停止序列
+pub fn responses_output_limits(request: &ResponsesRequest) -> OutputLimits {
+    OutputLimits {
+        stops: Vec::new(),
+        max_tokens: request.max_output_tokens,
+    }
+}
+
+/// Completions 请求的截断条件
+pub fn completion_output_limits(request: &CompletionRequest) -> OutputLimits {
+    OutputLimits {
+        stops: completion_stop_sequences(&request.stop),
+        max_tokens: request.max_tokens,
+    }
+}
+
+/// generateContent 请求的截断条件
+pub fn gemini_output_limits(request: &GeminiRequest) -> OutputLimits {
+    let config = request.generation_config.clone().unwrap_or_default();
+    OutputLimits {
+        stops: config.stop_sequences.unwrap_or_default(),
+        max_tokens: config.max_output_tokens,
+    }
+}
+
+/// 本地截断的原因
+enum Truncation {
+    /// 未截断，模型自然结束
+    None,
+    /// 遇到停止序列
+    StopSequence(String),
+    /// 达到 max_tokens
+    MaxTokens,
+}
+
+impl Truncation {
+    /// OpenAI 格式的 finish_reason
+    fn finish_reason(&self) -> &'static str {
+        match self {
+            Truncation::MaxTokens => "length",
+            _ => "stop",
+        }
+    }
+}
+
+/// 上游不支持 stop 和 max_tokens，在本地截断，返回截断后的文本、截断原因和输出 token 数
+///
+/// 本地未截断但上游因长度停止（自动继续轮数用完）时同样视为达到 max_tokens；
+/// 本地截断了文本时上游的 outputTokens 不再准确，按截断后的文本重新估算
+fn truncate_completion(kiro_response: &Value, text: &str, limits: &OutputLimits) -> (String, Truncation, u64) {
+    let original_len = text.len();
+    let (text, mut truncation) = match limits
+        .stops
+        .iter()
+        .filter_map(|stop| text.find(stop.as_str()).map(|end| (end, stop)))
+        .min_by_key(|(end, _)| *end)
+    {
+        Some((end, stop)) => (&text[..end], Truncation::StopSequence(stop.clone())),
+        None if stopped_for_length(kiro_response) => (text, Truncation::MaxTokens),
+        None => (text, Truncation::None),
+    };
+
+    let text = match limits.max_tokens.and_then(|max| truncate_to_token_limit(text, max as u64)) {
+        Some(truncated) => {
+            truncation = Truncation::MaxTokens;
+            truncated
+        }
+        None => text,
+    };
+
+    let output_tokens = if text.len() < original_len {
+        estimate_text_tokens(text)
+    } else {
+        kiro_response
+            .get("outputTokens")
+            .or_else(|| kiro_response.get("usage").and_then(|u| u.get("completion_tokens").or_else(|| u.get("output_tokens"))))
+            .and_then(|t| t.as_u64())
+            .unwrap_or(0)
+    };
+    (text.to_string(), truncation, output_tokens)
+}
+
+/// 流式输出的本地截断：可能是停止序列开头的文本先留着，输出的文本始终是 truncate_completion 最终结果的前缀
+pub struct StreamTruncator {
+    limits: OutputLimits,
+    text: String,
+    emitted: usize,
+    stopped: bool,
+}
+
+impl StreamTruncator {
+    pub fn new(limits: OutputLimits) -> Self {
+        Self {
+            limits,
+            text: String::new(),
+            emitted: 0,
+            stopped: false,
+        }
+    }
+
+    /// 追加上游的正文，返回可以输出的新文本；截断后不再输出
+    pub fn push(&mut self, delta: &str) -> String {
+        if self.stopped {
+            return String::new();
+        }
+        self.text.push_str(delta);
+
+        // 已出现的停止序列之后、以及末尾可能是停止序列开头的部分都不能输出
+        let stop = self.limits.stops.iter().filter_map(|stop| self.text.find(stop.as_str())).min();
+        let held = self
+            .limits
+            .stops
+            .iter()
+            .filter_map(|stop| {
+                stop.char_indices()
+                    .skip(1)
+                    .map(|(len, _)| len)
+                    .filter(|&len| self.text.ends_with(&stop[..len]))
+                    .max()
+            })
+            .max()
+            .map_or(self.text.len(), |len| self.text.len() - len);
+        let mut end = match stop {
+            Some(stop) if stop <= held => {
+                self.stopped = true;
+                stop
+            }
+            _ => held,
+        };
+
+        if let Some(truncated) = self.limits.max_tokens.and_then(|max| truncate_to_token_limit(&self.text[..end], max as u64)) {
+            end = truncated.len();
+            self.stopped = true;
+        }
+
+        if end <= self.emitted {
+            return String::new();
+        }
+        let ready = self.text[self.emitted..end].to_string();
+        self.emitted = end;
+        ready
+    }
+
+    /// 已输出的文本
+    pub fn emitted(&self) -> &str {
+        &self.text[..self.emitted]
+    }
+}
+
+/// Kiro 响应转换为 OpenAI Completions 格式
+pub fn kiro_to_completion_response(
+    kiro_response: &Value,
+    request: &CompletionRequest,
+) -> Result<Value, String> {
+    let content = extract_kiro_content(kiro_response)?;
+    let (text, truncation, output_tokens) = truncate_completion(kiro_response, &content, &completion_output_limits(request));
+
+    let input_tokens = kiro_response.get("inputTokens").and_then(|t| t.as_u64()).unwrap_or(0);
+
+    Ok(json!({
+        "id": format!("cmpl-{}", uuid::Uuid::new_v4()),
+        "object": "text_completion",
+        "created": chrono::Utc::now().timestamp(),
+        "model": request.model,
+        "choices": [{
+            "text": text,
+            "index": 0,
+            "logprobs": null,
+            "finish_reason": truncation.finish_reason()
+        }],
+        "usage": {
+            "prompt_tokens": input_tokens,
+            "completion_tokens": output_tokens,
+            "total_tokens": input_tokens + output_tokens
+        }
+    }))
+}
+
+/// 提取 Gemini parts 的文本，函数调用和返回结果按文本描述
+fn gemini_parts_text(parts: &[Value]) -> String {
+    parts
+        .iter()
+        .map(|part| {
+            if let Some(text) = part.get("text").and_then(|t| t.as_str()) {
+                text.to_string()
+            } else if let Some(call) = part.get("functionCall") {
+                format!(
+                    "[调用工具 {}] {}",
+                    call.get("name").and_then(|n| n.as_str()).unwrap_or_default(),
+                    call.get("args").cloned().unwrap_or(json!({}))
+                )
+            } else if let Some(response) = part.get("functionResponse") {
+                format!(
+                    "[工具 {} 返回] {}",
+                    response.get("name").and_then(|n| n.as_str()).unwrap_or_default(),
+                    response.get("response").cloned().unwrap_or(Value::Null)
+                )
+            } else {
+                // inlineData、fileData 等 Kiro 不支持，只保留类型
+                let kind = part
+                    .as_object()
+                    .and_then(|obj| obj.keys().next().cloned())
+                    .unwrap_or_else(|| "unknown".to_string());
+                format!("[{}]", kind)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Gemini 的 schema 类型为大写（OBJECT、STRING），转换为 JSON Schema 的小写写法
+fn gemini_schema_to_json_schema(schema: &Value) -> Value {
+    match schema {
+        Value::Object(obj) => Value::Object(
+            obj.iter()
+                .map(|(key, value)| {
+                    let value = match (key.as_str(), value) {
+                        ("type", Value::String(t)) => Value::String(t.to_lowercase()),
+                        _ => gemini_schema_to_json_schema(value),
+                    };
+                    (key.clone(), value)
+                })
+                .collect(),
+        ),
+        Value::Array(items) => Value::Array(items.iter().map(gemini_schema_to_json_schema).collect()),
+        other => other.clone(),
+    }
+}
+
+/// Gemini generateContent 格式转换为 Kiro 格式
+pub fn gemini_to_kiro(request: &GeminiRequest, window: &ContextWindow) -> Result<KiroRequest, String> {
+    if let Some(config) = &request.generation_config {
+        log_unsupported_sampling(config.temperature, config.top_p);
+    }
+
+    let system = merge_system_prompts(request.system_instruction.iter().map(|s| gemini_parts_text(&s.parts)));
+    let mut parts = Vec::new();
+
+    for content in &request.contents {
+        let role = match content.role.as_deref() {
+            Some("model") => "assistant",
+            Some("function") | Some("tool") => "tool",
+            _ => "user",
+        };
+        let text = format!("{}: {}", role, gemini_parts_text(&content.parts));
+        if role == "tool" || content.parts.iter().any(|part| part.get("functionResponse").is_some()) {
+            parts.push(Turn::tool_result(text));
+        } else {
+            parts.push(Turn::message(text));
+        }
+    }
+
+    let tools: Vec<KiroTool> = request
+        .tools
+        .iter()
+        .flatten()
+        .flat_map(|tool| tool.function_declarations.iter().flatten())
+        .map(|declaration| {
+            let parameters = declaration
+                .parameters_json_schema
+                .clone()
+                .or_else(|| declaration.parameters.as_ref().map(gemini_schema_to_json_schema));
+            kiro_tool(&declaration.name, declaration.description.as_deref(), parameters)
+        })
+        .collect();
+
+    build_fitted_request(window, system, String::new(), parts, if tools.is_empty() { None } else { Some(tools) })
+}
+
+/// Kiro 响应转换为 Gemini 格式
+pub fn kiro_to_gemini_response(
+    kiro_response: &Value,
+    request: &GeminiRequest,
+    model: &str,
+) -> Result<Value, String> {
+    let content = extract_kiro_content(kiro_response)?;
+    let (text, truncation, output_tokens) = truncate_completion(kiro_response, &content, &gemini_output_limits(request));
+
+    let mut parts = Vec::new();
+    if !text.is_empty() {
+        parts.push(json!({ "text": text }));
+    }
+    if let Some(tool_uses) = kiro_response.get("toolUses").and_then(|t| t.as_array()) {
+        for tool_use in tool_uses {
+            let args = match tool_use.get("input") {
+                Some(Value::String(raw)) => serde_json::from_str(raw).unwrap_or_else(|_| json!({ "input": raw })),
+                Some(input) => input.clone(),
+                None => json!({}),
+            };
+            parts.push(json!({
+                "functionCall": {
+                    "id": tool_use.get("toolUseId").cloned().unwrap_or(Value::Null),
+                    "name": tool_use.get("name").cloned().unwrap_or(Value::Null),
+                    "args": args
+                }
+            }));
+        }
+    }
+
+    let input_tokens = kiro_response.get("inputTokens").and_then(|t| t.as_u64()).unwrap_or(0);
+
+    Ok(json!({
+        "candidates": [{
+            "content": {
+                "role": "model",
+                "parts": parts
+            },
+            "finishReason": match truncation {
+                Truncation::MaxTokens => "MAX_TOKENS",
+                _ => "STOP",
+            },
+            "index": 0
+        }],
+        "usageMetadata": {
+            "promptTokenCount": input_tokens,
+            "candidatesTokenCount": output_tokens,
+            "totalTokenCount": input_tokens + output_tokens
+        },
+        "modelVersion": model
+    }))
+}
+
+/// 模型元数据：名称、上下文窗口、最大输出、输入类型和计费倍率
+fn kiro_model_metadata(model: &ModelInfo) -> serde_json::Map<String, Value> {
+    let input_types: Vec<String> = model.input_types.iter().flatten().map(|t| t.to_lowercase()).collect();
+
+    let mut metadata = serde_json::Map::new();
+    metadata.insert("display_name".to_string(), json!(model.name));
+    metadata.insert("description".to_string(), json!(model.description));
+    metadata.insert("context_window".to_string(), json!(model.max_input_tokens));
+    metadata.insert("max_output_tokens".to_string(), json!(model.max_output_tokens));
+    metadata.insert("supports_vision".to_string(), json!(model.supports_vision()));
+    metadata.insert("input_modalities".to_string(), json!(input_types));
+    metadata.insert("rate_multiplier".to_string(), json!(model.rate_multiplier));
+    metadata
+}
+
+/// Kiro 模型转换为 OpenAI 模型对象
+///
+/// Kiro 不提供模型发布时间，created 固定为 0
+pub fn kiro_model_to_openai(model: &ModelInfo) -> Value {
+    let mut result = kiro_model_metadata(model);
+    result.insert("id".to_string(), json!(model.id));
+    result.insert("object".to_string(), json!("model"));
+    result.insert("created".to_string(), json!(0));
+    result.insert("owned_by".to_string(), json!("amazon"));
+    Value::Object(result)
+}
+
+/// Kiro 模型转换为 Anthropic 模型对象
+pub fn kiro_model_to_anthropic(model: &ModelInfo) -> Value {
+    let mut result = kiro_model_metadata(model);
+    result.insert("id".to_string(), json!(model.id));
+    result.insert("type".to_string(), json!("model"));
+    result.insert("created_at".to_string(), json!("1970-01-01T00:00:00Z"));
+    Value::Object(result)
+}
+
+/// 按配置去掉思考过程，不返回给客户端
+pub fn strip_reasoning(kiro_response: &mut Value) {
+    if let Some(obj) = kiro_response.as_object_mut() {
+        obj.remove("reasoning");
+        obj.remove("reasoningSignature");
+    }
+}
+
 /// 创建 OpenAI 流式响应块
 pub fn create_openai_stream_chunk(
     content: &str,
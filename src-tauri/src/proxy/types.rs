@@ -1,5 +1,8 @@
 // 反代服务类型定义
+use crate::header_profile::HeaderOverrides;
+use crate::http_client::OutboundProxy;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use std::collections::HashMap;
 
 /// 代理账号信息
@@ -34,6 +37,14 @@ pub struct ProxyAccount {
     #[serde(default)]
     #[serde(rename = "errorCount")]
     pub error_count: u64,
+    /// 账号单独的出站代理，未设置时使用全局代理
+    #[serde(default)]
+    #[serde(rename = "outboundProxy")]
+    pub outbound_proxy: Option<OutboundProxy>,
+    /// 账号单独的请求头配置，未设置的字段使用全局配置
+    #[serde(default)]
+    #[serde(rename = "headerProfile")]
+    pub header_profile: Option<HeaderOverrides>,
 }
 
 /// API Key 信息
@@ -41,7 +52,19 @@ pub struct ProxyAccount {
 pub struct ApiKey {
     pub id: String,
     pub name: String,
-    pub key: String,
+    /// Key 开头的几个字符，用于在列表中辨认和校验时筛选
+    #[serde(default)]
+    pub prefix: String,
+    /// 加盐后的 SHA-256 哈希，不保存明文
+    #[serde(default)]
+    #[serde(rename = "keyHash")]
+    pub key_hash: String,
+    #[serde(default)]
+    pub salt: String,
+    /// 旧版配置中的明文 Key，加载时转换为哈希，不再写回文件
+    #[serde(default)]
+    #[serde(rename = "key", skip_serializing)]
+    pub legacy_key: Option<String>,
     pub enabled: bool,
     #[serde(default)]
     #[serde(rename = "creditsLimit")]
@@ -166,12 +189,117 @@ pub struct ProxyConfig {
     #[serde(default = "default_true")]
     #[serde(rename = "enableClaude")]
     pub enable_claude: bool,
+    #[serde(default = "default_true")]
+    #[serde(rename = "enableGemini")]
+    pub enable_gemini: bool,
+    /// 结构化输出校验失败时重新请求一次
+    #[serde(default = "default_true")]
+    #[serde(rename = "retryInvalidJson")]
+    pub retry_invalid_json: bool,
+    /// 不向客户端返回思考过程
+    #[serde(default)]
+    #[serde(rename = "stripThinking")]
+    pub strip_thinking: bool,
+    /// 输入超出模型上下文窗口时的处理方式：truncate 丢弃最早的轮次，
+    /// summarize 截断较早的工具结果并注明省略量，reject 直接返回 context_length_exceeded
+    #[serde(default = "default_context_overflow_policy")]
+    #[serde(rename = "contextOverflowPolicy")]
+    pub context_overflow_policy: String,
 }
 
+/// 自动继续的最大轮数
+pub const MAX_AUTO_CONTINUE_ROUNDS: u32 = 10;
+
 fn default_true() -> bool {
     true
 }
 
+fn default_context_overflow_policy() -> String {
+    "truncate".to_string()
+}
+
+impl Default for ProxyConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: 5580,
+            host: "127.0.0.1".to_string(),
+            api_key: None,
+            api_keys: None,
+            enable_multi_account: true,
+            selected_account_ids: vec![],
+            log_requests: true,
+            max_retries: Some(3),
+            preferred_endpoint: None,
+            auto_start: None,
+            auto_continue_rounds: None,
+            disable_tools: None,
+            auto_switch_on_quota_exhausted: None,
+            model_mappings: None,
+            enable_openai: true,
+            enable_claude: true,
+            enable_gemini: true,
+            retry_invalid_json: true,
+            strip_thinking: false,
+            context_overflow_policy: default_context_overflow_policy(),
+        }
+    }
+}
+
+impl ProxyConfig {
+    /// 校验配置，返回第一个错误
+    pub fn validate(&self) -> Result<(), String> {
+        if self.port == 0 {
+            return Err("端口不能为 0".to_string());
+        }
+        self.host
+            .parse::<std::net::IpAddr>()
+            .map_err(|_| format!("无效的监听地址: {}，请填写 IP 地址，例如 127.0.0.1 或 0.0.0.0", self.host))?;
+
+        if self.auto_continue_rounds.is_some_and(|rounds| rounds > MAX_AUTO_CONTINUE_ROUNDS) {
+            return Err(format!("自动继续轮数不能超过 {}", MAX_AUTO_CONTINUE_ROUNDS));
+        }
+
+        if !matches!(self.context_overflow_policy.as_str(), "truncate" | "summarize" | "reject") {
+            return Err(format!("无效的上下文超限策略: {}", self.context_overflow_policy));
+        }
+
+        if let Some(api_keys) = &self.api_keys {
+            let mut ids = std::collections::HashSet::new();
+            for api_key in api_keys {
+                let has_legacy_key = api_key.legacy_key.as_deref().is_some_and(|key| !key.trim().is_empty());
+                if api_key.key_hash.is_empty() && !has_legacy_key {
+                    return Err(format!("API Key \"{}\" 的密钥为空", api_key.name));
+                }
+                if !ids.insert(api_key.id.as_str()) {
+                    return Err(format!("API Key ID 重复: {}", api_key.id));
+                }
+            }
+        }
+
+        if let Some(rules) = &self.model_mappings {
+            for rule in rules {
+                if !matches!(rule.rule_type.as_str(), "replace" | "alias" | "loadbalance") {
+                    return Err(format!("模型映射 \"{}\" 的类型无效: {}", rule.name, rule.rule_type));
+                }
+                if rule.source_model.trim().is_empty() {
+                    return Err(format!("模型映射 \"{}\" 缺少源模型", rule.name));
+                }
+                if rule.enabled && rule.target_models.is_empty() {
+                    return Err(format!("模型映射 \"{}\" 缺少目标模型", rule.name));
+                }
+                if let Some(weights) = &rule.weights {
+                    if weights.len() != rule.target_models.len() {
+                        return Err(format!("模型映射 \"{}\" 的权重数量与目标模型不一致", rule.name));
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
 /// 代理统计信息
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct ProxyStats {
@@ -191,6 +319,39 @@ pub struct ProxyStats {
     pub output_tokens: u64,
     #[serde(rename = "startTime")]
     pub start_time: i64,
+    /// total_credits 中按模型倍率估算的部分
+    #[serde(default)]
+    #[serde(rename = "estimatedCredits")]
+    pub estimated_credits: f64,
+    /// 按账号 ID 汇总
+    #[serde(default)]
+    #[serde(rename = "byAccount")]
+    pub by_account: HashMap<String, UsageRollup>,
+    /// 按 API Key ID 汇总，未配置 API Key 的请求不计入
+    #[serde(default)]
+    #[serde(rename = "byApiKey")]
+    pub by_api_key: HashMap<String, UsageRollup>,
+    /// 按模型汇总
+    #[serde(default)]
+    #[serde(rename = "byModel")]
+    pub by_model: HashMap<String, UsageRollup>,
+}
+
+/// 用量汇总
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct UsageRollup {
+    pub requests: u64,
+    #[serde(rename = "failedRequests")]
+    pub failed_requests: u64,
+    #[serde(rename = "inputTokens")]
+    pub input_tokens: u64,
+    #[serde(rename = "outputTokens")]
+    pub output_tokens: u64,
+    /// 上游计量和估算的 credits 之和
+    pub credits: f64,
+    /// credits 中按模型倍率估算的部分
+    #[serde(rename = "estimatedCredits")]
+    pub estimated_credits: f64,
 }
 
 /// 会话统计信息
@@ -217,6 +378,16 @@ pub struct RequestLog {
     pub input_tokens: Option<u64>,
     pub output_tokens: Option<u64>,
     pub credits: Option<f64>,
+    /// credits 是否为估算值（上游未返回计量信息）
+    #[serde(default)]
+    #[serde(rename = "creditsEstimated")]
+    pub credits_estimated: Option<bool>,
+    #[serde(default)]
+    #[serde(rename = "accountId")]
+    pub account_id: Option<String>,
+    #[serde(default)]
+    #[serde(rename = "apiKeyId")]
+    pub api_key_id: Option<String>,
     pub error: Option<String>,
 }
 
@@ -229,17 +400,139 @@ pub struct OpenAIChatRequest {
     pub stream: Option<bool>,
     #[serde(default)]
     pub max_tokens: Option<u32>,
+    /// 新版 SDK 用来替代 max_tokens
+    #[serde(default)]
+    pub max_completion_tokens: Option<u32>,
+    /// 上游不支持采样参数，接受但忽略
     #[serde(default)]
     pub temperature: Option<f32>,
     #[serde(default)]
     pub top_p: Option<f32>,
+    /// 字符串或字符串数组
+    #[serde(default)]
+    pub stop: Option<Value>,
+    /// { type: "text" | "json_object" | "json_schema", json_schema? }
+    #[serde(default)]
+    pub response_format: Option<Value>,
+    /// "low" | "medium" | "high"，映射为思考预算
+    #[serde(default)]
+    pub reasoning_effort: Option<String>,
 }
 
 /// OpenAI 消息
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OpenAIMessage {
     pub role: String,
-    pub content: String,
+    /// 字符串或内容片段数组（[{"type":"text","text":...}]），带 tool_calls 的助手消息为 null
+    #[serde(default)]
+    pub content: Value,
+}
+
+/// OpenAI Responses 请求
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResponsesRequest {
+    pub model: String,
+    /// 字符串或输入项数组
+    #[serde(default)]
+    pub input: Value,
+    #[serde(default)]
+    pub instructions: Option<String>,
+    #[serde(default)]
+    pub tools: Option<Vec<Value>>,
+    #[serde(default)]
+    pub stream: Option<bool>,
+    #[serde(default)]
+    pub max_output_tokens: Option<u32>,
+    /// 上游不支持采样参数，接受但忽略
+    #[serde(default)]
+    pub temperature: Option<f32>,
+    #[serde(default)]
+    pub top_p: Option<f32>,
+}
+
+/// OpenAI Completions 请求（旧版 prompt 接口）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompletionRequest {
+    pub model: String,
+    /// 字符串或只含一个字符串的数组
+    #[serde(default)]
+    pub prompt: Value,
+    #[serde(default)]
+    pub suffix: Option<String>,
+    /// 字符串或字符串数组
+    #[serde(default)]
+    pub stop: Option<Value>,
+    #[serde(default)]
+    pub max_tokens: Option<u32>,
+    #[serde(default)]
+    pub stream: Option<bool>,
+    /// 上游不支持采样参数，接受但忽略
+    #[serde(default)]
+    pub temperature: Option<f32>,
+    #[serde(default)]
+    pub top_p: Option<f32>,
+}
+
+/// Gemini generateContent 请求
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GeminiRequest {
+    #[serde(default)]
+    pub contents: Vec<GeminiContent>,
+    #[serde(default)]
+    #[serde(rename = "systemInstruction", alias = "system_instruction")]
+    pub system_instruction: Option<GeminiContent>,
+    #[serde(default)]
+    pub tools: Option<Vec<GeminiTool>>,
+    #[serde(default)]
+    #[serde(rename = "generationConfig", alias = "generation_config")]
+    pub generation_config: Option<GeminiGenerationConfig>,
+}
+
+/// Gemini 对话内容，parts 可以是 text、functionCall、functionResponse 等
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GeminiContent {
+    #[serde(default)]
+    pub role: Option<String>,
+    #[serde(default)]
+    pub parts: Vec<Value>,
+}
+
+/// Gemini 工具，只支持函数声明
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GeminiTool {
+    #[serde(default)]
+    #[serde(rename = "functionDeclarations", alias = "function_declarations")]
+    pub function_declarations: Option<Vec<GeminiFunctionDeclaration>>,
+}
+
+/// Gemini 函数声明
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GeminiFunctionDeclaration {
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub parameters: Option<Value>,
+    #[serde(default)]
+    #[serde(rename = "parametersJsonSchema", alias = "parameters_json_schema")]
+    pub parameters_json_schema: Option<Value>,
+}
+
+/// Gemini 生成参数
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GeminiGenerationConfig {
+    /// 上游不支持采样参数，接受但忽略
+    #[serde(default)]
+    pub temperature: Option<f32>,
+    #[serde(default)]
+    #[serde(rename = "topP", alias = "top_p")]
+    pub top_p: Option<f32>,
+    #[serde(default)]
+    #[serde(rename = "maxOutputTokens", alias = "max_output_tokens")]
+    pub max_output_tokens: Option<u32>,
+    #[serde(default)]
+    #[serde(rename = "stopSequences", alias = "stop_sequences")]
+    pub stop_sequences: Option<Vec<String>>,
 }
 
 /// Claude 请求
@@ -251,17 +544,36 @@ pub struct ClaudeRequest {
     pub max_tokens: u32,
     #[serde(default)]
     pub stream: Option<bool>,
+    /// 上游不支持采样参数，接受但忽略
     #[serde(default)]
     pub temperature: Option<f32>,
     #[serde(default)]
-    pub system: Option<String>,
+    pub top_p: Option<f32>,
+    #[serde(default)]
+    pub stop_sequences: Option<Vec<String>>,
+    /// 字符串或 text 内容块数组（可带 cache_control）
+    #[serde(default)]
+    pub system: Option<Value>,
+    #[serde(default)]
+    pub thinking: Option<ClaudeThinking>,
+}
+
+/// Claude 扩展思考配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClaudeThinking {
+    /// "enabled" 或 "disabled"
+    #[serde(rename = "type")]
+    pub thinking_type: String,
+    #[serde(default)]
+    pub budget_tokens: Option<u32>,
 }
 
 /// Claude 消息
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ClaudeMessage {
     pub role: String,
-    pub content: String,
+    /// 字符串或内容块数组（text、thinking、tool_use、tool_result 等）
+    pub content: Value,
 }
 
 /// Kiro 请求
@@ -278,6 +590,19 @@ pub struct KiroConversationState {
     pub current_message: KiroMessage,
     #[serde(rename = "chatTriggerType")]
     pub chat_trigger_type: String,
+    /// 历史对话，系统提示词作为第一轮放在这里
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub history: Vec<KiroHistoryMessage>,
+}
+
+/// Kiro 历史消息
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum KiroHistoryMessage {
+    #[serde(rename = "userInputMessage")]
+    User { content: String },
+    #[serde(rename = "assistantResponseMessage")]
+    Assistant { content: String },
 }
 
 /// Kiro 消息
@@ -302,6 +627,31 @@ pub struct KiroUserInputMessage {
 pub struct KiroUserInputMessageContext {
     #[serde(rename = "editorState")]
     pub editor_state: KiroEditorState,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tools: Option<Vec<KiroTool>>,
+}
+
+/// Kiro 工具定义
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KiroTool {
+    #[serde(rename = "toolSpecification")]
+    pub tool_specification: KiroToolSpecification,
+}
+
+/// Kiro 工具规格
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KiroToolSpecification {
+    pub name: String,
+    pub description: String,
+    #[serde(rename = "inputSchema")]
+    pub input_schema: KiroInputSchema,
+}
+
+/// Kiro 工具参数 JSON Schema
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KiroInputSchema {
+    pub json: Value,
 }
 
 /// Kiro 编辑器状态
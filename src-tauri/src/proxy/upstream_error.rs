@@ -0,0 +1,373 @@
+// 上游错误 - 按 Kiro 返回的状态码和异常类型分类，映射为 OpenAI、Anthropic、Gemini 的状态码和错误体
+use serde_json::{json, Value};
+use std::fmt;
+
+/// 限流和过载时上游没给 Retry-After 的默认值（秒）
+const DEFAULT_RETRY_AFTER_SECS: u64 = 5;
+
+/// 错误类别
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpstreamErrorKind {
+    /// 请求参数无效
+    InvalidRequest,
+    /// 输入超出模型上下文窗口
+    ContextLengthExceeded,
+    /// 请求体超出上游允许的大小
+    RequestTooLarge,
+    /// 号池中账号的令牌无效或过期，与客户端的 API Key 无关
+    Authentication,
+    /// 账号无权访问
+    PermissionDenied,
+    /// 模型或资源不存在
+    NotFound,
+    /// 请求过于频繁
+    RateLimited,
+    /// 账号额度用尽
+    QuotaExhausted,
+    /// 上游暂时不可用
+    Overloaded,
+    /// 请求超时
+    Timeout,
+    /// 上游或本地的其他错误
+    ServerError,
+}
+
+/// 调用上游失败的原因
+#[derive(Debug, Clone)]
+pub struct UpstreamError {
+    pub kind: UpstreamErrorKind,
+    /// 返回给客户端的 HTTP 状态码（OpenAI 和 Gemini 格式）
+    pub status: u16,
+    pub message: String,
+    /// 建议客户端等待的秒数
+    pub retry_after: Option<u64>,
+}
+
+impl UpstreamError {
+    fn new(kind: UpstreamErrorKind, status: u16, message: String) -> Self {
+        Self {
+            kind,
+            status,
+            message,
+            retry_after: None,
+        }
+    }
+
+    /// 解析上游的错误响应
+    ///
+    /// 异常类型来自 `x-amzn-errortype` 头或响应体的 `__type` 字段，优先于状态码判断类别
+    pub fn from_response(status: u16, error_type: Option<&str>, body: &str, retry_after: Option<u64>) -> Self {
+        let parsed: Option<Value> = serde_json::from_str(body).ok();
+        let field = |key: &str| parsed.as_ref().and_then(|v| v.get(key)).and_then(|v| v.as_str());
+
+        // ThrottlingException:http://... 或 com.amazon.aws.codewhisperer#ThrottlingException
+        let exception = error_type
+            .or_else(|| field("__type"))
+            .map(|t| t.split(':').next().unwrap_or(t))
+            .map(|t| t.rsplit('#').next().unwrap_or(t).to_string());
+        let reason = field("reason").unwrap_or_default();
+        let detail = field("message").or_else(|| field("Message")).unwrap_or(body).trim();
+
+        let kind = classify(status, exception.as_deref(), reason, detail);
+        let message = match exception.as_deref() {
+            Some(exception) => format!("上游返回错误 {} ({}): {}", status, exception, detail),
+            None => format!("上游返回错误 {}: {}", status, detail),
+        };
+        let message = match kind {
+            UpstreamErrorKind::Authentication => format!("上游账号认证失败（与客户端 API Key 无关）: {}", message),
+            _ => message,
+        };
+        let mut error = Self::new(kind, kind.default_status(), message);
+        error.retry_after = retry_after.or(match kind {
+            UpstreamErrorKind::RateLimited | UpstreamErrorKind::Overloaded => Some(DEFAULT_RETRY_AFTER_SECS),
+            _ => None,
+        });
+        error
+    }
+
+    /// 请求没有到达上游或没有拿到完整响应
+    pub fn network(error: &reqwest::Error) -> Self {
+        if error.is_timeout() {
+            Self::new(UpstreamErrorKind::Timeout, 504, format!("请求上游超时: {}", error))
+        } else {
+            Self::new(UpstreamErrorKind::ServerError, 502, format!("请求失败: {}", error))
+        }
+    }
+
+    /// OpenAI 格式的状态码和错误体
+    pub fn openai_body(&self) -> (u16, Value) {
+        let (error_type, code) = match self.kind {
+            UpstreamErrorKind::InvalidRequest => ("invalid_request_error", "invalid_request"),
+            UpstreamErrorKind::ContextLengthExceeded => ("invalid_request_error", "context_length_exceeded"),
+            UpstreamErrorKind::RequestTooLarge => ("invalid_request_error", "request_too_large"),
+            UpstreamErrorKind::Authentication => ("server_error", "upstream_account_error"),
+            UpstreamErrorKind::PermissionDenied => ("permission_error", "permission_denied"),
+            UpstreamErrorKind::NotFound => ("invalid_request_error", "model_not_found"),
+            UpstreamErrorKind::RateLimited => ("rate_limit_exceeded", "rate_limit_exceeded"),
+            UpstreamErrorKind::QuotaExhausted => ("insufficient_quota", "insufficient_quota"),
+            UpstreamErrorKind::Overloaded => ("server_error", "service_unavailable"),
+            UpstreamErrorKind::Timeout => ("server_error", "timeout"),
+            UpstreamErrorKind::ServerError => ("server_error", "api_call_failed"),
+        };
+        (
+            self.status,
+            json!({
+                "error": {
+                    "message": self.message,
+                    "type": error_type,
+                    "param": null,
+                    "code": code
+                }
+            }),
+        )
+    }
+
+    /// Anthropic 格式的状态码和错误体，过载时按 Anthropic 的约定返回 529
+    pub fn anthropic_body(&self) -> (u16, Value) {
+        let (status, error_type) = match self.kind {
+            UpstreamErrorKind::InvalidRequest | UpstreamErrorKind::ContextLengthExceeded => (self.status, "invalid_request_error"),
+            UpstreamErrorKind::RequestTooLarge => (self.status, "request_too_large"),
+            UpstreamErrorKind::Authentication => (self.status, "api_error"),
+            UpstreamErrorKind::PermissionDenied => (self.status, "permission_error"),
+            UpstreamErrorKind::NotFound => (self.status, "not_found_error"),
+            UpstreamErrorKind::RateLimited | UpstreamErrorKind::QuotaExhausted => (self.status, "rate_limit_error"),
+            UpstreamErrorKind::Overloaded => (529, "overloaded_error"),
+            UpstreamErrorKind::Timeout => (self.status, "timeout_error"),
+            UpstreamErrorKind::ServerError => (self.status, "api_error"),
+        };
+        (
+            status,
+            json!({
+                "type": "error",
+                "error": {
+                    "type": error_type,
+                    "message": self.message
+                }
+            }),
+        )
+    }
+}
+
+impl UpstreamErrorKind {
+    /// 类别对应的 HTTP 状态码
+    fn default_status(self) -> u16 {
+        match self {
+            Self::InvalidRequest | Self::ContextLengthExceeded => 400,
+            Self::RequestTooLarge => 413,
+            // 客户端的 Key 是有效的，号池账号失效属于网关侧的问题
+            Self::Authentication => 502,
+            Self::PermissionDenied => 403,
+            Self::NotFound => 404,
+            Self::RateLimited | Self::QuotaExhausted => 429,
+            Self::Overloaded => 503,
+            Self::Timeout => 504,
+            Self::ServerError => 502,
+        }
+    }
+}
+
+/// 按异常类型和状态码判断错误类别，异常类型优先
+fn classify(status: u16, exception: Option<&str>, reason: &str, detail: &str) -> UpstreamErrorKind {
+    match exception {
+        Some("ValidationException") if is_context_length_error(reason, detail) => UpstreamErrorKind::ContextLengthExceeded,
+        Some("ValidationException") | Some("SerializationException") => UpstreamErrorKind::InvalidRequest,
+        Some("ThrottlingException") if is_quota_error(reason, detail) => UpstreamErrorKind::QuotaExhausted,
+        Some("ThrottlingException") => UpstreamErrorKind::RateLimited,
+        Some("ServiceQuotaExceededException") => UpstreamErrorKind::QuotaExhausted,
+        Some("ExpiredTokenException") | Some("UnrecognizedClientException") | Some("InvalidTokenException") => {
+            UpstreamErrorKind::Authentication
+        }
+        Some("AccessDeniedException") if is_token_error(detail) => UpstreamErrorKind::Authentication,
+        Some("AccessDeniedException") => UpstreamErrorKind::PermissionDenied,
+        Some("ResourceNotFoundException") => UpstreamErrorKind::NotFound,
+        Some("ServiceUnavailableException") | Some("ModelOverloadedException") => UpstreamErrorKind::Overloaded,
+        Some("InternalServerException") => UpstreamErrorKind::ServerError,
+        _ => match status {
+            400 | 422 if is_context_length_error(reason, detail) => UpstreamErrorKind::ContextLengthExceeded,
+            400 | 422 => UpstreamErrorKind::InvalidRequest,
+            401 => UpstreamErrorKind::Authentication,
+            403 if is_token_error(detail) => UpstreamErrorKind::Authentication,
+            403 => UpstreamErrorKind::PermissionDenied,
+            404 => UpstreamErrorKind::NotFound,
+            413 => UpstreamErrorKind::RequestTooLarge,
+            429 if is_quota_error(reason, detail) => UpstreamErrorKind::QuotaExhausted,
+            429 => UpstreamErrorKind::RateLimited,
+            503 | 529 => UpstreamErrorKind::Overloaded,
+            504 => UpstreamErrorKind::Timeout,
+            _ => UpstreamErrorKind::ServerError,
+        },
+    }
+}
+
+/// 输入过长的 ValidationException
+fn is_context_length_error(reason: &str, message: &str) -> bool {
+    let message = message.to_ascii_lowercase();
+    reason == "CONTENT_LENGTH_EXCEEDS_THRESHOLD"
+        || message.contains("too long")
+        || message.contains("context length")
+        || message.contains("content length")
+}
+
+/// 额度用尽而不是短时限流
+fn is_quota_error(reason: &str, message: &str) -> bool {
+    reason.contains("MONTHLY") || reason.contains("QUOTA") || message.to_ascii_lowercase().contains("quota")
+}
+
+/// 令牌无效或过期导致的 403
+fn is_token_error(message: &str) -> bool {
+    let message = message.to_ascii_lowercase();
+    message.contains("token") && (message.contains("invalid") || message.contains("expired"))
+}
+
+impl fmt::Display for UpstreamError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+/// 本地错误（构造请求头、序列化等）
+impl From<String> for UpstreamError {
+    fn from(message: String) -> Self {
+        Self::new(UpstreamErrorKind::ServerError, 500, message)
+    }
+}
+
+impl From<&str> for UpstreamError {
+    fn from(message: &str) -> Self {
+        Self::from(message.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kind_of(exception: &str) -> UpstreamErrorKind {
+        classify(400, Some(exception), "", "")
+    }
+
+    #[test]
+    fn classify_kiro_exception_types() {
+        use UpstreamErrorKind::*;
+        assert_eq!(kind_of("ValidationException"), InvalidRequest);
+        assert_eq!(kind_of("SerializationException"), InvalidRequest);
+        assert_eq!(
+            classify(400, Some("ValidationException"), "CONTENT_LENGTH_EXCEEDS_THRESHOLD", ""),
+            ContextLengthExceeded
+        );
+        assert_eq!(classify(400, Some("ValidationException"), "", "Input is too long"), ContextLengthExceeded);
+        assert_eq!(kind_of("ThrottlingException"), RateLimited);
+        assert_eq!(classify(429, Some("ThrottlingException"), "MONTHLY_REQUEST_COUNT", ""), QuotaExhausted);
+        assert_eq!(kind_of("ServiceQuotaExceededException"), QuotaExhausted);
+        assert_eq!(kind_of("ExpiredTokenException"), Authentication);
+        assert_eq!(kind_of("UnrecognizedClientException"), Authentication);
+        assert_eq!(kind_of("InvalidTokenException"), Authentication);
+        assert_eq!(classify(403, Some("AccessDeniedException"), "", "The bearer token is expired"), Authentication);
+        assert_eq!(kind_of("AccessDeniedException"), PermissionDenied);
+        assert_eq!(kind_of("ResourceNotFoundException"), NotFound);
+        assert_eq!(kind_of("ServiceUnavailableException"), Overloaded);
+        assert_eq!(kind_of("ModelOverloadedException"), Overloaded);
+        assert_eq!(kind_of("InternalServerException"), ServerError);
+        // 未知异常类型按状态码判断
+        assert_eq!(classify(429, Some("SomethingNewException"), "", ""), RateLimited);
+    }
+
+    #[test]
+    fn classify_by_status_without_exception_type() {
+        use UpstreamErrorKind::*;
+        assert_eq!(classify(400, None, "", ""), InvalidRequest);
+        assert_eq!(classify(422, None, "", "context length exceeded"), ContextLengthExceeded);
+        assert_eq!(classify(401, None, "", ""), Authentication);
+        assert_eq!(classify(403, None, "", "Invalid token"), Authentication);
+        assert_eq!(classify(403, None, "", ""), PermissionDenied);
+        assert_eq!(classify(404, None, "", ""), NotFound);
+        assert_eq!(classify(413, None, "", ""), RequestTooLarge);
+        assert_eq!(classify(429, None, "", "quota exceeded"), QuotaExhausted);
+        assert_eq!(classify(429, None, "", ""), RateLimited);
+        assert_eq!(classify(503, None, "", ""), Overloaded);
+        assert_eq!(classify(529, None, "", ""), Overloaded);
+        assert_eq!(classify(504, None, "", ""), Timeout);
+        assert_eq!(classify(500, None, "", ""), ServerError);
+    }
+
+    #[test]
+    fn from_response_reads_exception_type_from_header_or_body() {
+        let error = UpstreamError::from_response(
+            400,
+            Some("ThrottlingException:http://internal.amazon.com/coral/"),
+            "{}",
+            None,
+        );
+        assert_eq!(error.kind, UpstreamErrorKind::RateLimited);
+
+        let body = r#"{"__type":"com.amazon.aws.codewhisperer#ServiceQuotaExceededException","message":"limit reached"}"#;
+        let error = UpstreamError::from_response(400, None, body, None);
+        assert_eq!(error.kind, UpstreamErrorKind::QuotaExhausted);
+        assert_eq!(error.status, 429);
+        assert!(error.message.ends_with("(ServiceQuotaExceededException): limit reached"));
+    }
+
+    #[test]
+    fn retry_after_uses_upstream_value_or_default() {
+        assert_eq!(UpstreamError::from_response(429, None, "", Some(30)).retry_after, Some(30));
+        assert_eq!(UpstreamError::from_response(429, None, "", None).retry_after, Some(DEFAULT_RETRY_AFTER_SECS));
+        assert_eq!(UpstreamError::from_response(503, None, "", None).retry_after, Some(DEFAULT_RETRY_AFTER_SECS));
+        assert_eq!(UpstreamError::from_response(400, None, "", None).retry_after, None);
+    }
+
+    #[test]
+    fn account_auth_failures_are_gateway_errors() {
+        let error = UpstreamError::from_response(403, Some("ExpiredTokenException"), "", None);
+        assert_eq!(error.status, 502);
+        assert!(error.message.starts_with("上游账号认证失败"));
+
+        let (status, body) = error.openai_body();
+        assert_eq!(status, 502);
+        assert_eq!(body["error"]["type"], "server_error");
+        assert_eq!(body["error"]["code"], "upstream_account_error");
+
+        let (status, body) = error.anthropic_body();
+        assert_eq!(status, 502);
+        assert_eq!(body["error"]["type"], "api_error");
+    }
+
+    #[test]
+    fn openai_envelope() {
+        let body = r#"{"reason":"CONTENT_LENGTH_EXCEEDS_THRESHOLD"}"#;
+        let (status, body) = UpstreamError::from_response(400, Some("ValidationException"), body, None).openai_body();
+        assert_eq!(status, 400);
+        assert_eq!(body["error"]["type"], "invalid_request_error");
+        assert_eq!(body["error"]["code"], "context_length_exceeded");
+        assert!(body["error"]["param"].is_null());
+        assert!(body["error"]["message"].is_string());
+
+        let (status, body) = UpstreamError::from_response(429, None, "", None).openai_body();
+        assert_eq!(status, 429);
+        assert_eq!(body["error"]["type"], "rate_limit_exceeded");
+        assert_eq!(body["error"]["code"], "rate_limit_exceeded");
+
+        let (status, body) = UpstreamError::from_response(413, None, "", None).openai_body();
+        assert_eq!(status, 413);
+        assert_eq!(body["error"]["code"], "request_too_large");
+    }
+
+    #[test]
+    fn anthropic_envelope() {
+        let (status, body) = UpstreamError::from_response(413, None, "", None).anthropic_body();
+        assert_eq!(status, 413);
+        assert_eq!(body["type"], "error");
+        assert_eq!(body["error"]["type"], "request_too_large");
+
+        let (status, body) = UpstreamError::from_response(503, None, "", None).anthropic_body();
+        assert_eq!(status, 529);
+        assert_eq!(body["error"]["type"], "overloaded_error");
+
+        let (status, body) = UpstreamError::from_response(429, None, "", None).anthropic_body();
+        assert_eq!(status, 429);
+        assert_eq!(body["error"]["type"], "rate_limit_error");
+
+        let (status, body) = UpstreamError::from_response(404, None, "", None).anthropic_body();
+        assert_eq!(status, 404);
+        assert_eq!(body["error"]["type"], "not_found_error");
+    }
+}
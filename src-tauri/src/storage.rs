@@ -16,7 +16,7 @@ fn get_data_dir() -> Result<PathBuf, String> {
 }
 
 // 保存自定义 Logo
-#[tauri::command]
+#[cfg_attr(feature = "gui", tauri::command)]
 pub async fn save_custom_logo(source_path: String) -> Result<String, String> {
     let data_dir = get_data_dir()?;
     
@@ -40,7 +40,7 @@ pub async fn save_custom_logo(source_path: String) -> Result<String, String> {
 }
 
 // 删除自定义 Logo
-#[tauri::command]
+#[cfg_attr(feature = "gui", tauri::command)]
 pub async fn delete_custom_logo() -> Result<(), String> {
     let data_dir = get_data_dir()?;
     
@@ -56,11 +56,20 @@ pub async fn delete_custom_logo() -> Result<(), String> {
     Ok(())
 }
 
+// 获取账号数据文件路径
+pub fn accounts_file_path() -> Result<PathBuf, String> {
+    Ok(get_data_dir()?.join("accounts.json"))
+}
+
+// 网络设置文件路径
+pub fn network_settings_path() -> Result<PathBuf, String> {
+    Ok(get_data_dir()?.join("network.json"))
+}
+
 // 加载账号数据
-#[tauri::command]
+#[cfg_attr(feature = "gui", tauri::command)]
 pub async fn load_accounts() -> Result<String, String> {
-    let data_dir = get_data_dir()?;
-    let accounts_file = data_dir.join("accounts.json");
+    let accounts_file = accounts_file_path()?;
     
     if !accounts_file.exists() {
         return Ok("[]".to_string());
@@ -73,10 +82,9 @@ pub async fn load_accounts() -> Result<String, String> {
 }
 
 // 保存账号数据
-#[tauri::command]
+#[cfg_attr(feature = "gui", tauri::command)]
 pub async fn save_accounts(data: String) -> Result<(), String> {
-    let data_dir = get_data_dir()?;
-    let accounts_file = data_dir.join("accounts.json");
+    let accounts_file = accounts_file_path()?;
     
     fs::write(accounts_file, data)
         .map_err(|e| format!("保存账号数据失败: {}", e))?;
@@ -101,7 +109,7 @@ pub struct LocalActiveAccountData {
 }
 
 // 读取本地活跃账号
-#[tauri::command]
+#[cfg_attr(feature = "gui", tauri::command)]
 pub async fn get_local_active_account() -> Result<LocalActiveAccountResponse, String> {
     println!("[本地账号] 开始读取本地 SSO 缓存");
     
@@ -21,7 +21,7 @@ struct TokenCache {
 }
 
 // 获取当前激活的账号信息
-#[tauri::command]
+#[cfg_attr(feature = "gui", tauri::command)]
 pub async fn get_active_account() -> Result<Option<String>, String> {
     let home_dir = std::env::var("USERPROFILE")
         .or_else(|_| std::env::var("HOME"))
@@ -51,7 +51,9 @@ pub async fn get_active_account() -> Result<Option<String>, String> {
 }
 
 // 切换账号 - 写入凭证到本地 SSO 缓存
-#[tauri::command]
+// 参数与前端 invoke 的字段一一对应，不合并成结构体
+#[cfg_attr(feature = "gui", tauri::command)]
+#[allow(clippy::too_many_arguments)]
 pub async fn switch_account(
     access_token: String,
     refresh_token: String,
@@ -151,7 +153,7 @@ pub async fn switch_account(
 }
 
 // 退出登录 - 清除本地 SSO 缓存
-#[tauri::command]
+#[cfg_attr(feature = "gui", tauri::command)]
 pub async fn logout_account() -> Result<SwitchAccountResponse, String> {
     println!("[退出登录] 开始清除 SSO 缓存");
     
@@ -18,7 +18,9 @@ export async function refreshAccount(account: Account): Promise<void> {
       refreshToken: account.credentials.refreshToken,
       clientId: account.credentials.clientId,
       clientSecret: account.credentials.clientSecret,
-      region: account.credentials.region || 'us-east-1'
+      region: account.credentials.region || 'us-east-1',
+      outboundProxy: account.outboundProxy,
+      headerProfile: account.headerProfile
     })
 
     if (result.success && result.data) {
@@ -103,7 +105,9 @@ export async function refreshTokenOnly(account: Account): Promise<void> {
       refreshToken: account.credentials.refreshToken,
       clientId: account.credentials.clientId,
       clientSecret: account.credentials.clientSecret,
-      region: account.credentials.region || 'us-east-1'
+      region: account.credentials.region || 'us-east-1',
+      outboundProxy: account.outboundProxy,
+      headerProfile: account.headerProfile
     })
 
     if (result.success && result.data) {
@@ -247,7 +251,9 @@ export async function handleBatchCheck(selectedIds: Set<string>): Promise<void>
           clientSecret: account.credentials.clientSecret,
           region: account.credentials.region || 'us-east-1',
           authMethod: account.credentials.authMethod || 'IdC',
-          provider: account.credentials.provider || account.idp
+          provider: account.credentials.provider || account.idp,
+          outboundProxy: account.outboundProxy,
+          headerProfile: account.headerProfile
         })
 
         if (result.success) {
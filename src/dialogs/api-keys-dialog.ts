@@ -1,5 +1,6 @@
 // API Keys 管理对话框
 import type { ApiKey } from '../types/proxy'
+import { proxyService } from '../services/proxy-service'
 
 export function showApiKeysDialog(apiKeys: ApiKey[], onConfirm?: (keys: ApiKey[]) => void): void {
   const keys = [...apiKeys]
@@ -20,7 +21,7 @@ export function showApiKeysDialog(apiKeys: ApiKey[], onConfirm?: (keys: ApiKey[]
           <input type="checkbox" class="api-key-enabled" data-index="${index}" ${checked} style="width: 16px; height: 16px; cursor: pointer;" />
           <div style="flex: 1; min-width: 0;">
             <div style="font-weight: 600; color: var(--text-main); margin-bottom: 4px;">${key.name}</div>
-            <div style="font-family: monospace; font-size: 12px; color: var(--text-muted);">${key.key}</div>
+            <div style="font-family: monospace; font-size: 12px; color: var(--text-muted);">${key.prefix}...</div>
           </div>
           <button class="ui-btn ui-btn-danger ui-btn-sm" onclick="window.removeApiKey(${index})">删除</button>
         </div>
@@ -67,6 +68,29 @@ export function showApiKeysDialog(apiKeys: ApiKey[], onConfirm?: (keys: ApiKey[]
     }
   }
 
+  // 新 Key 的明文只显示在这里，关闭对话框后无法再次查看
+  function showNewKey(key: string): void {
+    const container = document.getElementById('add-key-container')
+    if (!container) return
+
+    container.innerHTML = `
+      <div style="padding: 16px; background: var(--slate-50); border-radius: 6px; margin-bottom: 12px;">
+        <div style="margin-bottom: 8px; font-size: 13px; font-weight: 500;">新的 API Key 只显示这一次，请立即复制保存</div>
+        <div style="display: flex; gap: 8px; align-items: center;">
+          <input type="text" class="ui-input" readonly value="${key}" style="flex: 1; padding: 8px; font-family: monospace;" />
+          <button class="ui-btn ui-btn-secondary ui-btn-sm" id="copy-new-key">复制</button>
+        </div>
+        <div style="margin-top: 12px;">
+          <button class="ui-btn ui-btn-primary ui-btn-sm" onclick="window.addApiKey()">添加 Key</button>
+        </div>
+      </div>
+    `
+    document.getElementById('copy-new-key')?.addEventListener('click', async () => {
+      await navigator.clipboard.writeText(key)
+      window.UI?.toast.success('已复制到剪贴板')
+    })
+  }
+
   function hideAddKeyForm(): void {
     const container = document.getElementById('add-key-container')
     if (container) {
@@ -105,7 +129,7 @@ export function showApiKeysDialog(apiKeys: ApiKey[], onConfirm?: (keys: ApiKey[]
     hideAddKeyForm()
   }
 
-  window.confirmAddKey = () => {
+  window.confirmAddKey = async () => {
     const input = document.getElementById('new-key-name') as HTMLInputElement
     const name = input?.value.trim()
     
@@ -114,18 +138,15 @@ export function showApiKeysDialog(apiKeys: ApiKey[], onConfirm?: (keys: ApiKey[]
       return
     }
 
-    const key = 'sk-' + Math.random().toString(36).substring(2, 15) + Math.random().toString(36).substring(2, 15)
-    keys.push({
-      id: Date.now().toString(),
-      name,
-      key,
-      enabled: true,
-      usage: { totalRequests: 0, totalCredits: 0, totalInputTokens: 0, totalOutputTokens: 0, daily: {}, byModel: {} },
-      createdAt: Date.now()
-    })
-    
-    hideAddKeyForm()
-    renderList()
+    // 由后端生成 Key，列表中只保留前缀和哈希
+    try {
+      const { apiKey, key } = await proxyService.generateApiKey(name)
+      keys.push(apiKey)
+      showNewKey(key)
+      renderList()
+    } catch (error) {
+      window.UI?.toast.error('生成 API Key 失败: ' + error)
+    }
   }
 
   window.removeApiKey = (index: number) => {
@@ -45,6 +45,34 @@ export function showEditAccountDialog(account: Account): void {
           <label class="form-label">Refresh Token</label>
           <textarea class="form-input form-textarea" id="edit-account-refresh-token" rows="3">${account.credentials.refreshToken || ''}</textarea>
         </div>
+        <div class="form-section">
+          <label class="form-label">出站代理</label>
+          <input type="text" class="form-input" id="edit-account-proxy-url" value="${account.outboundProxy?.url || ''}" placeholder="留空使用全局代理，例如 socks5://127.0.0.1:1080">
+        </div>
+        <div class="form-row">
+          <div class="form-section">
+            <label class="form-label">代理用户名</label>
+            <input type="text" class="form-input" id="edit-account-proxy-username" value="${account.outboundProxy?.username || ''}">
+          </div>
+          <div class="form-section">
+            <label class="form-label">代理密码</label>
+            <input type="password" class="form-input" id="edit-account-proxy-password" value="${account.outboundProxy?.password || ''}">
+          </div>
+        </div>
+        <div class="form-row">
+          <div class="form-section">
+            <label class="form-label">Kiro IDE 版本</label>
+            <input type="text" class="form-input" id="edit-account-ide-version" value="${account.headerProfile?.ideVersion || ''}" placeholder="留空使用全局配置">
+          </div>
+          <div class="form-section">
+            <label class="form-label">操作系统</label>
+            <input type="text" class="form-input" id="edit-account-os" value="${account.headerProfile?.os || ''}" placeholder="windows / macos / linux">
+          </div>
+          <div class="form-section">
+            <label class="form-label">Agent 模式</label>
+            <input type="text" class="form-input" id="edit-account-agent-mode" value="${account.headerProfile?.agentMode || ''}" placeholder="spec / vibe">
+          </div>
+        </div>
       </div>
     `,
     footer: `
@@ -75,6 +103,12 @@ export function showEditAccountDialog(account: Account): void {
     const idp = (document.getElementById('edit-account-idp') as HTMLInputElement)?.value
     const accessToken = (document.getElementById('edit-account-access-token') as HTMLTextAreaElement)?.value
     const refreshToken = (document.getElementById('edit-account-refresh-token') as HTMLTextAreaElement)?.value
+    const proxyUrl = (document.getElementById('edit-account-proxy-url') as HTMLInputElement)?.value.trim()
+    const proxyUsername = (document.getElementById('edit-account-proxy-username') as HTMLInputElement)?.value.trim()
+    const proxyPassword = (document.getElementById('edit-account-proxy-password') as HTMLInputElement)?.value
+    const ideVersion = (document.getElementById('edit-account-ide-version') as HTMLInputElement)?.value.trim()
+    const os = (document.getElementById('edit-account-os') as HTMLInputElement)?.value.trim()
+    const agentMode = (document.getElementById('edit-account-agent-mode') as HTMLInputElement)?.value.trim()
 
     // 输入验证
     if (!email || !accessToken) {
@@ -100,6 +134,12 @@ export function showEditAccountDialog(account: Account): void {
       return
     }
 
+    // 代理地址格式验证
+    if (proxyUrl && !/^(https?|socks5h?):\/\/[^\s]+$/.test(proxyUrl)) {
+      window.UI?.toast.error('代理地址格式不正确，支持 http、https、socks5')
+      return
+    }
+
     accountStore.updateAccount(account.id, {
       email,
       nickname: nickname || undefined,
@@ -108,7 +148,19 @@ export function showEditAccountDialog(account: Account): void {
         ...account.credentials,
         accessToken,
         refreshToken: refreshToken || undefined
-      }
+      },
+      outboundProxy: proxyUrl
+        ? { url: proxyUrl, username: proxyUsername || undefined, password: proxyPassword || undefined }
+        : undefined,
+      // 只保存填写的字段，其余沿用全局请求头配置
+      headerProfile: ideVersion || os || agentMode
+        ? {
+            ...account.headerProfile,
+            ideVersion: ideVersion || undefined,
+            os: os || undefined,
+            agentMode: agentMode || undefined
+          }
+        : undefined
     })
 
     window.UI?.toast.success('账号更新成功')
@@ -66,7 +66,9 @@ async function loadModels(account: Account) {
     // 调用后端 API 获取模型列表
     const result = await (window as any).__TAURI__.core.invoke('get_account_models', {
       accessToken: account.credentials.accessToken,
-      region: account.credentials.region || 'us-east-1'
+      region: account.credentials.region || 'us-east-1',
+      outboundProxy: account.outboundProxy,
+      headerProfile: account.headerProfile
     })
 
     if (result.success && result.models) {
@@ -37,3 +37,15 @@ if (window.__TAURI__) {
     }, 500)
   })
 }
+
+// 反代服务自动启动结果，监听注册后通知后端发送（启动早于页面加载时结果由后端暂存）
+if (window.__TAURI__) {
+  window.__TAURI__.event.listen<{ success: boolean; address?: string; error?: string }>('proxy-auto-start', (event) => {
+    const result = event.payload
+    if (result.success) {
+      window.UI?.toast.success(`反代服务已自动启动: ${result.address}`)
+    } else {
+      window.UI?.toast.error('反代服务自动启动失败: ' + result.error)
+    }
+  }).then(() => window.__TAURI__?.core.invoke('proxy_frontend_ready'))
+}
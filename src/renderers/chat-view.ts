@@ -1,5 +1,6 @@
 // 对话视图渲染器
 import { accountStore } from '../store'
+import type { HeaderProfile, OutboundProxy } from '../types'
 import { loadChatConfig, type ChatConfig } from './chat-config-view'
 import claudeSvg from '../assets/claude-color.svg'
 import openaiSvg from '../assets/openai.svg'
@@ -273,6 +274,8 @@ async function loadChatModels() {
     
     let accessToken: string
     let region: string
+    let outboundProxy: OutboundProxy | undefined
+    let headerProfile: Partial<HeaderProfile> | undefined
     
     // 如果启用了自定义 AWS API
     if (chatConfig.useCustomApi && !chatConfig.useOpenAIFormat) {
@@ -324,12 +327,16 @@ async function loadChatModels() {
       
       accessToken = activeAccount.credentials.accessToken
       region = activeAccount.credentials.region || 'us-east-1'
+      outboundProxy = activeAccount.outboundProxy
+      headerProfile = activeAccount.headerProfile
     }
     
     // 获取可用模型
     const response = await invoke('get_account_models', {
       accessToken: accessToken,
-      region: region
+      region: region,
+      outboundProxy,
+      headerProfile
     }) as any
     
     if (!response.success || !response.models || response.models.length === 0) {
@@ -452,6 +459,8 @@ async function sendMessage() {
     
     let accessToken: string
     let region: string
+    let outboundProxy: OutboundProxy | undefined
+    let headerProfile: Partial<HeaderProfile> | undefined
     
     // 如果启用了自定义 API
     if (chatConfig.useCustomApi) {
@@ -505,6 +514,8 @@ async function sendMessage() {
       
       accessToken = activeAccount.credentials.accessToken
       region = activeAccount.credentials.region || 'us-east-1'
+      outboundProxy = activeAccount.outboundProxy
+      headerProfile = activeAccount.headerProfile
     }
     
     // 获取对话历史
@@ -527,7 +538,9 @@ async function sendMessage() {
       temperature: useOpenAI ? 0.7 : null,
       maxTokens: useOpenAI ? 4096 : null,
       useCustomApi: useOpenAI,
-      customBaseUrl: useOpenAI ? chatConfig.customBaseUrl : null
+      customBaseUrl: useOpenAI ? chatConfig.customBaseUrl : null,
+      outboundProxy,
+      headerProfile
     }) as any
     
     // 移除加载消息
@@ -70,6 +70,19 @@ function renderStatusSection(isRunning: boolean, config: ProxyConfig): string {
         </div>
         <button class="ui-btn ui-btn-secondary ui-btn-sm" id="copy-address">复制地址</button>
       </div>
+
+      <div class="settings-item">
+        <div class="settings-item-info">
+          <div class="settings-item-label">自动启动</div>
+          <div class="settings-item-desc">打开应用时自动同步账号并启动反代服务</div>
+        </div>
+        <label class="ui-switch">
+          <input type="checkbox" id="auto-start-switch" ${config.autoStart ? 'checked' : ''}>
+          <span class="ui-switch-track">
+            <span class="ui-switch-thumb"></span>
+          </span>
+        </label>
+      </div>
     </div>
   `
 }
@@ -130,7 +143,7 @@ function renderStatsSection(stats: ProxyStats | null, sessionStats: SessionStats
             </span>
             <span>
               <span style="color: var(--text-muted); font-size: 12px;">总 Credits:</span>
-              <span style="color: var(--text-main); font-weight: 600; margin-left: 4px;" id="stat-total-credits">${stats.totalCredits.toFixed(2)}</span>
+              <span style="color: var(--text-main); font-weight: 600; margin-left: 4px;" id="stat-total-credits" title="其中按模型倍率估算: ${(stats.estimatedCredits ?? 0).toFixed(2)}">${stats.totalCredits.toFixed(2)}</span>
             </span>
           </div>
         </div>
@@ -249,6 +262,8 @@ export async function initProxyPage(container: HTMLElement) {
           clientSecret: acc.credentials.clientSecret,
           region: acc.credentials.region,
           authMethod: acc.credentials.authMethod,
+          outboundProxy: acc.outboundProxy,
+          headerProfile: acc.headerProfile,
           isAvailable: acc.status !== 'suspended' && acc.status !== 'error',
           lastUsed: 0,
           requestCount: 0,
@@ -297,7 +312,10 @@ export async function initProxyPage(container: HTMLElement) {
       successRateEl.textContent = `${rate}%`
     }
     if (totalTokensEl) totalTokensEl.textContent = stats.totalTokens.toLocaleString()
-    if (totalCreditsEl) totalCreditsEl.textContent = stats.totalCredits.toFixed(2)
+    if (totalCreditsEl) {
+      totalCreditsEl.textContent = stats.totalCredits.toFixed(2)
+      totalCreditsEl.setAttribute('title', `其中按模型倍率估算: ${(stats.estimatedCredits ?? 0).toFixed(2)}`)
+    }
     
     // 更新会话统计
     if (sessionStats) {
@@ -411,6 +429,15 @@ export async function initProxyPage(container: HTMLElement) {
       }
     })
 
+    // 自动启动开关
+    const autoStartSwitch = container.querySelector('#auto-start-switch') as HTMLInputElement
+    autoStartSwitch?.addEventListener('change', async () => {
+      if (currentConfig) {
+        currentConfig.autoStart = autoStartSwitch.checked
+        await saveConfig()
+      }
+    })
+
     // 选择账号
     const selectAccountsBtn = container.querySelector('#select-accounts') as HTMLButtonElement
     selectAccountsBtn?.addEventListener('click', () => {
@@ -438,6 +465,8 @@ export async function initProxyPage(container: HTMLElement) {
                 clientSecret: acc.credentials.clientSecret,
                 region: acc.credentials.region,
                 authMethod: acc.credentials.authMethod,
+                outboundProxy: acc.outboundProxy,
+                headerProfile: acc.headerProfile,
                 isAvailable: acc.status !== 'suspended' && acc.status !== 'error', // 排除封禁和错误状态
                 lastUsed: 0,
                 requestCount: 0,
@@ -1,6 +1,8 @@
 import { autoRefreshService } from '../services/auto-refresh-service'
 import { accountStore } from '../store'
 import { proxyService } from '../services/proxy-service'
+import type { HeaderProfile, OutboundProxy } from '../types'
+import type { ProxyConfig } from '../types/proxy'
 
 export async function renderSettingsView(): Promise<string> {
   const config = autoRefreshService.getConfig()
@@ -13,7 +15,9 @@ export async function renderSettingsView(): Promise<string> {
     logRequests: true,
     maxRetries: 3,
     enableOpenAI: true,
-    enableClaude: true
+    enableClaude: true,
+    enableGemini: true,
+    retryInvalidJson: true
   }
   
   try {
@@ -25,6 +29,17 @@ export async function renderSettingsView(): Promise<string> {
     console.error('[设置] 加载代理配置失败:', error)
   }
   
+  // 加载网络设置
+  let outboundProxy: OutboundProxy | null = null
+  let headerProfile: Partial<HeaderProfile> = {}
+  try {
+    const network = await (window as any).__TAURI__.core.invoke('get_network_settings')
+    outboundProxy = network.outboundProxy || null
+    headerProfile = network.headerProfile || {}
+  } catch (error) {
+    console.error('[设置] 加载网络设置失败:', error)
+  }
+  
   // 格式化显示文本
   let intervalText = ''
   if (config.interval < 1) {
@@ -252,10 +267,30 @@ export async function renderSettingsView(): Promise<string> {
           <input type="number" class="ui-input" id="max-retries" value="${proxyConfig.maxRetries || 3}" min="0" max="10" style="width: 100px; padding: 6px 10px;" />
         </div>
 
+        <div class="settings-item">
+          <div class="settings-item-info">
+            <div class="settings-item-label">自动继续轮数</div>
+            <div class="settings-item-desc">输出因长度限制被截断时自动请求继续的最大轮数，0 表示不继续</div>
+          </div>
+          <input type="number" class="ui-input" id="auto-continue-rounds" value="${proxyConfig.autoContinueRounds || 0}" min="0" max="10" style="width: 100px; padding: 6px 10px;" />
+        </div>
+
+        <div class="settings-item">
+          <div class="settings-item-info">
+            <div class="settings-item-label">上下文超限处理</div>
+            <div class="settings-item-desc">输入超出模型上下文窗口时的处理方式，上限来自模型列表</div>
+          </div>
+          <select class="ui-input" id="context-overflow-policy" style="width: 160px; padding: 6px 10px;">
+            <option value="truncate" ${(proxyConfig.contextOverflowPolicy || 'truncate') === 'truncate' ? 'selected' : ''}>丢弃最早的对话</option>
+            <option value="summarize" ${proxyConfig.contextOverflowPolicy === 'summarize' ? 'selected' : ''}>截断工具结果</option>
+            <option value="reject" ${proxyConfig.contextOverflowPolicy === 'reject' ? 'selected' : ''}>直接拒绝</option>
+          </select>
+        </div>
+
         <div class="settings-item">
           <div class="settings-item-info">
             <div class="settings-item-label">启用 OpenAI API</div>
-            <div class="settings-item-desc">启用 /v1/chat/completions 端点</div>
+            <div class="settings-item-desc">启用 /v1/chat/completions、/v1/completions 和 /v1/responses 端点</div>
           </div>
           <label class="ui-switch">
             <input type="checkbox" id="enable-openai" ${proxyConfig.enableOpenAI !== false ? 'checked' : ''}>
@@ -277,6 +312,98 @@ export async function renderSettingsView(): Promise<string> {
             </span>
           </label>
         </div>
+
+        <div class="settings-item">
+          <div class="settings-item-info">
+            <div class="settings-item-label">启用 Gemini API</div>
+            <div class="settings-item-desc">启用 /v1beta/models/{model}:generateContent 和 :streamGenerateContent 端点</div>
+          </div>
+          <label class="ui-switch">
+            <input type="checkbox" id="enable-gemini" ${proxyConfig.enableGemini !== false ? 'checked' : ''}>
+            <span class="ui-switch-track">
+              <span class="ui-switch-thumb"></span>
+            </span>
+          </label>
+        </div>
+
+        <div class="settings-item">
+          <div class="settings-item-info">
+            <div class="settings-item-label">JSON 输出校验失败时重试</div>
+            <div class="settings-item-desc">客户端指定 response_format 且输出不符合要求时，带上错误信息重新请求一次</div>
+          </div>
+          <label class="ui-switch">
+            <input type="checkbox" id="retry-invalid-json" ${proxyConfig.retryInvalidJson !== false ? 'checked' : ''}>
+            <span class="ui-switch-track">
+              <span class="ui-switch-thumb"></span>
+            </span>
+          </label>
+        </div>
+
+        <div class="settings-item">
+          <div class="settings-item-info">
+            <div class="settings-item-label">隐藏思考过程</div>
+            <div class="settings-item-desc">不向客户端返回模型的思考内容（thinking 块和 reasoning_content）</div>
+          </div>
+          <label class="ui-switch">
+            <input type="checkbox" id="strip-thinking" ${proxyConfig.stripThinking ? 'checked' : ''}>
+            <span class="ui-switch-track">
+              <span class="ui-switch-thumb"></span>
+            </span>
+          </label>
+        </div>
+      </div>
+
+      <div class="settings-section">
+        <h3 class="settings-section-title">网络</h3>
+        
+        <div class="settings-item">
+          <div class="settings-item-info">
+            <div class="settings-item-label">出站代理</div>
+            <div class="settings-item-desc">访问 Kiro 接口时使用的代理，支持 http、https、socks5，留空直连（账号单独设置的代理优先）</div>
+          </div>
+          <input type="text" class="ui-input" id="outbound-proxy-url" value="${outboundProxy?.url || ''}" placeholder="socks5://127.0.0.1:1080" style="width: 240px; padding: 6px 10px;" />
+        </div>
+
+        <div class="settings-item">
+          <div class="settings-item-info">
+            <div class="settings-item-label">代理认证</div>
+            <div class="settings-item-desc">代理需要认证时填写用户名和密码</div>
+          </div>
+          <div style="display: flex; gap: 8px;">
+            <input type="text" class="ui-input" id="outbound-proxy-username" value="${outboundProxy?.username || ''}" placeholder="用户名" style="width: 116px; padding: 6px 10px;" />
+            <input type="password" class="ui-input" id="outbound-proxy-password" value="${outboundProxy?.password || ''}" placeholder="密码" style="width: 116px; padding: 6px 10px;" />
+          </div>
+        </div>
+
+        <div class="settings-item">
+          <div class="settings-item-info">
+            <div class="settings-item-label">Kiro IDE 版本</div>
+            <div class="settings-item-desc">请求头中模拟的 Kiro IDE 版本，Kiro 更新后可直接修改</div>
+          </div>
+          <input type="text" class="ui-input" id="header-ide-version" value="${headerProfile.ideVersion || ''}" placeholder="0.6.18" style="width: 120px; padding: 6px 10px;" />
+        </div>
+
+        <div class="settings-item">
+          <div class="settings-item-info">
+            <div class="settings-item-label">操作系统 / Agent 模式</div>
+            <div class="settings-item-desc">请求头中的操作系统（windows、macos、linux）和 Agent 模式（spec、vibe）</div>
+          </div>
+          <div style="display: flex; gap: 8px;">
+            <input type="text" class="ui-input" id="header-os" value="${headerProfile.os || ''}" placeholder="windows" style="width: 116px; padding: 6px 10px;" />
+            <input type="text" class="ui-input" id="header-agent-mode" value="${headerProfile.agentMode || ''}" placeholder="spec" style="width: 116px; padding: 6px 10px;" />
+          </div>
+        </div>
+
+        <div class="settings-item">
+          <div class="settings-item-info">
+            <div class="settings-item-label">SDK / Node.js 版本</div>
+            <div class="settings-item-desc">User-Agent 中的 aws-sdk-js 和 Node.js 版本</div>
+          </div>
+          <div style="display: flex; gap: 8px;">
+            <input type="text" class="ui-input" id="header-sdk-version" value="${headerProfile.sdkVersion || ''}" placeholder="1.0.18" style="width: 116px; padding: 6px 10px;" />
+            <input type="text" class="ui-input" id="header-node-version" value="${headerProfile.nodeVersion || ''}" placeholder="20.16.0" style="width: 116px; padding: 6px 10px;" />
+          </div>
+        </div>
       </div>
 
       <div class="settings-section">
@@ -343,15 +470,25 @@ export function attachSettingsEvents(container: Element) {
       const hostInput = container.querySelector('#proxy-host') as HTMLInputElement
       const logRequestsToggle = container.querySelector('#log-requests') as HTMLInputElement
       const maxRetriesInput = container.querySelector('#max-retries') as HTMLInputElement
+      const autoContinueRoundsInput = container.querySelector('#auto-continue-rounds') as HTMLInputElement
+      const contextOverflowPolicySelect = container.querySelector('#context-overflow-policy') as HTMLSelectElement
       const enableOpenAIToggle = container.querySelector('#enable-openai') as HTMLInputElement
       const enableClaudeToggle = container.querySelector('#enable-claude') as HTMLInputElement
+      const enableGeminiToggle = container.querySelector('#enable-gemini') as HTMLInputElement
+      const retryInvalidJsonToggle = container.querySelector('#retry-invalid-json') as HTMLInputElement
+      const stripThinkingToggle = container.querySelector('#strip-thinking') as HTMLInputElement
       
       if (portInput) config.port = parseInt(portInput.value)
       if (hostInput) config.host = hostInput.value
       if (logRequestsToggle) config.logRequests = logRequestsToggle.checked
       if (maxRetriesInput) config.maxRetries = parseInt(maxRetriesInput.value)
+      if (autoContinueRoundsInput) config.autoContinueRounds = parseInt(autoContinueRoundsInput.value) || 0
+      if (contextOverflowPolicySelect) config.contextOverflowPolicy = contextOverflowPolicySelect.value as ProxyConfig['contextOverflowPolicy']
       if (enableOpenAIToggle) config.enableOpenAI = enableOpenAIToggle.checked
       if (enableClaudeToggle) config.enableClaude = enableClaudeToggle.checked
+      if (enableGeminiToggle) config.enableGemini = enableGeminiToggle.checked
+      if (retryInvalidJsonToggle) config.retryInvalidJson = retryInvalidJsonToggle.checked
+      if (stripThinkingToggle) config.stripThinking = stripThinkingToggle.checked
       
       await proxyService.updateConfig(config)
     } catch (error) {
@@ -380,6 +517,16 @@ export function attachSettingsEvents(container: Element) {
     maxRetriesInput.addEventListener('change', saveProxyConfig)
   }
   
+  const autoContinueRoundsInput = container.querySelector('#auto-continue-rounds') as HTMLInputElement
+  if (autoContinueRoundsInput) {
+    autoContinueRoundsInput.addEventListener('change', saveProxyConfig)
+  }
+  
+  const contextOverflowPolicySelect = container.querySelector('#context-overflow-policy') as HTMLSelectElement
+  if (contextOverflowPolicySelect) {
+    contextOverflowPolicySelect.addEventListener('change', saveProxyConfig)
+  }
+  
   const enableOpenAIToggle = container.querySelector('#enable-openai') as HTMLInputElement
   if (enableOpenAIToggle) {
     enableOpenAIToggle.addEventListener('change', saveProxyConfig)
@@ -390,6 +537,63 @@ export function attachSettingsEvents(container: Element) {
     enableClaudeToggle.addEventListener('change', saveProxyConfig)
   }
   
+  const enableGeminiToggle = container.querySelector('#enable-gemini') as HTMLInputElement
+  if (enableGeminiToggle) {
+    enableGeminiToggle.addEventListener('change', saveProxyConfig)
+  }
+  
+  const retryInvalidJsonToggle = container.querySelector('#retry-invalid-json') as HTMLInputElement
+  if (retryInvalidJsonToggle) {
+    retryInvalidJsonToggle.addEventListener('change', saveProxyConfig)
+  }
+  
+  const stripThinkingToggle = container.querySelector('#strip-thinking') as HTMLInputElement
+  if (stripThinkingToggle) {
+    stripThinkingToggle.addEventListener('change', saveProxyConfig)
+  }
+  
+  // 网络设置保存函数
+  const saveNetworkSettings = async () => {
+    const value = (id: string) => (container.querySelector(id) as HTMLInputElement)?.value.trim() || ''
+    const url = value('#outbound-proxy-url')
+    const username = value('#outbound-proxy-username')
+    const password = (container.querySelector('#outbound-proxy-password') as HTMLInputElement)?.value || ''
+    
+    try {
+      // 留空的请求头字段使用默认值，未在界面展示的字段保持不变
+      const current = await (window as any).__TAURI__.core.invoke('get_network_settings')
+      const headerProfile: HeaderProfile = {
+        ...current.headerProfile,
+        ideVersion: value('#header-ide-version') || '0.6.18',
+        os: value('#header-os') || 'windows',
+        agentMode: value('#header-agent-mode') || 'spec',
+        sdkVersion: value('#header-sdk-version') || '1.0.18',
+        nodeVersion: value('#header-node-version') || '20.16.0'
+      }
+      
+      await (window as any).__TAURI__.core.invoke('save_network_settings', {
+        settings: {
+          outboundProxy: url
+            ? { url, username: username || null, password: password || null }
+            : null,
+          headerProfile
+        }
+      })
+      window.UI?.toast.success('网络设置已保存')
+    } catch (error) {
+      console.error('[设置] 保存网络设置失败:', error)
+      window.UI?.toast.error(`保存网络设置失败: ${error}`)
+    }
+  }
+  
+  const networkInputs = [
+    '#outbound-proxy-url', '#outbound-proxy-username', '#outbound-proxy-password',
+    '#header-ide-version', '#header-os', '#header-agent-mode', '#header-sdk-version', '#header-node-version'
+  ]
+  for (const id of networkInputs) {
+    container.querySelector(id)?.addEventListener('change', saveNetworkSettings)
+  }
+  
   // 初始化主题开关状态
   const themeSwitch = container.querySelector('#theme-switch') as HTMLInputElement
   if (themeSwitch) {
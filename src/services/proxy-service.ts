@@ -1,5 +1,5 @@
 // 反代服务前端服务
-import type { ProxyConfig, ProxyAccount, ProxyStats, SessionStats, RequestLog } from '../types/proxy'
+import type { ApiKey, ProxyConfig, ProxyAccount, ProxyStats, SessionStats, RequestLog } from '../types/proxy'
 
 class ProxyService {
   /**
@@ -97,22 +97,12 @@ class ProxyService {
     fromCache: boolean
   }> {
     try {
-      // 获取账号池中的所有账号
-      const accountsInfo = await this.getAccounts()
-      if (accountsInfo.accounts.length === 0) {
-        throw new Error('账号池为空，请先同步账号')
-      }
-
-      // 使用第一个账号获取模型
-      const firstAccount = accountsInfo.accounts[0]
-      const result = await (window as any).__TAURI__.core.invoke('get_account_models', {
-        accessToken: firstAccount.accessToken,
-        region: firstAccount.region || 'us-east-1'
-      })
+      // 合并账号池中所有可用账号的模型，后端按账号缓存
+      const result = await (window as any).__TAURI__.core.invoke('get_proxy_models')
       
       return {
         models: result.models || [],
-        fromCache: false
+        fromCache: result.fromCache === true
       }
     } catch (error) {
       console.error('[ProxyService] 获取模型失败:', error)
@@ -120,6 +110,18 @@ class ProxyService {
     }
   }
 
+  /**
+   * 生成 API Key，返回的明文只能展示这一次
+   */
+  async generateApiKey(name: string): Promise<{ apiKey: ApiKey; key: string }> {
+    try {
+      return await (window as any).__TAURI__.core.invoke('generate_proxy_api_key', { name })
+    } catch (error) {
+      console.error('[ProxyService] 生成 API Key 失败:', error)
+      throw error
+    }
+  }
+
   /**
    * 获取日志
    */
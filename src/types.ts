@@ -28,6 +28,23 @@ export interface AccountCredentials {
   startUrl?: string
 }
 
+// 出站代理，支持 http/https/socks5
+export interface OutboundProxy {
+  url: string
+  username?: string
+  password?: string
+}
+
+// 调用 Kiro 接口时模拟的客户端信息
+export interface HeaderProfile {
+  ideVersion: string
+  os: string
+  agentMode: string
+  sdkVersion: string
+  nodeVersion: string
+  optout: boolean
+}
+
 export interface BonusData {
   code: string
   name: string
@@ -86,6 +103,8 @@ export interface Account {
   tags: string[]
   status: AccountStatus
   lastError?: string
+  outboundProxy?: OutboundProxy
+  headerProfile?: Partial<HeaderProfile>
   isActive: boolean
   createdAt: number
   lastUsedAt: number
@@ -119,6 +138,9 @@ declare global {
           listen: (event: string, handler: () => void) => Promise<void>
         }
       }
+      event: {
+        listen: <T = any>(event: string, handler: (event: { payload: T }) => void) => Promise<() => void>
+      }
     }
     // 全局函数
     showAccountModels?: () => void
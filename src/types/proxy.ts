@@ -1,4 +1,5 @@
 // 反代服务类型定义
+import type { HeaderProfile, OutboundProxy } from '../types'
 
 export interface ProxyAccount {
   id: string
@@ -11,6 +12,8 @@ export interface ProxyAccount {
   clientSecret?: string
   region?: string
   authMethod?: string
+  outboundProxy?: OutboundProxy
+  headerProfile?: Partial<HeaderProfile>
   isAvailable?: boolean
   lastUsed?: number
   requestCount?: number
@@ -20,7 +23,10 @@ export interface ProxyAccount {
 export interface ApiKey {
   id: string
   name: string
-  key: string
+  // Key 开头的几个字符，完整 Key 只在生成时显示一次
+  prefix: string
+  keyHash: string
+  salt: string
   enabled: boolean
   creditsLimit?: number
   usage: ApiKeyUsage
@@ -81,6 +87,10 @@ export interface ProxyConfig {
   modelMappings?: ModelMappingRule[]
   enableOpenAI?: boolean
   enableClaude?: boolean
+  enableGemini?: boolean
+  retryInvalidJson?: boolean
+  stripThinking?: boolean
+  contextOverflowPolicy?: 'truncate' | 'summarize' | 'reject'
 }
 
 export interface ProxyStats {
@@ -92,6 +102,19 @@ export interface ProxyStats {
   inputTokens: number
   outputTokens: number
   startTime: number
+  estimatedCredits?: number
+  byAccount?: Record<string, UsageRollup>
+  byApiKey?: Record<string, UsageRollup>
+  byModel?: Record<string, UsageRollup>
+}
+
+export interface UsageRollup {
+  requests: number
+  failedRequests: number
+  inputTokens: number
+  outputTokens: number
+  credits: number
+  estimatedCredits: number
 }
 
 export interface SessionStats {
@@ -110,5 +133,8 @@ export interface RequestLog {
   inputTokens?: number
   outputTokens?: number
   credits?: number
+  creditsEstimated?: boolean
   error?: string
+  accountId?: string
+  apiKeyId?: string
 }